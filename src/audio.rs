@@ -0,0 +1,58 @@
+use crate::gameboy::apu::MAX_BUFFERED_SECONDS;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+/// Plays audio through the default output device of the host. Sound stops when this is dropped.
+pub struct AudioOutput {
+    stream: cpal::Stream,
+    sample_rate: u32,
+}
+
+impl AudioOutput {
+    /// Opens the default output device in stereo, returning it along with the producer side of its sample ring
+    /// buffer. Interleaved stereo samples pushed to it are played in order, and silence is played whenever it runs
+    /// empty. The audio thread never waits on anything else. Playback doesn't start until [AudioOutput::play].
+    pub fn new() -> anyhow::Result<(Self, rtrb::Producer<f32>)> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| anyhow::anyhow!("No audio output device available"))?;
+
+        let sample_rate = device.default_output_config()?.sample_rate();
+        let config = cpal::StreamConfig {
+            channels: 2,
+            sample_rate,
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        let capacity = (sample_rate.0 as f32 * MAX_BUFFERED_SECONDS) as usize * 2;
+        let (producer, mut consumer) = rtrb::RingBuffer::new(capacity);
+
+        let stream = device.build_output_stream(
+            &config,
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                for sample in data {
+                    *sample = consumer.pop().unwrap_or(0.0);
+                }
+            },
+            |e| eprintln!("audio stream error: {}", e),
+            None,
+        )?;
+        stream.pause()?;
+
+        let output = Self {
+            stream,
+            sample_rate: sample_rate.0,
+        };
+
+        Ok((output, producer))
+    }
+
+    /// The rate of the samples the device expects, in Hz.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn play(&self) -> anyhow::Result<()> {
+        self.stream.play()?;
+        Ok(())
+    }
+}
@@ -1,13 +1,882 @@
+use super::memory::registers as memreg;
 use super::memory::Memory;
+use super::CLOCK_FREQUENCY;
+use std::collections::VecDeque;
 
-pub struct Apu;
+/// How much audio is kept buffered at most, in seconds. Older samples are dropped when the emulation runs ahead of
+/// the audio device.
+pub const MAX_BUFFERED_SECONDS: f32 = 0.1;
+
+/// Length counter of a sound channel. While enabled, it's clocked by the frame sequencer and turns the channel
+/// off once it reaches zero.
+struct LengthCounter {
+    /// The full length, loaded when the channel is triggered with a counter of zero.
+    max: u16,
+    value: u16,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    fn new(max: u16) -> Self {
+        Self {
+            max,
+            value: 0,
+            enabled: false,
+        }
+    }
+
+    /// Loads the counter from the length field of NRx1.
+    fn load(&mut self, data: u8) {
+        self.value = self.max - (data as u16 & (self.max - 1));
+    }
+
+    /// Clocks the counter, returning whether it just reached zero.
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.value > 0 {
+            self.value -= 1;
+            self.value == 0
+        } else {
+            false
+        }
+    }
+}
+
+/// One-pole high-pass filter like the capacitor on the gameboy's audio output, which removes the DC offset of the
+/// channel output.
+#[derive(Debug, Clone)]
+pub struct HighPassFilter {
+    /// How much of the charge the capacitor keeps between two samples.
+    charge_factor: f32,
+    capacitor: f32,
+}
+
+impl HighPassFilter {
+    /// Creates a filter for samples at the given rate. The capacitor keeps 0.999958 of its charge every clock cycle,
+    /// so the factor per sample depends on the rate.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            charge_factor: 0.999958f32.powf(CLOCK_FREQUENCY as f32 / sample_rate as f32),
+            capacitor: 0.0,
+        }
+    }
+
+    /// Filters the next sample.
+    pub fn apply(&mut self, sample: f32) -> f32 {
+        let output = sample - self.capacitor;
+        self.capacitor = sample - output * self.charge_factor;
+        output
+    }
+}
+
+/// Volume envelope of the square and noise channels, configured through NRx2.
+struct Envelope {
+    volume: u8,
+    increase: bool,
+    period: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Self {
+            volume: 0,
+            increase: false,
+            period: 0,
+            timer: 0,
+        }
+    }
+
+    /// Reloads the envelope from NRx2, as done on trigger.
+    fn trigger(&mut self, data: u8) {
+        self.volume = data >> 4;
+        self.increase = data & 0b0000_1000 != 0;
+        self.period = data & 0b0000_0111;
+        self.timer = self.period;
+    }
+
+    /// Clocks the envelope, changing the volume by one every `period` clocks until it reaches 0 or 15. A period
+    /// of 0 stops the envelope.
+    fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+/// Duty patterns of the square channels, selected by bits 6-7 of NRx1.
+const DUTY_PATTERNS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1], // 12.5%
+    [1, 0, 0, 0, 0, 0, 0, 1], // 25%
+    [1, 0, 0, 0, 0, 1, 1, 1], // 50%
+    [0, 1, 1, 1, 1, 1, 1, 0], // 75%
+];
+
+/// Waveform generator of the square channels.
+struct Square {
+    envelope: Envelope,
+    /// Clock cycles left until the next step of the duty pattern.
+    timer: u32,
+    /// The duty pattern in use, from bits 6-7 of NRx1.
+    duty: u8,
+    duty_position: u8,
+}
+
+impl Square {
+    fn new() -> Self {
+        Self {
+            envelope: Envelope::new(),
+            timer: 0,
+            duty: 0,
+            duty_position: 0,
+        }
+    }
+
+    /// Clock cycles between steps of the duty pattern for the given frequency.
+    fn period(frequency: u16) -> u32 {
+        (2048 - frequency as u32) * 4
+    }
+
+    fn trigger(&mut self, channel: &Channel, memory: &Memory) {
+        self.timer = Self::period(channel.frequency(memory));
+        self.envelope
+            .trigger(memory.read_apu_register(channel.dac_register));
+    }
+
+    fn advance(&mut self, channel: &Channel, memory: &Memory, mut cycles: u32) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = Self::period(channel.frequency(memory));
+            self.duty_position = (self.duty_position + 1) % 8;
+        }
+
+        self.timer -= cycles;
+    }
+
+    fn sample(&self, channel: &Channel) -> u8 {
+        if !channel.enabled {
+            return 0;
+        }
+
+        DUTY_PATTERNS[self.duty as usize][self.duty_position as usize] * self.envelope.volume
+    }
+}
+
+/// Waveform generator of the wave channel, which plays the 32 4-bit samples in wave RAM.
+struct Wave {
+    /// Clock cycles left until the next sample is read.
+    timer: u32,
+    /// Index of the sample being played, in the range 0..32.
+    position: u8,
+    /// The sample last read from wave RAM.
+    sample: u8,
+    /// Whether a sample was read from wave RAM on the last advance.
+    fetched: bool,
+    /// The volume code from bits 5-6 of NR32.
+    volume: u8,
+}
+
+impl Wave {
+    fn new() -> Self {
+        Self {
+            timer: 0,
+            position: 0,
+            sample: 0,
+            fetched: false,
+            volume: 0,
+        }
+    }
+
+    /// Clock cycles between samples for the given frequency.
+    fn period(frequency: u16) -> u32 {
+        (2048 - frequency as u32) * 2
+    }
+
+    /// Restarts playback from the first sample. The sample buffer isn't refilled, so the last sample read keeps
+    /// playing until the timer runs out.
+    fn trigger(&mut self, channel: &Channel, memory: &Memory) {
+        self.timer = Self::period(channel.frequency(memory));
+        self.position = 0;
+    }
+
+    fn advance(&mut self, channel: &Channel, memory: &Memory, mut cycles: u32) {
+        self.fetched = false;
+        if !channel.enabled {
+            return;
+        }
+
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = Self::period(channel.frequency(memory));
+            self.position = (self.position + 1) % 32;
+
+            // samples are stored high nibble first
+            let byte =
+                memory.read_apu_register(memreg::addresses::WAVE_RAM + self.position as u16 / 2);
+            self.sample = if self.position & 1 == 0 {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+            self.fetched = true;
+        }
+
+        self.timer -= cycles;
+    }
+
+    fn sample(&self, channel: &Channel) -> u8 {
+        if !channel.enabled {
+            return 0;
+        }
+
+        // volume codes 0 to 3 are mute, 100%, 50% and 25%
+        let shift = [4, 0, 1, 2][self.volume as usize];
+        self.sample >> shift
+    }
+}
+
+/// Waveform generator of the noise channel, a linear feedback shift register configured through NR43.
+struct Noise {
+    envelope: Envelope,
+    /// Clock cycles left until the next shift of the LFSR.
+    timer: u32,
+    lfsr: u16,
+}
+
+impl Noise {
+    fn new() -> Self {
+        Self {
+            envelope: Envelope::new(),
+            timer: 0,
+            lfsr: 0x7FFF,
+        }
+    }
+
+    /// Clock cycles between shifts of the LFSR: the divisor (8 for code 0, 16 times the code otherwise) shifted
+    /// left by the clock shift.
+    fn period(nr43: u8) -> u32 {
+        let divisor = match nr43 & 0b0111 {
+            0 => 8,
+            code => code as u32 * 16,
+        };
+
+        divisor << (nr43 >> 4)
+    }
+
+    fn trigger(&mut self, channel: &Channel, memory: &Memory) {
+        self.timer = Self::period(memory.read_apu_register(memreg::addresses::NR43));
+        self.lfsr = 0x7FFF;
+        self.envelope
+            .trigger(memory.read_apu_register(channel.dac_register));
+    }
+
+    fn advance(&mut self, memory: &Memory, mut cycles: u32) {
+        let nr43 = memory.read_apu_register(memreg::addresses::NR43);
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = Self::period(nr43);
+
+            // clock shifts 14 and 15 stop the LFSR
+            if nr43 >> 4 >= 14 {
+                continue;
+            }
+
+            let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+            // in 7-bit mode, the feedback also goes into bit 6
+            if nr43 & 0b0000_1000 != 0 {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (feedback << 6);
+            }
+        }
+
+        self.timer -= cycles;
+    }
+
+    fn sample(&self, channel: &Channel) -> u8 {
+        if !channel.enabled {
+            return 0;
+        }
+
+        (!self.lfsr & 1) as u8 * self.envelope.volume
+    }
+}
+
+/// Frequency sweep unit of channel 1, configured through NR10.
+struct Sweep {
+    enabled: bool,
+    /// Copy of the frequency the sweep works on, so writes to NR13/NR14 don't affect it until the next trigger.
+    shadow: u16,
+    timer: u8,
+}
+
+impl Sweep {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            shadow: 0,
+            timer: 0,
+        }
+    }
+
+    /// The sweep timer reloads with 8 when the period is 0.
+    fn reload_timer(&mut self, nr10: u8) {
+        let period = (nr10 >> 4) & 0b0111;
+        self.timer = if period == 0 { 8 } else { period };
+    }
+
+    /// Calculates the next frequency, or `None` if it overflows past 2047, which turns the channel off.
+    fn next_frequency(&self, nr10: u8) -> Option<u16> {
+        let delta = self.shadow >> (nr10 & 0b0111);
+        let frequency = if nr10 & 0b0000_1000 != 0 {
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        };
+
+        (frequency <= 2047).then_some(frequency)
+    }
+
+    /// Reloads the sweep on trigger, returning whether the channel stays on after the initial overflow check.
+    fn trigger(&mut self, channel: &Channel, memory: &Memory) -> bool {
+        let nr10 = memory.read_apu_register(memreg::addresses::NR10);
+        self.shadow = channel.frequency(memory);
+        self.reload_timer(nr10);
+        self.enabled = nr10 & 0b0111_0111 != 0;
+
+        nr10 & 0b0111 == 0 || self.next_frequency(nr10).is_some()
+    }
+
+    /// Clocks the sweep, updating the channel frequency when the timer runs out. Returns whether the channel
+    /// stays on.
+    fn clock(&mut self, channel: &Channel, memory: &mut Memory) -> bool {
+        let nr10 = memory.read_apu_register(memreg::addresses::NR10);
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return true;
+        }
+
+        self.reload_timer(nr10);
+        if !self.enabled || nr10 & 0b0111_0000 == 0 {
+            return true;
+        }
+
+        let Some(frequency) = self.next_frequency(nr10) else {
+            return false;
+        };
+
+        if nr10 & 0b0111 != 0 {
+            self.shadow = frequency;
+            channel.set_frequency(memory, frequency);
+        }
+
+        // the new frequency is checked for overflow once more, but not written back
+        self.next_frequency(nr10).is_some()
+    }
+}
+
+/// State shared by all sound channels.
+struct Channel {
+    enabled: bool,
+    length: LengthCounter,
+    /// NRx1, which holds the length field.
+    length_register: u16,
+    /// The register holding the DAC power bits: NRx2 for most channels, NR30 for the wave channel.
+    dac_register: u16,
+    /// Bits of the DAC register that must not all be zero for the DAC to be on.
+    dac_mask: u8,
+    /// NRx4, which holds the trigger and length enable bits.
+    control_register: u16,
+}
+
+impl Channel {
+    fn new(length_register: u16, max_length: u16, dac_register: u16, dac_mask: u8) -> Self {
+        Self {
+            enabled: false,
+            length: LengthCounter::new(max_length),
+            length_register,
+            dac_register,
+            dac_mask,
+            control_register: length_register + 3,
+        }
+    }
+
+    /// The 11-bit frequency in NRx3 and the lower bits of NRx4.
+    fn frequency(&self, memory: &Memory) -> u16 {
+        let low = memory.read_apu_register(self.length_register + 2);
+        let high = memory.read_apu_register(self.control_register) & 0b0111;
+        u16::from_le_bytes([low, high])
+    }
+
+    fn set_frequency(&self, memory: &mut Memory, frequency: u16) {
+        let [low, high] = frequency.to_le_bytes();
+        let control = memory.read_apu_register(self.control_register);
+        memory.write(self.length_register + 2, low);
+        memory.write(self.control_register, (control & !0b0111) | high);
+    }
+
+    fn dac_enabled(&self, memory: &Memory) -> bool {
+        memory.read_apu_register(self.dac_register) & self.dac_mask != 0
+    }
+
+    /// Handles a write to NRx4, returning whether the channel was triggered. `length_step_next` tells whether the
+    /// next frame sequencer step clocks the length counters, since enabling length or triggering outside of such a
+    /// step clocks the counter once more.
+    fn write_control(&mut self, memory: &Memory, length_step_next: bool) -> bool {
+        let data = memory.read_apu_register(self.control_register);
+        let was_enabled = self.length.enabled;
+        self.length.enabled = data & 0b0100_0000 != 0;
+
+        if !length_step_next && !was_enabled && self.length.clock() {
+            self.enabled = false;
+        }
+
+        let trigger = data & 0b1000_0000 != 0;
+        if trigger {
+            self.enabled = self.dac_enabled(memory);
+            if self.length.value == 0 {
+                self.length.value = self.length.max;
+                if self.length.enabled && !length_step_next {
+                    self.length.value -= 1;
+                }
+            }
+        }
+
+        trigger
+    }
+}
+
+/// One of the four sound channels, numbered 1 to 4 like their registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundChannel {
+    /// Square with sweep.
+    Square1 = 1,
+    Square2 = 2,
+    Wave = 3,
+    Noise = 4,
+}
+
+impl SoundChannel {
+    pub const ALL: [Self; 4] = [Self::Square1, Self::Square2, Self::Wave, Self::Noise];
+
+    #[inline]
+    fn index(self) -> usize {
+        self as usize - 1
+    }
+}
+
+/// Audio processing unit of the gameboy.
+pub struct Apu {
+    powered: bool,
+    channels: [Channel; 4],
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    sweep: Sweep,
+    /// Debugging override to mute channels in the mix, regardless of their hardware state.
+    channels_audible: [bool; 4],
+    /// Master volume of each side, as written to NR50.
+    nr50: u8,
+    /// Which channels go to each side, as written to NR51.
+    nr51: u8,
+    /// The next step of the frame sequencer, in the range 0..8. See [Apu::clock_sequencer] for what each step
+    /// clocks.
+    sequencer_step: u8,
+    /// The DIV bit whose falling edge clocks the frame sequencer, as seen on the last advance.
+    div_bit: bool,
+    /// Rate of the samples produced, in Hz. No samples are produced without one.
+    sample_rate: Option<u32>,
+    /// Progress towards the next output sample, in clock cycles times the sample rate.
+    sample_clock: u64,
+    /// Sum and count of the mixed output since the last output sample, which gets their average.
+    sample_sum: (f32, f32),
+    sample_count: u32,
+    /// High-pass filters of the left and right sides, applied to the resampled output.
+    high_pass: (HighPassFilter, HighPassFilter),
+    /// Interleaved stereo samples waiting to be drained.
+    samples: VecDeque<f32>,
+    /// Where samples go instead of being buffered, if set. Usually the ring buffer of an audio device.
+    #[cfg(feature = "audio")]
+    sample_output: Option<rtrb::Producer<f32>>,
+}
 
 impl Apu {
     pub fn new() -> Self {
-        Self
+        use memreg::addresses::*;
+        Self {
+            powered: true,
+            channels: [
+                Channel::new(NR11, 64, NR12, 0b1111_1000),
+                Channel::new(NR21, 64, NR22, 0b1111_1000),
+                Channel::new(NR31, 256, NR30, 0b1000_0000),
+                Channel::new(NR41, 64, NR42, 0b1111_1000),
+            ],
+            square1: Square::new(),
+            square2: Square::new(),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            sweep: Sweep::new(),
+            channels_audible: [true; 4],
+            nr50: 0,
+            nr51: 0,
+            sequencer_step: 0,
+            div_bit: false,
+            sample_rate: None,
+            sample_clock: 0,
+            sample_sum: (0.0, 0.0),
+            sample_count: 0,
+            high_pass: (HighPassFilter::new(48_000), HighPassFilter::new(48_000)),
+            samples: VecDeque::new(),
+            #[cfg(feature = "audio")]
+            sample_output: None,
+        }
+    }
+
+    fn handle_writes(&mut self, memory: &mut Memory) {
+        let writes = memory.take_apu_writes();
+        let written = |address: u16| writes & (1 << (address - memreg::addresses::NR10)) != 0;
+
+        if written(memreg::addresses::NR52) {
+            let powered = memory.read_apu_register(memreg::addresses::NR52) & 0b1000_0000 != 0;
+            if !powered && self.powered {
+                self.power_off(memory);
+            } else if powered && !self.powered {
+                self.sequencer_step = 0;
+            }
+
+            self.powered = powered;
+        }
+
+        let length_step_next = self.sequencer_step & 1 == 0;
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            // length counters can still be loaded while the apu is off
+            if written(channel.length_register) {
+                channel
+                    .length
+                    .load(memory.read_apu_register(channel.length_register));
+            }
+
+            if !self.powered {
+                continue;
+            }
+
+            if written(channel.dac_register) && !channel.dac_enabled(memory) {
+                channel.enabled = false;
+            }
+
+            let triggered = written(channel.control_register)
+                && channel.write_control(memory, length_step_next);
+            if !triggered {
+                continue;
+            }
+
+            match index {
+                0 => {
+                    self.square1.trigger(channel, memory);
+                    if !self.sweep.trigger(channel, memory) {
+                        channel.enabled = false;
+                    }
+                }
+                1 => self.square2.trigger(channel, memory),
+                2 => self.wave.trigger(channel, memory),
+                _ => self.noise.trigger(channel, memory),
+            }
+        }
+    }
+
+    /// Clocks the frame sequencer, which is clocked at 512 Hz and ticks the other units on these steps:
+    ///
+    /// | Step | Length counters (256 Hz) | Sweep (128 Hz) | Envelopes (64 Hz) |
+    /// |------|--------------------------|----------------|-------------------|
+    /// | 0    | x                        |                |                   |
+    /// | 1    |                          |                |                   |
+    /// | 2    | x                        | x              |                   |
+    /// | 3    |                          |                |                   |
+    /// | 4    | x                        |                |                   |
+    /// | 5    |                          |                |                   |
+    /// | 6    | x                        | x              |                   |
+    /// | 7    |                          |                | x                 |
+    ///
+    /// A length counter reaching zero turns its channel off, which shows up in NR52 on the same advance.
+    fn clock_sequencer(&mut self, memory: &mut Memory) {
+        if self.sequencer_step & 1 == 0 {
+            for channel in &mut self.channels {
+                if channel.length.clock() {
+                    channel.enabled = false;
+                }
+            }
+        }
+
+        if matches!(self.sequencer_step, 2 | 6)
+            && self.channels[0].enabled
+            && !self.sweep.clock(&self.channels[0], memory)
+        {
+            self.channels[0].enabled = false;
+        }
+
+        if self.sequencer_step == 7 {
+            self.square1.envelope.clock();
+            self.square2.envelope.clock();
+            self.noise.envelope.clock();
+        }
+
+        self.sequencer_step = (self.sequencer_step + 1) % 8;
+    }
+
+    /// Advances the APU by the given amount of clock cycles. The frame sequencer is clocked at 512 Hz by the
+    /// falling edge of DIV bit 4, so resetting DIV can clock it early like on hardware.
+    pub fn advance(&mut self, memory: &mut Memory, cycles: u32) {
+        self.handle_writes(memory);
+        self.latch_registers(memory);
+
+        let div_bit = memory.read(memreg::addresses::DIV) & 0b0001_0000 != 0;
+        if self.powered && self.div_bit && !div_bit {
+            self.clock_sequencer(memory);
+        }
+        self.div_bit = div_bit;
+
+        if self.powered {
+            self.square1.advance(&self.channels[0], memory, cycles);
+            self.square2.advance(&self.channels[1], memory, cycles);
+            self.wave.advance(&self.channels[2], memory, cycles);
+            self.noise.advance(memory, cycles);
+        }
+
+        // while the wave channel plays, the cpu can only reach the byte it's reading
+        let wave_position = self.channels[2].enabled.then_some(self.wave.position / 2);
+        memory.set_wave_ram_playback(wave_position, self.wave.fetched);
+
+        let status = self
+            .channels
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (index, channel)| {
+                acc | ((channel.enabled as u8) << index)
+            });
+        let nr52 = memory.read_apu_register(memreg::addresses::NR52);
+        memory.write(memreg::addresses::NR52, (nr52 & 0xF0) | status);
+
+        self.produce_samples(cycles);
+    }
+
+    /// Copies the register fields the samples depend on, so they can be read without the memory.
+    fn latch_registers(&mut self, memory: &Memory) {
+        use memreg::addresses::*;
+        self.square1.duty = memory.read_apu_register(NR11) >> 6;
+        self.square2.duty = memory.read_apu_register(NR21) >> 6;
+        self.wave.volume = (memory.read_apu_register(NR32) >> 5) & 0b11;
+        self.nr50 = memory.read_apu_register(NR50);
+        self.nr51 = memory.read_apu_register(NR51);
+    }
+
+    /// Turns the APU off, as done by clearing NR52 bit 7: every channel stops and every register but the length
+    /// counters and wave RAM is cleared.
+    fn power_off(&mut self, memory: &mut Memory) {
+        for address in memreg::addresses::NR10..=memreg::addresses::NR51 {
+            memory.write(address, 0x00);
+        }
+
+        self.channels.iter_mut().for_each(|c| c.enabled = false);
+        self.square1 = Square::new();
+        self.square2 = Square::new();
+        self.wave = Wave::new();
+        self.noise = Noise::new();
+        self.sweep = Sweep::new();
+    }
+
+    /// Mixes the channels into a stereo sample, with each side in the range -1.0..=1.0. NR51 routes each channel
+    /// to the left and right sides, which NR50 then scales by their master volume. The output is silent while the
+    /// APU is off.
+    pub fn mix(&self) -> (f32, f32) {
+        if !self.powered {
+            return (0.0, 0.0);
+        }
+
+        let samples = [
+            self.channel1_sample(),
+            self.channel2_sample(),
+            self.channel3_sample(),
+            self.channel4_sample(),
+        ];
+
+        let (mut left, mut right) = (0.0, 0.0);
+        for (index, (sample, channel)) in samples.into_iter().zip(&self.channels).enumerate() {
+            // the dac of a playing channel maps 0..=15 to -1.0..=1.0, the others are silent
+            if !channel.enabled || !self.channels_audible[index] {
+                continue;
+            }
+
+            let analog = sample as f32 / 7.5 - 1.0;
+            if self.nr51 & (0b0001_0000 << index) != 0 {
+                left += analog;
+            }
+
+            if self.nr51 & (0b0000_0001 << index) != 0 {
+                right += analog;
+            }
+        }
+
+        // master volumes go from 1/8 to 8/8
+        let left_volume = ((self.nr50 >> 4) & 0b0111) as f32 + 1.0;
+        let right_volume = (self.nr50 & 0b0111) as f32 + 1.0;
+        (
+            left / 4.0 * left_volume / 8.0,
+            right / 4.0 * right_volume / 8.0,
+        )
+    }
+
+    /// Resamples the mixed output down to the sample rate by averaging it over each output sample, then high-pass
+    /// filters it.
+    fn produce_samples(&mut self, cycles: u32) {
+        let Some(sample_rate) = self.sample_rate else {
+            return;
+        };
+
+        let (left, right) = self.mix();
+        self.sample_sum.0 += left;
+        self.sample_sum.1 += right;
+        self.sample_count += 1;
+
+        self.sample_clock += cycles as u64 * sample_rate as u64;
+        if self.sample_clock < CLOCK_FREQUENCY as u64 {
+            return;
+        }
+
+        self.sample_clock -= CLOCK_FREQUENCY as u64;
+        let count = self.sample_count as f32;
+        let left = self.high_pass.0.apply(self.sample_sum.0 / count);
+        let right = self.high_pass.1.apply(self.sample_sum.1 / count);
+        self.sample_sum = (0.0, 0.0);
+        self.sample_count = 0;
+
+        #[cfg(feature = "audio")]
+        if let Some(output) = &mut self.sample_output {
+            // when the device falls behind, newer samples are dropped. both sides go in at once so they stay paired
+            if output.slots() >= 2 {
+                let _ = output.push(left);
+                let _ = output.push(right);
+            }
+
+            return;
+        }
+
+        self.samples.push_back(left);
+        self.samples.push_back(right);
+
+        let max_len = (sample_rate as f32 * MAX_BUFFERED_SECONDS) as usize * 2;
+        while self.samples.len() > max_len {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Whether a channel is heard in the mix. This is a debugging override on top of the emulation: a muted channel
+    /// keeps running and shows up as on in NR52.
+    pub fn channel_enabled(&self, channel: SoundChannel) -> bool {
+        self.channels_audible[channel.index()]
+    }
+
+    /// Mutes or unmutes a channel in the mix. See [Apu::channel_enabled].
+    pub fn set_channel_enabled(&mut self, channel: SoundChannel, on: bool) {
+        self.channels_audible[channel.index()] = on;
+    }
+
+    /// The rate of the samples returned by [Apu::drain_samples], if any.
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.sample_rate
+    }
+
+    /// Sets the rate of the samples returned by [Apu::drain_samples]. With `None`, no samples are produced, which
+    /// is the default.
+    pub fn set_sample_rate(&mut self, sample_rate: Option<u32>) {
+        self.sample_rate = sample_rate;
+        self.sample_clock = 0;
+        self.sample_sum = (0.0, 0.0);
+        self.sample_count = 0;
+        if let Some(sample_rate) = sample_rate {
+            self.high_pass = (
+                HighPassFilter::new(sample_rate),
+                HighPassFilter::new(sample_rate),
+            );
+        }
+        self.samples.clear();
+    }
+
+    /// Sends samples to `output` as they're produced, instead of buffering them for [Apu::drain_samples].
+    #[cfg(feature = "audio")]
+    pub fn set_sample_output(&mut self, output: Option<rtrb::Producer<f32>>) {
+        self.sample_output = output;
+        self.samples.clear();
+    }
+
+    /// Removes the output set with [Apu::set_sample_output], if any, so samples are buffered again.
+    #[cfg(feature = "audio")]
+    pub fn take_sample_output(&mut self) -> Option<rtrb::Producer<f32>> {
+        self.sample_output.take()
+    }
+
+    /// Fills `out` with interleaved stereo samples at the sample rate, oldest first. If there aren't enough
+    /// samples buffered, the rest is filled with silence. Nothing is buffered while a sample output is set.
+    pub fn drain_samples(&mut self, out: &mut [f32]) {
+        for sample in out {
+            *sample = self.samples.pop_front().unwrap_or(0.0);
+        }
+    }
+
+    /// The current output of channel 1 (square with sweep), in the range 0..=15.
+    pub fn channel1_sample(&self) -> u8 {
+        self.square1.sample(&self.channels[0])
+    }
+
+    /// The current output of channel 2 (square), in the range 0..=15.
+    pub fn channel2_sample(&self) -> u8 {
+        self.square2.sample(&self.channels[1])
+    }
+
+    /// The current output of channel 3 (wave), in the range 0..=15.
+    pub fn channel3_sample(&self) -> u8 {
+        self.wave.sample(&self.channels[2])
+    }
+
+    /// The current output of channel 4 (noise), in the range 0..=15.
+    pub fn channel4_sample(&self) -> u8 {
+        self.noise.sample(&self.channels[3])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn high_pass_filter_removes_a_constant_input() {
+        let mut filter = HighPassFilter::new(48_000);
+        let mut last = filter.apply(1.0);
+        assert_eq!(last, 1.0);
+
+        // a second's worth of samples
+        for _ in 0..48_000 {
+            let output = filter.apply(1.0);
+            assert!(output <= last);
+            last = output;
+        }
+        assert!(last.abs() < 0.01, "{}", last);
     }
 
-    pub fn cycle(&mut self, _memory: &mut Memory) {
-        // do nothing
+    #[test]
+    fn muted_channels_stay_muted() {
+        let mut apu = Apu::new();
+        apu.set_channel_enabled(SoundChannel::Wave, false);
+        for channel in SoundChannel::ALL {
+            assert_eq!(apu.channel_enabled(channel), channel != SoundChannel::Wave);
+        }
     }
 }
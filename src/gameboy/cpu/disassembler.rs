@@ -0,0 +1,328 @@
+use super::{
+    operation::{Operation, PrefixedOperation},
+    CpuFlag,
+};
+use crate::gameboy::memory::Memory;
+
+/// Opcodes that don't exist. The CPU locks up when executing them.
+const ILLEGAL_OPCODES: [u8; 11] = [
+    0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+];
+
+/// Disassembles the instruction at `pc`, returning its mnemonic with the immediate operands resolved and the length of
+/// the instruction in bytes.
+pub fn disassemble(memory: &Memory, pc: u16) -> (String, u8) {
+    disassemble_with(|address| memory.read(address), pc)
+}
+
+/// Like [disassemble], but reads the instruction bytes through `read`. Useful to disassemble a rom that isn't
+/// loaded.
+pub fn disassemble_with<R>(read: R, pc: u16) -> (String, u8)
+where
+    R: Fn(u16) -> u8,
+{
+    let opcode = read(pc);
+    if ILLEGAL_OPCODES.contains(&opcode) {
+        return (format!("ILLEGAL {:#04X}", opcode), 1);
+    }
+
+    let op = Operation::from(opcode);
+    let d8 = read(pc.wrapping_add(1));
+    let d16 = u16::from_le_bytes([d8, read(pc.wrapping_add(2))]);
+    let s8 = d8 as i8;
+    // relative jumps are relative to the end of the instruction
+    let target = pc.wrapping_add(2).wrapping_add(s8 as u16);
+
+    let mnemonic = match op {
+        Operation::Noop => "NOP".to_string(),
+        Operation::LoadImmediateIntoWordReg(wreg) => format!("LD {},{:#06X}", wreg, d16),
+        Operation::LoadRegIntoAddressInWordReg(reg, wreg) => format!("LD ({}),{}", wreg, reg),
+        Operation::IncrementWordReg(wreg) => format!("INC {}", wreg),
+        Operation::IncrementReg(reg) => format!("INC {}", reg),
+        Operation::DecrementReg(reg) => format!("DEC {}", reg),
+        Operation::LoadImmediateIntoReg(reg) => format!("LD {},{:#04X}", reg, d8),
+        Operation::RotateAccLeft => "RLCA".to_string(),
+        Operation::LoadSPIntoImmediateAddress => format!("LD ({:#06X}),SP", d16),
+        Operation::AddWordRegIntoWordReg(src, dst) => format!("ADD {},{}", dst, src),
+        Operation::LoadAtAddressInWordRegIntoReg(wreg, reg) => format!("LD {},({})", reg, wreg),
+        Operation::DecrementWordReg(wreg) => format!("DEC {}", wreg),
+        Operation::RotateAccRight => "RRCA".to_string(),
+        Operation::Stop => "STOP".to_string(),
+        Operation::RotateAccLeftThroughCarry => "RLA".to_string(),
+        Operation::RelativeJumpImmediateOffset => format!("JR {:#06X}", target),
+        Operation::RotateAccRightThroughCarry => "RRA".to_string(),
+        Operation::ConditionalRelativeJumpImmediateOffset(flag) => {
+            format!("JR {},{:#06X}", condition(flag, false), target)
+        }
+        Operation::NegativeConditionalRelativeJumpImmediateOffset(flag) => {
+            format!("JR {},{:#06X}", condition(flag, true), target)
+        }
+        Operation::LoadRegIntoAddressInWordRegAndIncrementWordReg(reg, wreg) => {
+            format!("LD ({}+),{}", wreg, reg)
+        }
+        Operation::DecimalAdjustAcc => "DAA".to_string(),
+        Operation::LoadAtAddressInWordRegIntoRegAndIncrementWordReg(wreg, reg) => {
+            format!("LD {},({}+)", reg, wreg)
+        }
+        Operation::ComplementAcc => "CPL".to_string(),
+        Operation::LoadRegIntoAddressInWordRegAndDecrementWordReg(reg, wreg) => {
+            format!("LD ({}-),{}", wreg, reg)
+        }
+        Operation::IncrementAtAddressInWordReg(wreg) => format!("INC ({})", wreg),
+        Operation::DecrementAtAddressInWordReg(wreg) => format!("DEC ({})", wreg),
+        Operation::LoadImmediateIntoAddressInWordReg(wreg) => {
+            format!("LD ({}),{:#04X}", wreg, d8)
+        }
+        Operation::SetCarry => "SCF".to_string(),
+        Operation::LoadAtAddressInWordRegIntoRegAndDecrementWordReg(wreg, reg) => {
+            format!("LD {},({}-)", reg, wreg)
+        }
+        Operation::ComplementCarry => "CCF".to_string(),
+        Operation::LoadRegIntoReg(src, dst) => format!("LD {},{}", dst, src),
+        Operation::Halt => "HALT".to_string(),
+        Operation::AddRegIntoReg(src, dst) => format!("ADD {},{}", dst, src),
+        Operation::AddAtAddressInWordRegIntoReg(wreg, reg) => format!("ADD {},({})", reg, wreg),
+        Operation::AddRegIntoRegWithCarry(src, dst) => format!("ADC {},{}", dst, src),
+        Operation::AddAtAddressInWordRegIntoRegWithCarry(wreg, reg) => {
+            format!("ADC {},({})", reg, wreg)
+        }
+        Operation::SubRegFromReg(src, dst) => format!("SUB {},{}", dst, src),
+        Operation::SubAtAddressInWordRegFromReg(wreg, reg) => format!("SUB {},({})", reg, wreg),
+        Operation::SubRegFromRegWithCarry(src, dst) => format!("SBC {},{}", dst, src),
+        Operation::SubAtAddressInWordRegFromRegWithCarry(wreg, reg) => {
+            format!("SBC {},({})", reg, wreg)
+        }
+        Operation::AndRegIntoReg(src, dst) => format!("AND {},{}", dst, src),
+        Operation::AndAtAddressInWordRegIntoReg(wreg, reg) => format!("AND {},({})", reg, wreg),
+        Operation::XorRegIntoReg(src, dst) => format!("XOR {},{}", dst, src),
+        Operation::XorAtAddressInWordRegIntoReg(wreg, reg) => format!("XOR {},({})", reg, wreg),
+        Operation::OrRegIntoReg(src, dst) => format!("OR {},{}", dst, src),
+        Operation::OrAtAddressInWordRegIntoReg(wreg, reg) => format!("OR {},({})", reg, wreg),
+        Operation::CompareRegAndReg(src, dst) => format!("CP {},{}", dst, src),
+        Operation::CompareAtAddressInWordRegAndReg(wreg, reg) => {
+            format!("CP {},({})", reg, wreg)
+        }
+        Operation::ConditionalReturn(flag) => format!("RET {}", condition(flag, false)),
+        Operation::NegativeConditionalReturn(flag) => format!("RET {}", condition(flag, true)),
+        Operation::PopStackIntoWordReg(wreg) => format!("POP {}", wreg),
+        Operation::ConditionalJumpImmediateAddress(flag) => {
+            format!("JP {},{:#06X}", condition(flag, false), d16)
+        }
+        Operation::NegativeConditionalJumpImmediateAddress(flag) => {
+            format!("JP {},{:#06X}", condition(flag, true), d16)
+        }
+        Operation::JumpImmediateAddress => format!("JP {:#06X}", d16),
+        Operation::ConditionalCallImmediateAddress(flag) => {
+            format!("CALL {},{:#06X}", condition(flag, false), d16)
+        }
+        Operation::NegativeConditionalCallImmediateAddress(flag) => {
+            format!("CALL {},{:#06X}", condition(flag, true), d16)
+        }
+        Operation::PushWordRegIntoStack(wreg) => format!("PUSH {}", wreg),
+        Operation::AddImmediateIntoReg(reg) => format!("ADD {},{:#04X}", reg, d8),
+        Operation::CallFixedAddress(address) => format!("RST {:#04X}", address),
+        Operation::Return => "RET".to_string(),
+        Operation::Prefixed => disassemble_prefixed(PrefixedOperation::from(d8)),
+        Operation::CallImmediateAddress => format!("CALL {:#06X}", d16),
+        Operation::AddImmediateIntoRegWithCarry(reg) => format!("ADC {},{:#04X}", reg, d8),
+        Operation::SubImmediateFromReg(reg) => format!("SUB {},{:#04X}", reg, d8),
+        Operation::ReturnAndEnableInterrupts => "RETI".to_string(),
+        Operation::SubImmediateFromRegWithCarry(reg) => format!("SBC {},{:#04X}", reg, d8),
+        Operation::LoadRegIntoImmediateIORegister(reg) => {
+            format!("LDH ({:#06X}),{}", 0xFF00 | d8 as u16, reg)
+        }
+        Operation::LoadRegIntoRegIORegister(src, offset) => {
+            format!("LD (0xFF00+{}),{}", offset, src)
+        }
+        Operation::AndImmediateIntoReg(reg) => format!("AND {},{:#04X}", reg, d8),
+        Operation::AddSignedImmediateIntoWordReg(wreg) => format!("ADD {},{}", wreg, s8),
+        Operation::JumpToAddressInWordReg(wreg) => format!("JP {}", wreg),
+        Operation::LoadRegIntoImmediateAddress(reg) => format!("LD ({:#06X}),{}", d16, reg),
+        Operation::XorImmediateIntoReg(reg) => format!("XOR {},{:#04X}", reg, d8),
+        Operation::LoadImmediateIORegisterIntoReg(reg) => {
+            format!("LDH {},({:#06X})", reg, 0xFF00 | d8 as u16)
+        }
+        Operation::LoadRegIORegisterIntoReg(offset, dst) => {
+            format!("LD {},(0xFF00+{})", dst, offset)
+        }
+        Operation::DisableInterrupts => "DI".to_string(),
+        Operation::OrImmediateIntoReg(reg) => format!("OR {},{:#04X}", reg, d8),
+        Operation::LoadSumOfWordRegAndSignedImmediateIntoWordReg(src, dst) => {
+            format!("LD {},{}{:+}", dst, src, s8)
+        }
+        Operation::LoadWordRegIntoWordReg(src, dst) => format!("LD {},{}", dst, src),
+        Operation::LoadAtImmediateAddressIntoReg(reg) => format!("LD {},({:#06X})", reg, d16),
+        Operation::EnableInterrupts => "EI".to_string(),
+        Operation::CompareImmediateAndReg(reg) => format!("CP {},{:#04X}", reg, d8),
+    };
+
+    (mnemonic, length(op))
+}
+
+/// The length of an operation in bytes, including its opcode and immediate operands.
+pub fn length(op: Operation) -> u8 {
+    match op {
+        Operation::LoadImmediateIntoWordReg(_)
+        | Operation::LoadSPIntoImmediateAddress
+        | Operation::ConditionalJumpImmediateAddress(_)
+        | Operation::NegativeConditionalJumpImmediateAddress(_)
+        | Operation::JumpImmediateAddress
+        | Operation::ConditionalCallImmediateAddress(_)
+        | Operation::NegativeConditionalCallImmediateAddress(_)
+        | Operation::CallImmediateAddress
+        | Operation::LoadRegIntoImmediateAddress(_)
+        | Operation::LoadAtImmediateAddressIntoReg(_) => 3,
+        Operation::LoadImmediateIntoReg(_)
+        | Operation::Stop
+        | Operation::RelativeJumpImmediateOffset
+        | Operation::ConditionalRelativeJumpImmediateOffset(_)
+        | Operation::NegativeConditionalRelativeJumpImmediateOffset(_)
+        | Operation::LoadImmediateIntoAddressInWordReg(_)
+        | Operation::AddImmediateIntoReg(_)
+        | Operation::Prefixed
+        | Operation::AddImmediateIntoRegWithCarry(_)
+        | Operation::SubImmediateFromReg(_)
+        | Operation::SubImmediateFromRegWithCarry(_)
+        | Operation::LoadRegIntoImmediateIORegister(_)
+        | Operation::AndImmediateIntoReg(_)
+        | Operation::AddSignedImmediateIntoWordReg(_)
+        | Operation::XorImmediateIntoReg(_)
+        | Operation::LoadImmediateIORegisterIntoReg(_)
+        | Operation::OrImmediateIntoReg(_)
+        | Operation::LoadSumOfWordRegAndSignedImmediateIntoWordReg(_, _)
+        | Operation::CompareImmediateAndReg(_) => 2,
+        _ => 1,
+    }
+}
+
+fn disassemble_prefixed(op: PrefixedOperation) -> String {
+    match op {
+        PrefixedOperation::RotateRegLeft(reg) => format!("RLC {}", reg),
+        PrefixedOperation::RotateAtAddressInWordRegLeft(wreg) => format!("RLC ({})", wreg),
+        PrefixedOperation::RotateRegRight(reg) => format!("RRC {}", reg),
+        PrefixedOperation::RotateAtAddressInWordRegRight(wreg) => format!("RRC ({})", wreg),
+        PrefixedOperation::RotateRegLeftThroughCarry(reg) => format!("RL {}", reg),
+        PrefixedOperation::RotateAtAddressInWordRegLeftThroughCarry(wreg) => {
+            format!("RL ({})", wreg)
+        }
+        PrefixedOperation::RotateRegRightThroughCarry(reg) => format!("RR {}", reg),
+        PrefixedOperation::RotateAtAddressInWordRegRightThroughCarry(wreg) => {
+            format!("RR ({})", wreg)
+        }
+        PrefixedOperation::ShiftRegLeftArithmetically(reg) => format!("SLA {}", reg),
+        PrefixedOperation::ShiftAtAddressInWordRegLeftArithmetically(wreg) => {
+            format!("SLA ({})", wreg)
+        }
+        PrefixedOperation::ShiftRegRightArithmetically(reg) => format!("SRA {}", reg),
+        PrefixedOperation::ShiftAtAddressInWordRegRightArithmetically(wreg) => {
+            format!("SRA ({})", wreg)
+        }
+        PrefixedOperation::SwapRegNibbles(reg) => format!("SWAP {}", reg),
+        PrefixedOperation::SwapAtAddressInWordRegNibbles(wreg) => format!("SWAP ({})", wreg),
+        PrefixedOperation::ShiftRegRightLogically(reg) => format!("SRL {}", reg),
+        PrefixedOperation::ShiftAtAddressInWordRegRightLogically(wreg) => {
+            format!("SRL ({})", wreg)
+        }
+        PrefixedOperation::TestForBitInReg(bit, reg) => format!("BIT {},{}", bit, reg),
+        PrefixedOperation::TestForBitInAtAddressInWordReg(bit, wreg) => {
+            format!("BIT {},({})", bit, wreg)
+        }
+        PrefixedOperation::ClearBitInReg(bit, reg) => format!("RES {},{}", bit, reg),
+        PrefixedOperation::ClearBitInAtAddressInWordReg(bit, wreg) => {
+            format!("RES {},({})", bit, wreg)
+        }
+        PrefixedOperation::SetBitInReg(bit, reg) => format!("SET {},{}", bit, reg),
+        PrefixedOperation::SetBitInAtAddressInWordReg(bit, wreg) => {
+            format!("SET {},({})", bit, wreg)
+        }
+    }
+}
+
+/// The name of a jump condition, such as `NZ`.
+fn condition(flag: CpuFlag, negative: bool) -> String {
+    let name = match flag {
+        CpuFlag::Zero => "Z",
+        CpuFlag::Negative => "N",
+        CpuFlag::Half => "H",
+        CpuFlag::Carry => "C",
+    };
+
+    if negative {
+        format!("N{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{
+        cpu::{Cpu, WordRegister},
+        rom::Rom,
+    };
+
+    /// Opcodes that jump, call or return, so PC doesn't end up right after them.
+    const JUMPS: [u8; 30] = [
+        0x18, 0x20, 0x28, 0x30, 0x38, 0xC0, 0xC2, 0xC3, 0xC4, 0xC7, 0xC8, 0xC9, 0xCA, 0xCC, 0xCD,
+        0xCF, 0xD0, 0xD2, 0xD4, 0xD7, 0xD8, 0xD9, 0xDA, 0xDC, 0xDF, 0xE7, 0xE9, 0xEF, 0xF7, 0xFF,
+    ];
+
+    #[test]
+    fn length_matches_how_far_the_cpu_moves() {
+        for opcode in 0..=0xFFu8 {
+            if ILLEGAL_OPCODES.contains(&opcode) || JUMPS.contains(&opcode) {
+                continue;
+            }
+
+            let mut memory =
+                Memory::new(Rom::from_bytes_unchecked(&[opcode]).unwrap(), Box::new([]));
+            let mut cpu = Cpu::new();
+            cpu.registers.set_reg_16(WordRegister::PC, 0x0000);
+            cpu.registers.set_reg_16(WordRegister::HL, 0xC000);
+            cpu.registers.set_reg_16(WordRegister::SP, 0xDFF0);
+            cpu.step(&mut memory, &mut |_| ());
+
+            let pc = cpu.registers.get_reg_16(WordRegister::PC);
+            assert_eq!(
+                length(Operation::from(opcode)) as u16,
+                pc,
+                "{:#04X}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn operands_are_resolved() {
+        // bytes past the instruction read as 0
+        let disassemble_bytes = |bytes: &[u8], pc: u16| {
+            let read = |address: u16| {
+                let index = address.wrapping_sub(pc) as usize;
+                bytes.get(index).copied().unwrap_or(0)
+            };
+            disassemble_with(read, pc)
+        };
+
+        assert_eq!(
+            disassemble_bytes(&[0x3E, 0x3F], 0x0000),
+            ("LD A,0x3F".to_string(), 2)
+        );
+        assert_eq!(
+            disassemble_bytes(&[0x20, 0xFB, 0x00], 0x0150),
+            ("JR NZ,0x014D".to_string(), 2)
+        );
+        assert_eq!(
+            disassemble_bytes(&[0xCB, 0x7C], 0x0000),
+            ("BIT 7,H".to_string(), 2)
+        );
+        assert_eq!(
+            disassemble_bytes(&[0xFA, 0x34, 0x12], 0x0000),
+            ("LD A,(0x1234)".to_string(), 3)
+        );
+        assert_eq!(
+            disassemble_bytes(&[0xD3], 0x0000),
+            ("ILLEGAL 0xD3".to_string(), 1)
+        );
+    }
+}
@@ -1,7 +1,9 @@
+pub mod disassembler;
 pub mod operation;
 
 use self::operation::*;
 use super::memory::{self, Memory};
+use super::scheduler::Event;
 use flagset::{flags, FlagSet};
 
 flags! {
@@ -37,6 +39,19 @@ pub enum WordRegister {
     PC,
 }
 
+impl std::fmt::Display for ByteRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the debug names are already the register names
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::fmt::Display for WordRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 /// Collection of Gameboy registers.
 #[derive(Default, Clone)]
 pub struct Registers {
@@ -151,11 +166,56 @@ pub enum MasterInterrupt {
     On,
 }
 
+impl std::fmt::Display for MasterInterrupt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MasterInterrupt::Off => write!(f, "Off"),
+            MasterInterrupt::TurningOn => write!(f, "Turning on"),
+            MasterInterrupt::On => write!(f, "On"),
+        }
+    }
+}
+
+/// What a call to [Cpu::step] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuStep {
+    /// The CPU is halted, nothing was executed.
+    Halted,
+    /// An interrupt was serviced instead of executing an instruction.
+    Interrupt,
+    /// An instruction was executed.
+    Instruction(ExecutedInstruction),
+}
+
+/// An instruction executed by [Cpu::step].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutedInstruction {
+    /// The address the opcode was fetched from.
+    pub pc: u16,
+    /// The opcode byte. For prefixed instructions, this is 0xCB.
+    pub opcode: u8,
+    /// The decoded operation.
+    pub operation: Operation,
+    /// The operation after the 0xCB prefix, for prefixed instructions.
+    pub prefixed: Option<PrefixedOperation>,
+}
+
+/// Called with the CPU state right before an instruction is fetched. See [Cpu::set_trace_hook].
+pub type TraceHook = Box<dyn FnMut(&Registers, &Memory) + Send>;
+
 /// CPU (Central Processing Unit) component of the Gameboy.
 pub struct Cpu {
     registers: Registers,
     master_interrupt_flag: MasterInterrupt,
     halt: bool,
+    stopped: bool,
+    trace_hook: Option<TraceHook>,
+    #[cfg(feature = "tdebugger")]
+    pc_guard: bool,
+    #[cfg(feature = "tdebugger")]
+    pc_guard_hit: Option<u16>,
+    #[cfg(feature = "tdebugger")]
+    masked_interrupts: FlagSet<memory::registers::Interrupt>,
 }
 
 impl Cpu {
@@ -164,6 +224,26 @@ impl Cpu {
             registers: Registers::new(),
             master_interrupt_flag: MasterInterrupt::Off,
             halt: false,
+            stopped: false,
+            trace_hook: None,
+            #[cfg(feature = "tdebugger")]
+            pc_guard: false,
+            #[cfg(feature = "tdebugger")]
+            pc_guard_hit: None,
+            #[cfg(feature = "tdebugger")]
+            masked_interrupts: FlagSet::default(),
+        }
+    }
+
+    /// Puts the CPU back in its power-on state. Debugging settings, like masked interrupts, are kept.
+    pub fn reset(&mut self) {
+        self.registers = Registers::new();
+        self.master_interrupt_flag = MasterInterrupt::Off;
+        self.halt = false;
+        self.stopped = false;
+        #[cfg(feature = "tdebugger")]
+        {
+            self.pc_guard_hit = None;
         }
     }
 
@@ -175,23 +255,106 @@ impl Cpu {
         &mut self.registers
     }
 
+    pub fn set_master_interrupt_flag(&mut self, value: MasterInterrupt) {
+        self.master_interrupt_flag = value;
+    }
+
     pub fn master_interrupt_flag(&self) -> MasterInterrupt {
         self.master_interrupt_flag
     }
 
+    /// Whether the CPU is halted, waiting for an interrupt.
+    pub fn halted(&self) -> bool {
+        self.halt
+    }
+
+    /// Sets a hook that's called exactly once before every opcode fetch, with the registers and memory as they are
+    /// at that point. It isn't called for interrupts being serviced or while halted.
+    pub fn set_trace_hook(&mut self, hook: TraceHook) {
+        self.trace_hook = Some(hook);
+    }
+
+    /// Removes the trace hook, if any, returning it.
+    pub fn take_trace_hook(&mut self) -> Option<TraceHook> {
+        self.trace_hook.take()
+    }
+
+    /// Whether the CPU is in STOP mode, waiting for a button press. [Cpu::step] must not be called while stopped.
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Leaves STOP mode. Called when a selected button is pressed.
+    pub fn leave_stop(&mut self) {
+        self.stopped = false;
+    }
+
+    /// Address accessed by the LDH family of instructions: `offset` bytes into 0xFF00..=0xFFFF, which covers the
+    /// IO registers, hram and IE.
+    #[inline]
+    fn high_address(offset: u8) -> u16 {
+        0xFF00 | offset as u16
+    }
+
     #[inline]
     fn mem_read(memory: &Memory, address: u16) -> u8 {
-        // TODO: add restrictions regarding PPU modes
+        if let Some(value) = memory.dma_conflict(address) {
+            return value;
+        }
+
+        if Self::ppu_locked(memory, address) {
+            return 0xFF;
+        }
+
         memory.read(address)
     }
 
+    /// Whether `address` is currently in use by the PPU and therefore inaccessible to the CPU. VRAM and the CGB
+    /// background color RAM are locked while rendering and OAM is locked during both OAM search and rendering.
+    #[inline]
+    fn ppu_locked(memory: &Memory, address: u16) -> bool {
+        let lcdc = memory::registers::LCDC::from(memory.read(memory::registers::addresses::LCDC));
+        if !lcdc.screen_enabled() {
+            return false;
+        }
+
+        let mode = memory.read(memory::registers::addresses::STAT) & 0b11;
+        match address {
+            0x8000..=0x9FFF | memory::registers::addresses::BCPD => mode == 3,
+            0xFE00..=0xFE9F => mode >= 2,
+            _ => false,
+        }
+    }
+
     #[inline]
     fn mem_write(memory: &mut Memory, address: u16, data: u8) {
+        if memory.dma_conflict(address).is_some() || Self::ppu_locked(memory, address) {
+            return;
+        }
+
         match address {
             memory::registers::addresses::LY => (),
-            memory::registers::addresses::DIV => memory.write(address, 0x00),
+            memory::registers::addresses::DIV => memory.reset_div(),
+            memory::registers::addresses::SC => memory.write_serial_control(data),
+            memory::registers::addresses::NR10..=memory::registers::addresses::NR52 => {
+                memory.write_apu_register(address, data)
+            }
+            memory::registers::addresses::JOYP => {
+                // only the select bits are writable
+                let current = memory.read(address);
+                memory.write(address, (current & !0b0011_0000) | (data & 0b0011_0000));
+            }
             memory::registers::addresses::STAT => {
                 memory.write(address, data & !0b0000_0111);
+                memory.request_sync(Event::Ppu);
+            }
+            memory::registers::addresses::LYC => {
+                memory.write(address, data);
+                memory.request_sync(Event::Ppu);
+            }
+            memory::registers::addresses::TAC => {
+                memory.write(address, data);
+                memory.request_sync(Event::Timer);
             }
             _ => memory.write(address, data),
         }
@@ -208,7 +371,7 @@ impl Cpu {
     }
 
     /// Step the CPU emulation. This is equivalent to one "fetch, decode, execute" cycle.
-    pub fn step<F>(&mut self, memory: &mut Memory, on_machine_cycle: &mut F)
+    pub fn step<F>(&mut self, memory: &mut Memory, on_machine_cycle: &mut F) -> CpuStep
     where
         F: FnMut(&mut Memory),
     {
@@ -227,23 +390,34 @@ impl Cpu {
                 if turn_master_interrupt_on {
                     self.master_interrupt_flag = MasterInterrupt::On;
                 }
-                return;
+                return CpuStep::Halted;
             }
 
             self.halt = false;
         }
 
-        // fetch
-        let opcode = self.fetch(memory);
-
+        // interrupts are checked before fetching, so PC still points at the instruction that would have
+        // been executed and is pushed as the return address
         if let MasterInterrupt::On = self.master_interrupt_flag {
             if self.handle_interrupts(memory, on_machine_cycle) {
-                return;
+                return CpuStep::Interrupt;
             }
         }
 
-        // decode and execute
+        // fetch
+        #[cfg(feature = "tdebugger")]
+        self.dbg_check_pc_region();
+        if let Some(hook) = &mut self.trace_hook {
+            hook(&self.registers, memory);
+        }
+
+        let pc = self.registers.get_reg_16(WordRegister::PC);
+        let opcode = self.fetch(memory);
+
+        // decode and execute. the byte after the prefix is peeked beforehand, as executing may overwrite it
         let op = Operation::from(opcode);
+        let prefixed = (op == Operation::Prefixed)
+            .then(|| PrefixedOperation::from(Self::mem_read(memory, pc.wrapping_add(1))));
         self.execute(op, memory, on_machine_cycle);
 
         // only turn master interrupt on if it was turning on at the start of the function and if it
@@ -251,12 +425,17 @@ impl Cpu {
         if turn_master_interrupt_on && self.master_interrupt_flag == MasterInterrupt::TurningOn {
             self.master_interrupt_flag = MasterInterrupt::On;
         }
+
+        CpuStep::Instruction(ExecutedInstruction {
+            pc,
+            opcode,
+            operation: op,
+            prefixed,
+        })
     }
 
-    pub fn handle_interrupts<F>(&mut self, memory: &mut Memory, on_machine_cycle: &mut F) -> bool
-    where
-        F: FnMut(&mut Memory),
-    {
+    /// Returns the highest priority interrupt that is both enabled and requested, if any.
+    fn pending_interrupt(&self, memory: &Memory) -> Option<memory::registers::Interrupt> {
         const INTERRUPT_PRIORITY: [memory::registers::Interrupt; 5] = [
             memory::registers::Interrupt::VBlank,
             memory::registers::Interrupt::STAT,
@@ -274,28 +453,30 @@ impl Cpu {
         )
         .unwrap();
 
-        let interrupt_to_handle = if let Some(int) = INTERRUPT_PRIORITY
+        // masked interrupts are never serviced, but they still wake the CPU from HALT
+        #[cfg(feature = "tdebugger")]
+        let enabled = enabled - self.masked_interrupts;
+
+        INTERRUPT_PRIORITY
             .into_iter()
             .find(|&i| requested.contains(i) && enabled.contains(i))
-        {
-            int
-        } else {
-            return false;
-        };
+    }
 
-        let address = match interrupt_to_handle {
-            memory::registers::Interrupt::VBlank => 0x40,
-            memory::registers::Interrupt::STAT => 0x48,
-            memory::registers::Interrupt::Timer => 0x50,
-            memory::registers::Interrupt::Serial => 0x58,
-            memory::registers::Interrupt::Joypad => 0x60,
-        };
+    /// Services the highest priority pending interrupt, if any. Returns whether an interrupt was serviced.
+    ///
+    /// Servicing an interrupt takes 5 machine cycles: 2 wait states, 2 cycles pushing PC and 1 cycle setting PC
+    /// to the interrupt vector. The first wait state is the machine cycle that would otherwise fetch the next
+    /// opcode, so it is expected to have already elapsed when this is called.
+    pub fn handle_interrupts<F>(&mut self, memory: &mut Memory, on_machine_cycle: &mut F) -> bool
+    where
+        F: FnMut(&mut Memory),
+    {
+        if self.pending_interrupt(memory).is_none() {
+            return false;
+        }
 
-        // decrement PC
+        // wait state
         on_machine_cycle(memory);
-        let current_pc = self.registers.get_reg_16(WordRegister::PC);
-        self.registers
-            .set_reg_16(WordRegister::PC, current_pc.wrapping_sub(1));
 
         // same as a CALL
         on_machine_cycle(memory);
@@ -305,20 +486,36 @@ impl Cpu {
         on_machine_cycle(memory);
         Self::mem_write(memory, current_sp.wrapping_sub(1), current_pc[1]);
 
+        // the interrupt to service is only decided after the high byte of PC is pushed: if that push
+        // overwrote IE and nothing is pending anymore, the dispatch is cancelled and PC ends up at 0x0000
+        let interrupt_to_handle = self.pending_interrupt(memory);
+
         on_machine_cycle(memory);
         Self::mem_write(memory, current_sp.wrapping_sub(2), current_pc[0]);
 
         self.registers
             .set_reg_16(WordRegister::SP, current_sp.wrapping_sub(2));
-        self.registers.set_reg_16(WordRegister::PC, address);
-
-        // clear interrupt request for the handled interrupt
-        let requested = requested & !interrupt_to_handle;
-        Self::mem_write(
-            memory,
-            memory::registers::addresses::INTERRUPT_REQUEST,
-            requested.bits(),
-        );
+
+        if let Some(interrupt) = interrupt_to_handle {
+            let address = match interrupt {
+                memory::registers::Interrupt::VBlank => 0x40,
+                memory::registers::Interrupt::STAT => 0x48,
+                memory::registers::Interrupt::Timer => 0x50,
+                memory::registers::Interrupt::Serial => 0x58,
+                memory::registers::Interrupt::Joypad => 0x60,
+            };
+            self.registers.set_reg_16(WordRegister::PC, address);
+
+            // clear interrupt request for the handled interrupt
+            let requested = Self::mem_read(memory, memory::registers::addresses::INTERRUPT_REQUEST);
+            Self::mem_write(
+                memory,
+                memory::registers::addresses::INTERRUPT_REQUEST,
+                requested & !FlagSet::from(interrupt).bits(),
+            );
+        } else {
+            self.registers.set_reg_16(WordRegister::PC, 0x0000);
+        }
 
         // turn off master interrupt
         self.master_interrupt_flag = MasterInterrupt::Off;
@@ -449,7 +646,21 @@ impl Cpu {
                 self.registers.set_flag(CpuFlag::Carry, carry);
             }
             Operation::Stop => {
-                // currently, no op
+                // STOP is 2 bytes long: the second one is usually 0x00 and is ignored. it also resets DIV.
+                self.fetch(memory);
+                Self::mem_write(memory, memory::registers::addresses::DIV, 0x00);
+
+                // on CGB, an armed KEY1 makes STOP switch speeds instead of stopping. double speed isn't emulated
+                // yet, so only KEY1 itself reflects the switch
+                let key1 = Self::mem_read(memory, memory::registers::addresses::KEY1);
+                if memory.cgb_mode() && key1 & 0b0000_0001 != 0 {
+                    memory.write(
+                        memory::registers::addresses::KEY1,
+                        (key1 ^ 0b1000_0000) & 0b1000_0000,
+                    );
+                } else {
+                    self.stopped = true;
+                }
             }
             Operation::RotateAccLeftThroughCarry => {
                 let acc = self.registers.get_reg_8(ByteRegister::A);
@@ -532,20 +743,29 @@ impl Cpu {
                 self.registers.set_reg_16(wreg, address.wrapping_add(1));
             }
             Operation::DecimalAdjustAcc => {
-                // when i wrote this, i knew why and how it worked. i definitely don't anymore.
-                // thanks https://ehaskins.com/2018-01-30%20Z80%20DAA/
+                // adjusts A back into BCD after an addition or subtraction of two BCD numbers. after an addition,
+                // the corrections are found from both the flags and the digits of A. after a subtraction, only the
+                // flags tell whether a digit borrowed, so carry is never set from A itself.
+                // see https://ehaskins.com/2018-01-30%20Z80%20DAA/
                 let a = self.registers.get_reg_8(ByteRegister::A);
                 let neg = self.registers.get_flag(CpuFlag::Negative);
+                let half = self.registers.get_flag(CpuFlag::Half);
+                let carry = self.registers.get_flag(CpuFlag::Carry);
+
+                let (low_correction, high_correction) = if neg {
+                    (half, carry)
+                } else {
+                    (half || a & 0x0F > 0x09, carry || a > 0x99)
+                };
 
-                let mut carry = false;
                 let mut correction = 0x00;
-                if !neg && (a > 0x99) || self.registers.get_flag(CpuFlag::Carry) {
-                    correction |= 0x60;
-                    carry = true;
-                }
-                if !neg && (a & 0x0F > 9) || self.registers.get_flag(CpuFlag::Half) {
+                if low_correction {
                     correction |= 0x06;
                 }
+                if high_correction {
+                    correction |= 0x60;
+                }
+
                 let corrected = if neg {
                     a.wrapping_sub(correction)
                 } else {
@@ -556,7 +776,7 @@ impl Cpu {
 
                 self.registers.set_flag(CpuFlag::Zero, corrected == 0);
                 self.registers.set_flag(CpuFlag::Half, false);
-                self.registers.set_flag(CpuFlag::Carry, carry);
+                self.registers.set_flag(CpuFlag::Carry, high_correction);
             }
             Operation::LoadAtAddressInWordRegIntoRegAndIncrementWordReg(wreg, reg) => {
                 on_machine_cycle(memory);
@@ -895,6 +1115,8 @@ impl Cpu {
 
                     self.registers
                         .set_reg_16(WordRegister::SP, current_sp.wrapping_add(2));
+
+                    on_machine_cycle(memory);
                     self.registers
                         .set_reg_16(WordRegister::PC, u16::from_le_bytes([low, high]));
                 }
@@ -912,6 +1134,8 @@ impl Cpu {
 
                     self.registers
                         .set_reg_16(WordRegister::SP, current_sp.wrapping_add(2));
+
+                    on_machine_cycle(memory);
                     self.registers
                         .set_reg_16(WordRegister::PC, u16::from_le_bytes([low, high]));
                 }
@@ -1041,6 +1265,7 @@ impl Cpu {
                 self.registers.set_flag(CpuFlag::Carry, carry);
             }
             Operation::CallFixedAddress(address) => {
+                // same as a CALL, but without reading an immediate address: 4 machine cycles in total
                 on_machine_cycle(memory);
                 let current_pc = self.registers.get_reg_16(WordRegister::PC).to_le_bytes();
                 let current_sp = self.registers.get_reg_16(WordRegister::SP);
@@ -1409,6 +1634,8 @@ impl Cpu {
                 self.registers.set_flag(CpuFlag::Carry, carry);
             }
             Operation::ReturnAndEnableInterrupts => {
+                // unlike EI there's no delay: interrupts are checked at the start of the next step, so one that's
+                // already pending is serviced right after RETI, with the address RETI returned to pushed
                 self.master_interrupt_flag = MasterInterrupt::On;
                 let current_sp = self.registers.get_reg_16(WordRegister::SP);
 
@@ -1420,6 +1647,8 @@ impl Cpu {
 
                 self.registers
                     .set_reg_16(WordRegister::SP, current_sp.wrapping_add(2));
+
+                on_machine_cycle(memory);
                 self.registers
                     .set_reg_16(WordRegister::PC, u16::from_le_bytes([low, high]));
             }
@@ -1445,18 +1674,22 @@ impl Cpu {
             }
             Operation::LoadRegIntoImmediateIORegister(reg) => {
                 on_machine_cycle(memory);
-                let register = self.fetch(memory);
+                let offset = self.fetch(memory);
                 on_machine_cycle(memory);
                 Self::mem_write(
                     memory,
-                    0xFF00u16.wrapping_add(register as u16),
+                    Self::high_address(offset),
                     self.registers.get_reg_8(reg),
                 );
             }
             Operation::LoadRegIntoRegIORegister(reg_a, reg_b) => {
                 on_machine_cycle(memory);
-                let b = self.registers.get_reg_8(reg_b) as u16;
-                Self::mem_write(memory, 0xFF00u16 + b, self.registers.get_reg_8(reg_a));
+                let offset = self.registers.get_reg_8(reg_b);
+                Self::mem_write(
+                    memory,
+                    Self::high_address(offset),
+                    self.registers.get_reg_8(reg_a),
+                );
             }
             Operation::AndImmediateIntoReg(reg) => {
                 on_machine_cycle(memory);
@@ -1477,7 +1710,8 @@ impl Cpu {
                 let r = self.registers.get_reg_16(reg);
                 let res = r.wrapping_add_signed(signed as i16);
 
-                // maybe inaccurate
+                // two internal cycles: the alu adds the offset to the low byte, then adjusts the high byte with the
+                // carry. SP is only written back at the end of the second one
                 on_machine_cycle(memory);
                 on_machine_cycle(memory);
                 self.registers.set_reg_16(reg, res);
@@ -1494,6 +1728,8 @@ impl Cpu {
                 );
             }
             Operation::JumpToAddressInWordReg(wreg) => {
+                // JP HL is the only jump that takes a single machine cycle: the address is already in a register,
+                // so nothing besides the opcode fetch touches memory
                 let address = self.registers.get_reg_16(wreg);
                 self.registers.set_reg_16(WordRegister::PC, address);
             }
@@ -1523,17 +1759,17 @@ impl Cpu {
             }
             Operation::LoadImmediateIORegisterIntoReg(reg) => {
                 on_machine_cycle(memory);
-                let register = self.fetch(memory);
+                let offset = self.fetch(memory);
 
                 on_machine_cycle(memory);
-                let byte = Self::mem_read(memory, 0xFF00u16.wrapping_add(register as u16));
+                let byte = Self::mem_read(memory, Self::high_address(offset));
 
                 self.registers.set_reg_8(reg, byte);
             }
             Operation::LoadRegIORegisterIntoReg(reg_a, reg_b) => {
                 on_machine_cycle(memory);
-                let a = self.registers.get_reg_8(reg_a) as u16;
-                let byte = Self::mem_read(memory, 0xFF00u16 + a);
+                let offset = self.registers.get_reg_8(reg_a);
+                let byte = Self::mem_read(memory, Self::high_address(offset));
 
                 self.registers.set_reg_8(reg_b, byte);
             }
@@ -1557,6 +1793,8 @@ impl Cpu {
                 on_machine_cycle(memory);
                 let signed = self.fetch(memory) as i8;
 
+                // a single internal cycle: unlike ADD SP,e8, the high byte is adjusted while HL is written, so it
+                // takes one cycle less
                 on_machine_cycle(memory);
                 let a = self.registers.get_reg_16(wreg_a);
                 let res = a.wrapping_add_signed(signed as i16);
@@ -1607,3 +1845,470 @@ impl Cpu {
         }
     }
 }
+
+// debug
+#[cfg(feature = "tdebugger")]
+impl Cpu {
+    /// Enables or disables the PC guard, which records when PC enters a region that almost certainly means
+    /// the game has crashed.
+    pub fn dbg_set_pc_guard(&mut self, enabled: bool) {
+        self.pc_guard = enabled;
+        self.pc_guard_hit = None;
+    }
+
+    pub fn dbg_pc_guard(&self) -> bool {
+        self.pc_guard
+    }
+
+    /// Stops or resumes servicing the given interrupt, without the game noticing: IE and IF are left untouched.
+    pub fn dbg_set_interrupt_masked(
+        &mut self,
+        interrupt: memory::registers::Interrupt,
+        masked: bool,
+    ) {
+        if masked {
+            self.masked_interrupts |= interrupt;
+        } else {
+            self.masked_interrupts -= interrupt;
+        }
+    }
+
+    pub fn dbg_masked_interrupts(&self) -> FlagSet<memory::registers::Interrupt> {
+        self.masked_interrupts
+    }
+
+    /// Returns the address of the last instruction fetched from an unusual region, if any, clearing it.
+    pub fn dbg_take_pc_guard_hit(&mut self) -> Option<u16> {
+        self.pc_guard_hit.take()
+    }
+
+    fn dbg_check_pc_region(&mut self) {
+        if !self.pc_guard {
+            return;
+        }
+
+        // hram is left out since games commonly run their OAM DMA routine from it
+        let pc = self.registers.get_reg_16(WordRegister::PC);
+        if matches!(pc, 0xE000..=0xFDFF | 0xFEA0..=0xFEFF | 0xFF00..=0xFF7F | 0xFFFF) {
+            self.pc_guard_hit = Some(pc);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::rom::Rom;
+
+    /// Machine cycles taken by each unprefixed opcode, using the not taken path for conditionals. Illegal opcodes,
+    /// the 0xCB prefix, STOP and HALT are 0 and checked elsewhere.
+    #[rustfmt::skip]
+    const CYCLES: [u8; 256] = [
+        1, 3, 2, 2, 1, 1, 2, 1, 5, 2, 2, 2, 1, 1, 2, 1, 0, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1,
+        2, 3, 2, 2, 1, 1, 2, 1, 2, 2, 2, 2, 1, 1, 2, 1, 2, 3, 2, 2, 3, 3, 3, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, 2, 2, 2, 2, 2, 2, 0, 2, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        2, 3, 3, 4, 3, 4, 2, 4, 2, 4, 3, 0, 3, 6, 2, 4, 2, 3, 3, 0, 3, 4, 2, 4, 2, 4, 3, 0, 3, 0, 2, 4,
+        3, 3, 2, 0, 0, 4, 2, 4, 4, 1, 4, 0, 0, 0, 2, 4, 3, 3, 2, 1, 0, 4, 2, 4, 3, 2, 4, 1, 0, 0, 2, 4,
+    ];
+
+    /// Conditional opcodes and their machine cycles when the condition holds.
+    const TAKEN_CYCLES: [(u8, u8); 16] = [
+        (0x20, 3),
+        (0x28, 3),
+        (0x30, 3),
+        (0x38, 3),
+        (0xC0, 5),
+        (0xC8, 5),
+        (0xD0, 5),
+        (0xD8, 5),
+        (0xC2, 4),
+        (0xCA, 4),
+        (0xD2, 4),
+        (0xDA, 4),
+        (0xC4, 6),
+        (0xCC, 6),
+        (0xD4, 6),
+        (0xDC, 6),
+    ];
+
+    /// Creates a CPU running `program` from 0x0000, with no MBC and the rest of the address space as it is at
+    /// power on. HL points to wram and SP to the top of it.
+    fn machine(program: &[u8]) -> (Cpu, Memory) {
+        let memory = Memory::new(Rom::from_bytes_unchecked(program).unwrap(), Box::new([]));
+        let mut cpu = Cpu::new();
+        cpu.registers.set_reg_16(WordRegister::PC, 0x0000);
+        cpu.registers.set_reg_16(WordRegister::HL, 0xC000);
+        cpu.registers.set_reg_16(WordRegister::SP, 0xDFF0);
+        (cpu, memory)
+    }
+
+    /// Runs a single [Cpu::step], returning how many machine cycles it took.
+    fn step(cpu: &mut Cpu, memory: &mut Memory) -> u8 {
+        let mut cycles = 0;
+        cpu.step(memory, &mut |_| cycles += 1);
+        cycles
+    }
+
+    /// Runs `program` for a single step with the given flags, returning how many machine cycles it took.
+    fn cycles_with_flags(program: &[u8], flags: u8) -> u8 {
+        let (mut cpu, mut memory) = machine(program);
+        cpu.registers.set_reg_8(ByteRegister::F, flags);
+        step(&mut cpu, &mut memory)
+    }
+
+    #[test]
+    fn unprefixed_cycles() {
+        for opcode in 0..=255u8 {
+            let expected = CYCLES[opcode as usize];
+            if expected == 0 {
+                continue;
+            }
+
+            let program = [opcode, 0x00, 0x00];
+            match TAKEN_CYCLES.iter().find(|(op, _)| *op == opcode) {
+                Some(&(_, taken)) => {
+                    // bit 3 selects between the NZ/NC and Z/C conditions
+                    let (taken_flags, not_taken_flags) = if opcode & 0x08 == 0 {
+                        (0x00, 0xF0)
+                    } else {
+                        (0xF0, 0x00)
+                    };
+                    assert_eq!(
+                        cycles_with_flags(&program, not_taken_flags),
+                        expected,
+                        "{:02X} not taken",
+                        opcode
+                    );
+                    assert_eq!(
+                        cycles_with_flags(&program, taken_flags),
+                        taken,
+                        "{:02X} taken",
+                        opcode
+                    );
+                }
+                None => assert_eq!(
+                    cycles_with_flags(&program, 0x00),
+                    expected,
+                    "{:02X}",
+                    opcode
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn prefixed_cycles() {
+        for opcode in 0..=255u8 {
+            // (HL) operands need an extra read, and an extra write too unless it's BIT
+            let expected = match opcode {
+                _ if opcode & 0x07 != 0x06 => 2,
+                0x40..=0x7F => 3,
+                _ => 4,
+            };
+            assert_eq!(
+                cycles_with_flags(&[0xCB, opcode], 0x00),
+                expected,
+                "CB {:02X}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn rst_vectors() {
+        for (opcode, vector) in [
+            (0xC7, 0x00),
+            (0xCF, 0x08),
+            (0xD7, 0x10),
+            (0xDF, 0x18),
+            (0xE7, 0x20),
+            (0xEF, 0x28),
+            (0xF7, 0x30),
+            (0xFF, 0x38),
+        ] {
+            assert_eq!(Operation::from(opcode), Operation::CallFixedAddress(vector));
+
+            let mut program = vec![0u8; 0x300];
+            program[0x200] = opcode;
+            let (mut cpu, mut memory) = machine(&program);
+            cpu.registers.set_reg_16(WordRegister::PC, 0x0200);
+
+            assert_eq!(step(&mut cpu, &mut memory), 4, "{:02X}", opcode);
+            assert_eq!(cpu.registers.get_reg_16(WordRegister::PC), vector);
+            assert_eq!(cpu.registers.get_reg_16(WordRegister::SP), 0xDFEE);
+            // the return address is the byte after the RST
+            assert_eq!(memory.read(0xDFEE), 0x01);
+            assert_eq!(memory.read(0xDFEF), 0x02);
+        }
+    }
+
+    /// Reads the word at the top of the stack.
+    fn stack_top(cpu: &Cpu, memory: &Memory) -> u16 {
+        let sp = cpu.registers.get_reg_16(WordRegister::SP);
+        u16::from_le_bytes([memory.read(sp), memory.read(sp.wrapping_add(1))])
+    }
+
+    fn request(memory: &mut Memory, enabled: u8, requested: u8) {
+        memory.write(memory::registers::addresses::INTERRUPT_ENABLE, enabled);
+        memory.write(memory::registers::addresses::INTERRUPT_REQUEST, requested);
+    }
+
+    #[test]
+    fn interrupt_after_ei_waits_one_instruction() {
+        // EI; NOP; NOP
+        let (mut cpu, mut memory) = machine(&[0xFB, 0x00, 0x00]);
+        request(&mut memory, 0x01, 0x01);
+
+        assert_eq!(step(&mut cpu, &mut memory), 1);
+        assert!(cpu.master_interrupt_flag == MasterInterrupt::TurningOn);
+
+        // the instruction right after EI still runs
+        assert_eq!(step(&mut cpu, &mut memory), 1);
+        assert_eq!(cpu.registers.get_reg_16(WordRegister::PC), 0x0002);
+        assert!(cpu.master_interrupt_flag == MasterInterrupt::On);
+
+        // the dispatch takes 5 cycles, the first being the skipped fetch
+        assert_eq!(step(&mut cpu, &mut memory), 5);
+        assert_eq!(cpu.registers.get_reg_16(WordRegister::PC), 0x0040);
+        assert_eq!(stack_top(&cpu, &memory), 0x0002);
+        assert!(cpu.master_interrupt_flag == MasterInterrupt::Off);
+    }
+
+    #[test]
+    fn interrupt_cancelled_when_push_clears_ie() {
+        // SP at 0x0000 makes the high byte of PC land on IE
+        let (mut cpu, mut memory) = machine(&[]);
+        cpu.registers.set_reg_16(WordRegister::PC, 0x0010);
+        cpu.registers.set_reg_16(WordRegister::SP, 0x0000);
+        cpu.set_master_interrupt_flag(MasterInterrupt::On);
+        request(&mut memory, 0x01, 0x01);
+
+        step(&mut cpu, &mut memory);
+        assert_eq!(cpu.registers.get_reg_16(WordRegister::PC), 0x0000);
+        assert_eq!(
+            memory.read(memory::registers::addresses::INTERRUPT_ENABLE),
+            0x00
+        );
+        // nothing was serviced, so the request is still there
+        assert_eq!(
+            memory.read(memory::registers::addresses::INTERRUPT_REQUEST) & 0x1F,
+            0x01
+        );
+        assert!(cpu.master_interrupt_flag == MasterInterrupt::Off);
+    }
+
+    #[test]
+    fn interrupt_redirected_when_push_changes_ie() {
+        // the high byte of PC (0x02) leaves only STAT enabled, so it's serviced instead of vblank
+        let (mut cpu, mut memory) = machine(&[]);
+        cpu.registers.set_reg_16(WordRegister::PC, 0x0210);
+        cpu.registers.set_reg_16(WordRegister::SP, 0x0000);
+        cpu.set_master_interrupt_flag(MasterInterrupt::On);
+        request(&mut memory, 0x01, 0x03);
+
+        step(&mut cpu, &mut memory);
+        assert_eq!(cpu.registers.get_reg_16(WordRegister::PC), 0x0048);
+        assert_eq!(
+            memory.read(memory::registers::addresses::INTERRUPT_REQUEST) & 0x1F,
+            0x01
+        );
+    }
+
+    #[test]
+    fn interrupt_pushes_next_instruction() {
+        let (mut cpu, mut memory) = machine(&[]);
+        cpu.registers.set_reg_16(WordRegister::PC, 0x0123);
+        cpu.set_master_interrupt_flag(MasterInterrupt::On);
+        request(&mut memory, 0x04, 0x04);
+
+        assert_eq!(step(&mut cpu, &mut memory), 5);
+        assert_eq!(cpu.registers.get_reg_16(WordRegister::PC), 0x0050);
+        assert_eq!(cpu.registers.get_reg_16(WordRegister::SP), 0xDFEE);
+        assert_eq!(stack_top(&cpu, &memory), 0x0123);
+        assert_eq!(
+            memory.read(memory::registers::addresses::INTERRUPT_REQUEST) & 0x1F,
+            0x00
+        );
+    }
+
+    #[test]
+    fn interrupt_after_halt_pushes_next_instruction() {
+        // HALT; NOP
+        let (mut cpu, mut memory) = machine(&[0x76, 0x00]);
+        cpu.set_master_interrupt_flag(MasterInterrupt::On);
+        request(&mut memory, 0x01, 0x00);
+
+        step(&mut cpu, &mut memory);
+        assert!(cpu.halted());
+        assert!(matches!(
+            cpu.step(&mut memory, &mut |_| ()),
+            CpuStep::Halted
+        ));
+
+        request(&mut memory, 0x01, 0x01);
+        assert!(matches!(
+            cpu.step(&mut memory, &mut |_| ()),
+            CpuStep::Interrupt
+        ));
+        assert_eq!(cpu.registers.get_reg_16(WordRegister::PC), 0x0040);
+        assert_eq!(stack_top(&cpu, &memory), 0x0001);
+    }
+
+    #[test]
+    fn ldh_forms_reach_the_high_page() {
+        let (mut cpu, mut memory) = machine(&[
+            0x3E, 0x42, // LD A, 0x42
+            0xE0, 0x80, // LDH (0x80), A
+            0x0E, 0x81, // LD C, 0x81
+            0x3E, 0x37, // LD A, 0x37
+            0xE2, // LD (C), A
+            0x0E, 0x80, // LD C, 0x80
+            0xF2, // LD A, (C)
+            0x47, // LD B, A
+            0xF0, 0x81, // LDH A, (0x81)
+            0x57, // LD D, A
+            0x3E, 0x1F, // LD A, 0x1F
+            0x0E, 0xFF, // LD C, 0xFF
+            0xE2, // LD (C), A
+            0xF0, 0xFF, // LDH A, (0xFF)
+            0x5F, // LD E, A
+        ]);
+        for _ in 0..15 {
+            step(&mut cpu, &mut memory);
+        }
+
+        assert_eq!(memory.read(0xFF80), 0x42);
+        assert_eq!(memory.read(0xFF81), 0x37);
+        assert_eq!(
+            memory.read(memory::registers::addresses::INTERRUPT_ENABLE),
+            0x1F
+        );
+        assert_eq!(cpu.registers.get_reg_8(ByteRegister::B), 0x42);
+        assert_eq!(cpu.registers.get_reg_8(ByteRegister::D), 0x37);
+        assert_eq!(cpu.registers.get_reg_8(ByteRegister::E), 0x1F);
+    }
+
+    #[test]
+    fn interrupt_pending_across_reti_is_serviced_right_after() {
+        // inside the vblank handler, about to return to 0x0200 with the timer interrupt pending
+        let mut program = vec![0x00; 0x201];
+        program[0x40] = 0xD9; // RETI
+        let (mut cpu, mut memory) = machine(&program);
+        cpu.registers.set_reg_16(WordRegister::PC, 0x0040);
+        cpu.registers.set_reg_16(WordRegister::SP, 0xDFEE);
+        memory.write(0xDFEE, 0x00);
+        memory.write(0xDFEF, 0x02);
+        request(&mut memory, 0x05, 0x04);
+
+        // unlike EI, RETI enables interrupts right away
+        assert_eq!(step(&mut cpu, &mut memory), 4);
+        assert_eq!(cpu.registers.get_reg_16(WordRegister::PC), 0x0200);
+        assert!(cpu.master_interrupt_flag == MasterInterrupt::On);
+
+        // so not even the instruction at the return address runs before the dispatch
+        assert_eq!(step(&mut cpu, &mut memory), 5);
+        assert_eq!(cpu.registers.get_reg_16(WordRegister::PC), 0x0050);
+        assert_eq!(stack_top(&cpu, &memory), 0x0200);
+    }
+
+    #[test]
+    fn jp_hl_takes_a_single_cycle() {
+        for opcode in 0..=0xFF {
+            let jp_hl =
+                Operation::from(opcode) == Operation::JumpToAddressInWordReg(WordRegister::HL);
+            assert_eq!(jp_hl, opcode == 0xE9, "{:02X}", opcode);
+        }
+
+        let (mut cpu, mut memory) = machine(&[0xE9]);
+        cpu.registers.set_reg_16(WordRegister::HL, 0x1234);
+        assert_eq!(step(&mut cpu, &mut memory), 1);
+        assert_eq!(cpu.registers.get_reg_16(WordRegister::PC), 0x1234);
+    }
+
+    #[test]
+    fn sp_offset_instructions() {
+        // ADD SP, -0x10 writes back to SP, taking one more cycle than LD HL, SP-0x10
+        let (mut cpu, mut memory) = machine(&[0xE8, 0xF0]);
+        assert_eq!(step(&mut cpu, &mut memory), 4);
+        assert_eq!(cpu.registers.get_reg_16(WordRegister::SP), 0xDFE0);
+
+        let (mut cpu, mut memory) = machine(&[0xF8, 0xF0]);
+        assert_eq!(step(&mut cpu, &mut memory), 3);
+        assert_eq!(cpu.registers.get_reg_16(WordRegister::HL), 0xDFE0);
+        assert_eq!(cpu.registers.get_reg_16(WordRegister::SP), 0xDFF0);
+
+        // H and C come from the unsigned addition of the low byte, Z and N are always cleared
+        for opcode in [0xE8, 0xF8] {
+            let (mut cpu, mut memory) = machine(&[opcode, 0x01]);
+            cpu.registers.set_reg_16(WordRegister::SP, 0x00FF);
+            cpu.registers.set_reg_8(ByteRegister::F, 0xF0);
+            step(&mut cpu, &mut memory);
+            assert_eq!(
+                cpu.registers.get_reg_8(ByteRegister::F),
+                0x30,
+                "{:02X}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn daa_after_arithmetic() {
+        // (opcode, carry in, a, b, a after daa, carry after daa)
+        let cases = [
+            // ADD A,B
+            (0x80, false, 0x12, 0x34, 0x46, false),
+            (0x80, false, 0x15, 0x27, 0x42, false),
+            (0x80, false, 0x19, 0x28, 0x47, false),
+            (0x80, false, 0x90, 0x90, 0x80, true),
+            (0x80, false, 0x99, 0x99, 0x98, true),
+            (0x80, false, 0x50, 0x50, 0x00, true),
+            // ADC A,B
+            (0x88, true, 0x19, 0x28, 0x48, false),
+            (0x88, true, 0x99, 0x00, 0x00, true),
+            (0x88, false, 0x45, 0x45, 0x90, false),
+            // SUB A,B
+            (0x90, false, 0x47, 0x15, 0x32, false),
+            (0x90, false, 0x42, 0x15, 0x27, false),
+            (0x90, false, 0x15, 0x42, 0x73, true),
+            (0x90, false, 0x12, 0x29, 0x83, true),
+            (0x90, false, 0x37, 0x37, 0x00, false),
+            // SBC A,B
+            (0x98, true, 0x40, 0x00, 0x39, false),
+            (0x98, true, 0x00, 0x00, 0x99, true),
+            (0x98, false, 0x50, 0x25, 0x25, false),
+        ];
+
+        for (opcode, carry_in, a, b, expected, carry) in cases {
+            let mut program = vec![0x3E, a, 0x06, b];
+            if carry_in {
+                program.push(0x37);
+            }
+            program.extend([opcode, 0x27]);
+
+            let (mut cpu, mut memory) = machine(&program);
+            while (cpu.registers.get_reg_16(WordRegister::PC) as usize) < program.len() {
+                step(&mut cpu, &mut memory);
+            }
+
+            let subtract = opcode >= 0x90;
+            let flags = ((expected == 0) as u8) << 7 | (subtract as u8) << 6 | (carry as u8) << 4;
+            let context = format!("{:02X} {:02X} {:02X} with carry {}", opcode, a, b, carry_in);
+            assert_eq!(
+                cpu.registers.get_reg_8(ByteRegister::A),
+                expected,
+                "{}",
+                context
+            );
+            assert_eq!(
+                cpu.registers.get_reg_8(ByteRegister::F),
+                flags,
+                "{}",
+                context
+            );
+        }
+    }
+}
@@ -60,6 +60,7 @@ pub enum Operation {
     NegativeConditionalCallImmediateAddress(CpuFlag),
     PushWordRegIntoStack(WordRegister),
     AddImmediateIntoReg(ByteRegister),
+    /// RST instructions. Pushes PC and jumps to one of the fixed vectors 0x00, 0x08, ..., 0x38.
     CallFixedAddress(u16),
     Return,
     Prefixed,
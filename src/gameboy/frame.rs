@@ -0,0 +1,252 @@
+use super::ppu::{ScreenBuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Amount of bytes in a row of a [FrameBuffer].
+pub const FRAME_STRIDE: usize = SCREEN_WIDTH * 4;
+
+/// Shades of the original DMG LCD, from lightest to darkest. Unlike palettes, they aren't evenly spaced: the two
+/// lightest shades are very close to each other.
+pub const DMG_LCD_COLORS: [[u8; 4]; 4] = [
+    [0x9B, 0xBC, 0x0F, 0xFF],
+    [0x8B, 0xAC, 0x0F, 0xFF],
+    [0x30, 0x62, 0x30, 0xFF],
+    [0x0F, 0x38, 0x0F, 0xFF],
+];
+
+/// The unblended RGBA of the last two frames, used when frame blending is enabled.
+#[derive(Clone)]
+struct FrameBlend {
+    current: Box<[u8; FRAME_STRIDE * SCREEN_HEIGHT]>,
+    previous: Option<Box<[u8; FRAME_STRIDE * SCREEN_HEIGHT]>>,
+}
+
+/// A frame ready to be displayed: the screen with a palette applied, as RGBA bytes.
+#[derive(Clone)]
+pub struct FrameBuffer {
+    rgba: Box<[u8; FRAME_STRIDE * SCREEN_HEIGHT]>,
+    frame_number: u64,
+    dirty: bool,
+    dmg_look: bool,
+    blend: Option<FrameBlend>,
+}
+
+impl FrameBuffer {
+    pub fn new() -> Self {
+        Self {
+            rgba: crate::util::boxed_array_copy(0),
+            frame_number: 0,
+            dirty: false,
+            dmg_look: false,
+            blend: None,
+        }
+    }
+
+    /// Sets whether frames are converted with the shades of the original DMG LCD ([DMG_LCD_COLORS]) instead of
+    /// the colors given to [FrameBuffer::update].
+    pub fn set_dmg_look(&mut self, enabled: bool) {
+        self.dmg_look = enabled;
+    }
+
+    pub fn dmg_look(&self) -> bool {
+        self.dmg_look
+    }
+
+    /// Sets whether each frame is averaged with the one before it, smoothing out games that only update the screen
+    /// every other frame.
+    pub fn set_frame_blend(&mut self, enabled: bool) {
+        self.blend = enabled.then(|| FrameBlend {
+            current: crate::util::boxed_array_copy(0),
+            previous: None,
+        });
+    }
+
+    pub fn frame_blend(&self) -> bool {
+        self.blend.is_some()
+    }
+
+    /// Converts the given screen into RGBA through `colors`, which is indexed by shade, unless the screen holds
+    /// CGB colors. The frame is marked as dirty if `frame_number` is different from the one it previously held.
+    pub fn update(&mut self, screen: &ScreenBuffer, frame_number: u64, colors: &[[u8; 4]; 4]) {
+        let colors = if self.dmg_look {
+            &DMG_LCD_COLORS
+        } else {
+            colors
+        };
+
+        if let Some(blend) = &mut self.blend {
+            // the frame being replaced becomes the one to blend with
+            if let Some(previous) = blend
+                .previous
+                .as_mut()
+                .filter(|_| self.frame_number != frame_number)
+            {
+                previous.copy_from_slice(&blend.current[..]);
+            }
+
+            Self::convert(screen, colors, &mut blend.current[..]);
+
+            // the first frame is blended with itself
+            let previous = blend.previous.get_or_insert_with(|| blend.current.clone());
+            for ((target, current), previous) in self
+                .rgba
+                .iter_mut()
+                .zip(blend.current.iter())
+                .zip(previous.iter())
+            {
+                *target = ((*current as u16 + *previous as u16) / 2) as u8;
+            }
+        } else {
+            Self::convert(screen, colors, &mut self.rgba[..]);
+        }
+
+        self.dirty |= self.frame_number != frame_number;
+        self.frame_number = frame_number;
+    }
+
+    fn convert(screen: &ScreenBuffer, colors: &[[u8; 4]; 4], target: &mut [u8]) {
+        // screens rendered in CGB mode already have their own colors
+        if let Some(screen_colors) = screen.colors() {
+            for (pixel, color) in target.chunks_exact_mut(4).zip(screen_colors) {
+                let [r, g, b] = crate::util::bgr555_to_rgb(*color);
+                pixel.copy_from_slice(&[r, g, b, 0xFF]);
+            }
+            return;
+        }
+
+        for (i, pixel) in target.chunks_exact_mut(4).enumerate() {
+            let (y, x) = crate::util::div_rem(i, SCREEN_WIDTH);
+            let shade = screen.get_pixel(x, y).unwrap();
+            pixel.copy_from_slice(&colors[shade as usize]);
+        }
+    }
+
+    /// The RGBA bytes of the frame, row by row.
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba[..]
+    }
+
+    /// The number of the frame this buffer holds, as counted by the PPU.
+    pub fn frame_number(&self) -> u64 {
+        self.frame_number
+    }
+
+    /// Whether a new frame was written since the dirty flag was last cleared.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty = false;
+    }
+
+    /// Copies the frame into `target`, which has rows of `stride` bytes. Rows must be at least [FRAME_STRIDE] bytes
+    /// long.
+    pub fn copy_to(&self, target: &mut [u8], stride: usize) -> anyhow::Result<()> {
+        if stride < FRAME_STRIDE {
+            anyhow::bail!("Stride of {} bytes is too short for a frame row", stride);
+        }
+
+        for (source, target) in self
+            .rgba
+            .chunks_exact(FRAME_STRIDE)
+            .zip(target.chunks_mut(stride))
+        {
+            target[..FRAME_STRIDE].copy_from_slice(source);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [[u8; 4]; 4] = [
+        [0xFF, 0xFF, 0xFF, 0xFF],
+        [0xAA, 0xAA, 0xAA, 0xFF],
+        [0x40, 0x20, 0x10, 0xFF],
+        [0x00, 0x00, 0x00, 0xFF],
+    ];
+
+    /// A screen with every pixel set to `shade`.
+    fn screen(shade: u8) -> ScreenBuffer {
+        let mut screen = ScreenBuffer::new();
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                screen.set_pixel(x, y, shade).unwrap();
+            }
+        }
+        screen
+    }
+
+    #[test]
+    fn update_maps_shades_through_the_colors() {
+        let mut screen = ScreenBuffer::new();
+        for shade in 0..4 {
+            screen.set_pixel(shade as usize, 0, shade).unwrap();
+        }
+
+        let mut frame = FrameBuffer::new();
+        frame.update(&screen, 1, &COLORS);
+        for (pixel, color) in frame.rgba().chunks_exact(4).zip(COLORS) {
+            assert_eq!(pixel, color);
+        }
+
+        frame.set_dmg_look(true);
+        frame.update(&screen, 1, &COLORS);
+        for (pixel, color) in frame.rgba().chunks_exact(4).zip(DMG_LCD_COLORS) {
+            assert_eq!(pixel, color);
+        }
+    }
+
+    #[test]
+    fn dirty_only_on_new_frames() {
+        let mut frame = FrameBuffer::new();
+        frame.update(&screen(0), 0, &COLORS);
+        assert!(!frame.is_dirty());
+
+        frame.update(&screen(0), 1, &COLORS);
+        assert!(frame.is_dirty());
+        assert_eq!(frame.frame_number(), 1);
+
+        frame.clear_dirty();
+        assert!(!frame.is_dirty());
+        frame.update(&screen(3), 1, &COLORS);
+        assert!(!frame.is_dirty());
+    }
+
+    #[test]
+    fn blending_averages_with_the_previous_frame() {
+        let mut frame = FrameBuffer::new();
+        frame.set_frame_blend(true);
+
+        // the first frame is blended with itself
+        frame.update(&screen(0), 1, &COLORS);
+        assert_eq!(&frame.rgba()[..4], &COLORS[0]);
+
+        frame.update(&screen(2), 2, &COLORS);
+        assert_eq!(&frame.rgba()[..4], &[0x9F, 0x8F, 0x87, 0xFF]);
+
+        // updating the same frame again keeps blending with the one before it
+        frame.update(&screen(3), 2, &COLORS);
+        assert_eq!(&frame.rgba()[..4], &[0x7F, 0x7F, 0x7F, 0xFF]);
+    }
+
+    #[test]
+    fn copy_to_wider_rows() {
+        let mut frame = FrameBuffer::new();
+        frame.update(&screen(2), 1, &COLORS);
+
+        let stride = FRAME_STRIDE + 8;
+        let mut target = vec![0x55; stride * SCREEN_HEIGHT];
+        frame.copy_to(&mut target, stride).unwrap();
+        for row in target.chunks_exact(stride) {
+            assert!(row[..FRAME_STRIDE]
+                .chunks_exact(4)
+                .all(|pixel| pixel == COLORS[2]));
+            assert!(row[FRAME_STRIDE..].iter().all(|byte| *byte == 0x55));
+        }
+
+        assert!(frame.copy_to(&mut target, FRAME_STRIDE - 4).is_err());
+    }
+}
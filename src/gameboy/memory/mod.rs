@@ -2,6 +2,7 @@ pub mod registers;
 use std::ops::Deref;
 
 use super::rom::*;
+use super::scheduler::Event;
 
 /// Trait for memory components of the gameboy.
 pub trait GameboyMemory {
@@ -10,7 +11,16 @@ pub trait GameboyMemory {
 }
 
 pub trait Vram: GameboyMemory {
+    /// The whole VRAM, with bank 1 (if any) right after bank 0.
     fn as_slice(&self) -> &[u8];
+
+    /// The bank mapped at 0x8000..=0x9FFF.
+    fn bank(&self) -> u8 {
+        0
+    }
+
+    /// Selects the bank mapped at 0x8000..=0x9FFF. Ignored if there's only one.
+    fn set_bank(&mut self, _bank: u8) {}
 }
 
 pub struct DMGVram {
@@ -41,26 +51,30 @@ impl Vram for DMGVram {
     }
 }
 
-// TODO: fix implementation with banking
+/// Size of a VRAM bank.
+pub const VRAM_BANK_SIZE: usize = 8 * bytesize::KIB as usize;
+
 pub struct CGBVram {
-    data: Box<[u8; 16 * bytesize::KIB as usize]>,
+    data: Box<[u8; 2 * VRAM_BANK_SIZE]>,
+    bank: u8,
 }
 
 impl Default for CGBVram {
     fn default() -> Self {
         Self {
             data: crate::util::boxed_array_copy(0xFF),
+            bank: 0,
         }
     }
 }
 
 impl GameboyMemory for CGBVram {
     fn read(&self, address: u16) -> u8 {
-        self.data[address as usize]
+        self.data[self.bank as usize * VRAM_BANK_SIZE + address as usize]
     }
 
     fn write(&mut self, address: u16, data: u8) {
-        self.data[address as usize] = data;
+        self.data[self.bank as usize * VRAM_BANK_SIZE + address as usize] = data;
     }
 }
 
@@ -68,6 +82,14 @@ impl Vram for CGBVram {
     fn as_slice(&self) -> &[u8] {
         &self.data[..]
     }
+
+    fn bank(&self) -> u8 {
+        self.bank
+    }
+
+    fn set_bank(&mut self, bank: u8) {
+        self.bank = bank & 0x01;
+    }
 }
 
 pub struct DMGWram {
@@ -195,6 +217,51 @@ impl GameboyMemory for Hram {
     }
 }
 
+/// Size of the DMG boot rom, mapped at 0x0000..=0x00FF.
+pub const DMG_BOOT_SIZE: usize = 0x100;
+/// Size of the CGB boot rom, mapped at 0x0000..=0x00FF and 0x0200..=0x08FF.
+pub const CGB_BOOT_SIZE: usize = 0x900;
+
+/// Bits of the sound registers (0xFF10..=0xFF2F) that always read as 1, regardless of what was written. Unused
+/// addresses read as 0xFF.
+const APU_READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // unused, NR21-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // unused, NR41-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, // unused
+];
+
+/// How reads from the unusable region (0xFEA0..=0xFEFF) behave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnusableRegionMode {
+    /// Reads always return 0xFF.
+    #[default]
+    Fixed,
+    /// Reads return what the emulated model returns. On DMG that's 0x00, or 0xFF while the PPU has OAM locked
+    /// (modes 2 and 3). On CGB it's the upper nibble of the lower address byte, repeated (e.g. 0xFEB4 reads 0xBB).
+    Accurate,
+}
+
+/// An OAM DMA transfer in progress.
+#[derive(Debug, Clone, Copy)]
+struct OamDma {
+    source: u16,
+    /// How many bytes were already copied.
+    copied: u8,
+    /// Machine cycles left before the first byte is copied.
+    delay: u8,
+    /// The last byte copied, which is what the CPU sees when reading from the bus the transfer is using.
+    last: u8,
+}
+
+/// How many bytes an OAM DMA transfer copies, one per machine cycle.
+const OAM_DMA_LEN: u8 = 0xA0;
+
+/// Size of the CGB background color RAM: 8 palettes of 4 colors, each a little endian BGR555 word.
+pub const BG_PALETTE_RAM_SIZE: usize = 64;
+
 /// A Gameboy memory component.
 pub struct Memory {
     boot_mode: bool,
@@ -205,14 +272,27 @@ pub struct Memory {
     oam: Oam,
     io_registers: IORegisters,
     hram: Hram,
+    div_reset: bool,
+    apu_writes: u32,
+    serial_transfer: bool,
+    /// Components to advance before the next machine cycle, as a bitmask indexed by [Event].
+    sync_requests: u8,
+    /// The wave RAM byte channel 3 is playing, if it's on.
+    wave_ram_playback: Option<u8>,
+    /// Whether channel 3 read from wave RAM during the last machine cycle.
+    wave_ram_fetched: bool,
+    unusable_region_mode: UnusableRegionMode,
+    dma: Option<OamDma>,
+    bg_palette_ram: [u8; BG_PALETTE_RAM_SIZE],
 }
 
 impl Memory {
+    /// Creates a new memory component. If `boot` is empty, boot mode starts disabled.
     pub fn new(rom: Rom, boot: Box<[u8]>) -> Self {
         // here we already have the rom, so we can already decide if we should use CGB mode or not (and etc)!
         match rom.header().cgb {
             RomCgbStatus::CGBOnly | RomCgbStatus::CGBSupport => Self {
-                boot_mode: true,
+                boot_mode: !boot.is_empty(),
                 boot,
                 rom,
                 vram: Box::<CGBVram>::default(),
@@ -220,9 +300,18 @@ impl Memory {
                 oam: Oam::default(),
                 io_registers: IORegisters::default(),
                 hram: Hram::default(),
+                div_reset: false,
+                apu_writes: 0,
+                serial_transfer: false,
+                sync_requests: 0,
+                wave_ram_playback: None,
+                wave_ram_fetched: false,
+                unusable_region_mode: UnusableRegionMode::default(),
+                dma: None,
+                bg_palette_ram: [0xFF; BG_PALETTE_RAM_SIZE],
             },
             RomCgbStatus::NoCGB => Self {
-                boot_mode: true,
+                boot_mode: !boot.is_empty(),
                 boot,
                 rom,
                 vram: Box::<DMGVram>::default(),
@@ -230,17 +319,62 @@ impl Memory {
                 oam: Oam::default(),
                 io_registers: IORegisters::default(),
                 hram: Hram::default(),
+                div_reset: false,
+                apu_writes: 0,
+                serial_transfer: false,
+                sync_requests: 0,
+                wave_ram_playback: None,
+                wave_ram_fetched: false,
+                unusable_region_mode: UnusableRegionMode::default(),
+                dma: None,
+                bg_palette_ram: [0xFF; BG_PALETTE_RAM_SIZE],
             },
         }
     }
 
+    /// Puts memory back in its power-on state, re-enabling boot mode if there's a boot rom. Battery-backed
+    /// external ram is kept, everything else is cleared.
+    pub fn reset(&mut self) {
+        self.boot_mode = !self.boot.is_empty();
+        match self.rom.header().cgb {
+            RomCgbStatus::CGBOnly | RomCgbStatus::CGBSupport => {
+                self.vram = Box::<CGBVram>::default();
+                self.wram = Box::<CGBWram>::default();
+            }
+            RomCgbStatus::NoCGB => {
+                self.vram = Box::<DMGVram>::default();
+                self.wram = Box::<DMGWram>::default();
+            }
+        }
+        self.oam = Oam::default();
+        self.io_registers = IORegisters::default();
+        self.hram = Hram::default();
+        self.div_reset = false;
+        self.apu_writes = 0;
+        self.serial_transfer = false;
+        self.sync_requests = 0;
+        self.wave_ram_playback = None;
+        self.wave_ram_fetched = false;
+        self.dma = None;
+        self.bg_palette_ram = [0xFF; BG_PALETTE_RAM_SIZE];
+
+        let battery = self.rom.header().rom_type.has_battery();
+        self.rom.reset(!battery);
+    }
+
     /// Reads a value from memory.
     ///
-    /// Adresses 0xFEA0..=0xFEFF always return 0xFF.
+    /// While boot mode is on, 0x0000..=0x00FF (and 0x0200..=0x08FF with a CGB boot rom) read from the boot rom
+    /// instead of the cartridge. Adresses 0xE000..=0xFDFF mirror 0xC000..=0xDDFF, and 0xFEA0..=0xFEFF return 0xFF
+    /// unless [UnusableRegionMode::Accurate] is set. The unused bit 7 of STAT always reads as 1, as do the unused
+    /// and write-only bits of the sound registers.
     #[inline]
     pub fn read(&self, address: u16) -> u8 {
-        // TODO: this boot mode behaviour doesn't take CGB into consideration
-        if self.boot_mode && address <= 0xFF {
+        // the CGB boot rom leaves the cartridge header at 0x0100..=0x01FF visible
+        if self.boot_mode
+            && (address <= 0xFF
+                || ((0x0200..0x0900).contains(&address) && self.boot.len() == CGB_BOOT_SIZE))
+        {
             return self.boot[address as usize];
         }
 
@@ -253,15 +387,126 @@ impl Memory {
             0xD000..=0xDFFF => self.wram.read(address - 0xC000), // wram | in cgb, switchable bank 1-7
             0xE000..=0xFDFF => self.wram.read(address - 0xE000), // echo ram, mirror of C000~DDFF
             0xFE00..=0xFE9F => self.oam.read(address - 0xFE00),  // sprite attribute table (oam)
-            0xFEA0..=0xFEFF => 0xFF,                             // unused
+            0xFEA0..=0xFEFF => self.read_unusable(address),      // unused
+            registers::addresses::STAT => self.io_registers.read(address - 0xFF00) | 0x80, // bit 7 always reads 1
+            registers::addresses::VBK if self.cgb_mode() => self.vram.bank() | 0xFE, // only bit 0 is used
+            registers::addresses::BCPS if self.cgb_mode() => {
+                self.io_registers.read(address - 0xFF00) | 0x40
+            } // bit 6 always reads 1
+            registers::addresses::BCPD if self.cgb_mode() => {
+                self.bg_palette_ram[self.bg_palette_index()]
+            }
+            // sound registers, whose unused and write-only bits read as 1
+            0xFF10..=0xFF2F => {
+                self.io_registers.read(address - 0xFF00) | APU_READ_MASKS[address as usize - 0xFF10]
+            }
+            0xFF30..=0xFF3F => self.read_wave_ram(address), // wave ram
             0xFF00..=0xFF7F => self.io_registers.read(address - 0xFF00), // I/O registers
             0xFF80..=0xFFFF => self.hram.read(address - 0xFF80), // high ram (hram)
         }
     }
 
+    /// While channel 3 plays, wave RAM reads return the byte it's reading instead. On DMG that only works in the
+    /// same cycle the channel reads it, other reads return 0xFF.
+    fn read_wave_ram(&self, address: u16) -> u8 {
+        match self.wave_ram_playback {
+            Some(index) if self.cgb_mode() || self.wave_ram_fetched => self
+                .io_registers
+                .read(registers::addresses::WAVE_RAM - 0xFF00 + index as u16),
+            Some(_) => 0xFF,
+            None => self.io_registers.read(address - 0xFF00),
+        }
+    }
+
+    fn read_unusable(&self, address: u16) -> u8 {
+        match self.unusable_region_mode {
+            UnusableRegionMode::Fixed => 0xFF,
+            UnusableRegionMode::Accurate if self.cgb_mode() => {
+                let nibble = (address as u8) >> 4;
+                (nibble << 4) | nibble
+            }
+            UnusableRegionMode::Accurate => {
+                // oam is locked during oam scan and drawing
+                let mode = self.io_registers.read(registers::addresses::STAT - 0xFF00) & 0b11;
+                if mode >= 2 {
+                    0xFF
+                } else {
+                    0x00
+                }
+            }
+        }
+    }
+
+    /// Reads a byte for OAM DMA. DMA goes through the normal read path, except that sources from 0xE000 and up
+    /// always reach WRAM, since the DMA unit sees echo ram over the whole range.
+    #[inline]
+    fn dma_read(&self, address: u16) -> u8 {
+        match address {
+            0xE000..=0xFFFF => self.read(address - 0x2000),
+            _ => self.read(address),
+        }
+    }
+
+    /// Advances the OAM DMA transfer in progress, if any, by one machine cycle. After a machine cycle of setup, one
+    /// byte is copied per machine cycle.
+    pub fn advance_dma(&mut self) {
+        if let Some(mut dma) = self.dma {
+            if dma.delay > 0 {
+                dma.delay -= 1;
+            } else {
+                let byte = self.dma_read(dma.source + dma.copied as u16);
+                self.oam.write(dma.copied as u16, byte);
+                dma.last = byte;
+                dma.copied += 1;
+            }
+
+            self.dma = (dma.copied < OAM_DMA_LEN).then_some(dma);
+        }
+    }
+
+    /// Whether an OAM DMA transfer is copying bytes.
+    pub fn dma_active(&self) -> bool {
+        self.dma.is_some_and(|dma| dma.delay == 0)
+    }
+
+    /// If a CPU access to `address` conflicts with the OAM DMA transfer in progress, returns what a read sees
+    /// instead: 0xFF for OAM and the byte being copied for the bus the transfer reads from. Writes in conflict are
+    /// ignored. Only hram, the IO registers and the other bus stay accessible.
+    pub fn dma_conflict(&self, address: u16) -> Option<u8> {
+        let dma = self.dma.filter(|dma| dma.delay == 0)?;
+        let vram_bus = |address: u16| (0x8000..=0x9FFF).contains(&address);
+        match address {
+            0xFE00..=0xFEFF => Some(0xFF),
+            0xFF00..=0xFFFF => None,
+            _ if vram_bus(address) == vram_bus(dma.source) => Some(dma.last),
+            _ => None,
+        }
+    }
+
+    /// Index of the background color RAM byte BCPD accesses, as selected by BCPS.
+    #[inline]
+    fn bg_palette_index(&self) -> usize {
+        (self.io_registers.read(registers::addresses::BCPS - 0xFF00) & 0x3F) as usize
+    }
+
+    /// Writes to the background color RAM through BCPD, moving BCPS to the next byte if its auto increment bit
+    /// is set.
+    fn write_bg_palette_data(&mut self, data: u8) {
+        self.bg_palette_ram[self.bg_palette_index()] = data;
+
+        let bcps = self.io_registers.read(registers::addresses::BCPS - 0xFF00);
+        if bcps & 0x80 != 0 {
+            let next = (bcps & 0xC0) | (bcps.wrapping_add(1) & 0x3F);
+            self.io_registers
+                .write(registers::addresses::BCPS - 0xFF00, next);
+        }
+    }
+
     /// Writes a value to memory.
     ///
-    /// Writes to adresses 0xFEA0..=0xFEFF have no effect and writing any value to 0xFF50 while boot mode is on turns it off.
+    /// Writes to adresses 0xFEA0..=0xFEFF have no effect and writing any value to 0xFF50 while boot mode is on turns it
+    /// off for good, so later writes to 0xFF50 can't turn it back on. Writes to the rom area only reach the MBC. In
+    /// CGB mode, VBK switches the VRAM bank and BCPD writes to the background color RAM.
     #[inline]
     pub fn write(&mut self, address: u16, data: u8) {
         if self.boot_mode && address == 0xFF50 {
@@ -270,10 +515,16 @@ impl Memory {
         }
 
         if address == registers::addresses::DMA {
-            let source = ((data as u16) << 8)..=(((data as u16) << 8) | 0x9F);
-            for (oam_index, source_index) in source.enumerate() {
-                self.oam.write(oam_index as u16, self.read(source_index));
-            }
+            self.dma = Some(OamDma {
+                source: (data as u16) << 8,
+                copied: 0,
+                delay: 1,
+                last: 0xFF,
+            });
+        }
+
+        if address == registers::addresses::VBK && self.cgb_mode() {
+            self.vram.set_bank(data);
         }
 
         match address {
@@ -286,8 +537,9 @@ impl Memory {
             0xE000..=0xFDFF => self.wram.write(address - 0xE000, data), // echo ram, mirror of C000~DDFF
             0xFE00..=0xFE9F => self.oam.write(address - 0xFE00, data), // sprite attribute table (oam)
             0xFEA0..=0xFEFF => (),                                     // unused
+            registers::addresses::BCPD if self.cgb_mode() => self.write_bg_palette_data(data),
             0xFF00..=0xFF7F => self.io_registers.write(address - 0xFF00, data), // I/O registers
-            0xFF80..=0xFFFF => self.hram.write(address - 0xFF80, data), // high ram (hram)
+            0xFF80..=0xFFFF => self.hram.write(address - 0xFF80, data),         // high ram (hram)
         }
     }
 
@@ -301,11 +553,107 @@ impl Memory {
         );
     }
 
+    /// Resets DIV, as done by any write to it. The timer picks the reset up to reset its internal counter.
+    pub fn reset_div(&mut self) {
+        self.write(registers::addresses::DIV, 0x00);
+        self.div_reset = true;
+        self.request_sync(Event::Timer);
+    }
+
+    /// Whether DIV was reset since the last call.
+    pub fn take_div_reset(&mut self) -> bool {
+        std::mem::take(&mut self.div_reset)
+    }
+
+    /// Writes to a sound register (NR10..=NR52), recording the write so the APU can react to it. While the APU is
+    /// off, only NR52 and the length fields of NRx1 can be written.
+    pub fn write_apu_register(&mut self, address: u16, data: u8) {
+        use registers::addresses::*;
+
+        let powered = self.read_apu_register(NR52) & 0b1000_0000 != 0;
+        let data = match address {
+            _ if powered => data,
+            NR52 | NR31 => data,
+            NR11 | NR21 | NR41 => data & 0b0011_1111,
+            _ => return,
+        };
+
+        self.write(address, data);
+        self.apu_writes |= 1 << (address - registers::addresses::NR10);
+    }
+
+    /// Reads a sound register or wave RAM as it was written, bypassing the bits that always read as 1 through
+    /// [Memory::read] and the wave RAM access restrictions.
+    pub fn read_apu_register(&self, address: u16) -> u8 {
+        self.io_registers.read(address - 0xFF00)
+    }
+
+    /// Which sound registers were written since the last call, as a bitmask indexed by `address - NR10`.
+    pub fn take_apu_writes(&mut self) -> u32 {
+        std::mem::take(&mut self.apu_writes)
+    }
+
+    /// Tells which wave RAM byte channel 3 is playing (`None` if it's off) and whether it was read in the last
+    /// machine cycle, for the access restrictions of [Memory::read].
+    pub fn set_wave_ram_playback(&mut self, index: Option<u8>, fetched: bool) {
+        self.wave_ram_playback = index;
+        self.wave_ram_fetched = fetched;
+    }
+
+    /// Writes to SC. Setting bit 7 starts a transfer, which the serial port picks up.
+    pub fn write_serial_control(&mut self, data: u8) {
+        self.write(registers::addresses::SC, data);
+        self.serial_transfer |= data & 0b1000_0000 != 0;
+        self.request_sync(Event::Serial);
+    }
+
+    /// Whether a serial transfer was started since the last call.
+    pub fn take_serial_transfer(&mut self) -> bool {
+        std::mem::take(&mut self.serial_transfer)
+    }
+
+    /// Asks for a component to be advanced before the next machine cycle, since a register write might have moved its
+    /// next event.
+    pub fn request_sync(&mut self, event: Event) {
+        self.sync_requests |= 1 << event as u8;
+    }
+
+    /// Which components were asked to be advanced since the last call, as a bitmask indexed by [Event].
+    pub fn take_sync_requests(&mut self) -> u8 {
+        std::mem::take(&mut self.sync_requests)
+    }
+
+    /// How reads from the unusable region (0xFEA0..=0xFEFF) behave.
+    pub fn unusable_region_mode(&self) -> UnusableRegionMode {
+        self.unusable_region_mode
+    }
+
+    /// Sets how reads from the unusable region (0xFEA0..=0xFEFF) behave. Writes are always ignored.
+    pub fn set_unusable_region_mode(&mut self, mode: UnusableRegionMode) {
+        self.unusable_region_mode = mode;
+    }
+
     /// Whether boot mode is active or not.
     pub fn boot_mode(&self) -> bool {
         self.boot_mode
     }
 
+    /// Turns boot mode on or off. It can only be turned on if there's a boot rom.
+    pub fn set_boot_mode(&mut self, enabled: bool) {
+        self.boot_mode = enabled && !self.boot.is_empty();
+    }
+
+    /// Replaces the boot rom, turning boot mode on if it's not empty.
+    pub fn set_boot(&mut self, boot: Box<[u8]>) {
+        self.boot_mode = !boot.is_empty();
+        self.boot = boot;
+    }
+
+    /// Whether the gameboy is running as a CGB, which is the case when a CGB boot rom is used.
+    pub fn cgb_mode(&self) -> bool {
+        self.boot.len() == CGB_BOOT_SIZE
+    }
+
     pub fn oam(&self) -> &Oam {
         &self.oam
     }
@@ -314,7 +662,174 @@ impl Memory {
         &*self.vram
     }
 
+    /// The CGB background color RAM.
+    pub fn bg_palette_ram(&self) -> &[u8; BG_PALETTE_RAM_SIZE] {
+        &self.bg_palette_ram
+    }
+
+    /// The BGR555 color at `index` (0 to 3) of the given background palette (0 to 7).
+    pub fn bg_color(&self, palette: u8, index: u8) -> u16 {
+        let offset = ((palette & 0x07) as usize * 4 + (index & 0x03) as usize) * 2;
+        u16::from_le_bytes([self.bg_palette_ram[offset], self.bg_palette_ram[offset + 1]])
+    }
+
+    pub fn rom(&self) -> &Rom {
+        &self.rom
+    }
+
+    pub fn rom_mut(&mut self) -> &mut Rom {
+        &mut self.rom
+    }
+
     pub fn rom_header(&self) -> &RomHeader {
         self.rom.header()
     }
+
+    /// Whether the cartridge's rumble motor is on.
+    pub fn rumble(&self) -> bool {
+        self.rom.rumble()
+    }
+
+    /// The external (cartridge) ram, if any.
+    pub fn external_ram(&self) -> &[u8] {
+        self.rom.external_ram()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates the memory of an MBC-less cartridge holding `program`, without a boot rom.
+    fn memory(program: &[u8]) -> Memory {
+        Memory::new(Rom::from_bytes_unchecked(program).unwrap(), Box::new([]))
+    }
+
+    #[test]
+    fn stat_bit_7_always_reads_set() {
+        let mut memory = memory(&[]);
+        for data in [0x00, 0x05, 0x7F] {
+            memory.write(registers::addresses::STAT, data);
+            assert_eq!(memory.read(registers::addresses::STAT), data | 0x80);
+        }
+    }
+
+    #[test]
+    fn dma_from_wram_and_echo_ram() {
+        // (DMA source, where the bytes actually come from)
+        for (source, base) in [(0xC1, 0xC100), (0xE1, 0xC100), (0xFE, 0xDE00)] {
+            let mut memory = memory(&[]);
+            for offset in 0..0xA0u16 {
+                memory.write(base + offset, (offset as u8).wrapping_mul(3) ^ source);
+            }
+
+            memory.write(registers::addresses::DMA, source);
+            for _ in 0..1 + OAM_DMA_LEN {
+                memory.advance_dma();
+            }
+
+            assert!(!memory.dma_active());
+            for offset in 0..0xA0u16 {
+                let expected = (offset as u8).wrapping_mul(3) ^ source;
+                assert_eq!(memory.oam()[offset as usize], expected, "{:02X}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn echo_ram_mirrors_wram() {
+        let mut memory = memory(&[]);
+        memory.write(0xC000, 0x12);
+        memory.write(0xDDFF, 0x34);
+        assert_eq!(memory.read(0xE000), 0x12);
+        assert_eq!(memory.read(0xFDFF), 0x34);
+
+        memory.write(0xE123, 0x56);
+        assert_eq!(memory.read(0xC123), 0x56);
+    }
+
+    #[test]
+    fn unusable_region_reads_ff() {
+        let mut memory = memory(&[]);
+        for address in 0xFEA0..=0xFEFF {
+            memory.write(address, 0x00);
+            assert_eq!(memory.read(address), 0xFF, "{:04X}", address);
+        }
+    }
+
+    #[test]
+    fn rom_writes_without_mbc_are_ignored() {
+        let mut program = vec![0x00; 0x8000];
+        program[0x0000] = 0x11;
+        program[0x4000] = 0x22;
+        let mut memory = memory(&program);
+
+        // these would select a rom bank on a cartridge with an MBC
+        memory.write(0x2000, 0x02);
+        memory.write(0x0000, 0xFF);
+        assert_eq!(memory.read(0x0000), 0x11);
+        assert_eq!(memory.read(0x4000), 0x22);
+    }
+
+    #[test]
+    fn boot_rom_overlays_the_cartridge_until_ff50_is_written() {
+        let program = vec![0xAA; 0x0101];
+        let mut memory = Memory::new(
+            Rom::from_bytes_unchecked(program).unwrap(),
+            vec![0xBB; 0x100].into_boxed_slice(),
+        );
+        assert!(memory.boot_mode());
+        assert_eq!(memory.read(0x0000), 0xBB);
+        assert_eq!(memory.read(0x00FF), 0xBB);
+        assert_eq!(memory.read(0x0100), 0xAA);
+
+        memory.write(0xFF50, 0x01);
+        assert!(!memory.boot_mode());
+        assert_eq!(memory.read(0x0000), 0xAA);
+        assert_eq!(memory.read(0x00FF), 0xAA);
+
+        // boot mode can't be turned back on
+        memory.write(0xFF50, 0x00);
+        assert!(!memory.boot_mode());
+        assert_eq!(memory.read(0x0000), 0xAA);
+    }
+
+    #[test]
+    fn no_boot_rom_starts_with_boot_mode_off() {
+        let memory = memory(&[0xAA; 0x100]);
+        assert!(!memory.boot_mode());
+        assert_eq!(memory.read(0x0000), 0xAA);
+    }
+
+    #[test]
+    fn dma_copies_a_byte_per_machine_cycle() {
+        let mut memory = memory(&[]);
+        for offset in 0..0xA0u16 {
+            memory.write(0xC100 + offset, offset as u8 + 1);
+            memory.write(0xFE00 + offset, 0x00);
+        }
+
+        memory.write(registers::addresses::DMA, 0xC1);
+        memory.advance_dma();
+        for copied in 0..OAM_DMA_LEN {
+            assert!(memory.dma_active());
+            assert_eq!(memory.oam()[copied as usize], 0x00);
+
+            // oam is unreachable, the bus the transfer reads from returns the byte in flight and the rest works
+            let in_flight = if copied == 0 { 0xFF } else { copied };
+            assert_eq!(memory.dma_conflict(0xFE00), Some(0xFF));
+            assert_eq!(memory.dma_conflict(0xC000), Some(in_flight));
+            assert_eq!(memory.dma_conflict(0x4000), Some(in_flight));
+            assert_eq!(memory.dma_conflict(0x8000), None);
+            assert_eq!(memory.dma_conflict(0xFF80), None);
+
+            memory.advance_dma();
+        }
+
+        assert!(!memory.dma_active());
+        assert_eq!(memory.dma_conflict(0xC000), None);
+        for offset in 0..0xA0 {
+            assert_eq!(memory.oam()[offset], offset as u8 + 1);
+        }
+    }
 }
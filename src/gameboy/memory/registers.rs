@@ -17,11 +17,18 @@ pub mod addresses {
     pub const OBP0: u16 = 0xFF48;
     pub const OBP1: u16 = 0xFF49;
     pub const DMA: u16 = 0xFF46;
+    pub const OPRI: u16 = 0xFF6C;
+    pub const KEY1: u16 = 0xFF4D;
+    pub const VBK: u16 = 0xFF4F;
+    pub const BCPS: u16 = 0xFF68;
+    pub const BCPD: u16 = 0xFF69;
     pub const DIV: u16 = 0xFF04;
     pub const TIMA: u16 = 0xFF05;
     pub const TMA: u16 = 0xFF06;
     pub const TAC: u16 = 0xFF07;
     pub const JOYP: u16 = 0xFF00;
+    pub const SB: u16 = 0xFF01;
+    pub const SC: u16 = 0xFF02;
 
     pub const NR10: u16 = 0xFF10;
     pub const NR11: u16 = 0xFF11;
@@ -48,6 +55,7 @@ pub mod addresses {
     pub const NR50: u16 = 0xFF24;
     pub const NR51: u16 = 0xFF25;
     pub const NR52: u16 = 0xFF26;
+    pub const WAVE_RAM: u16 = 0xFF30;
 }
 
 flags! {
@@ -1,19 +1,29 @@
 pub mod apu;
 pub mod cpu;
+pub mod frame;
 pub mod memory;
 pub mod ppu;
 pub mod rom;
+pub mod scheduler;
+pub mod serial;
+pub mod sgb;
+pub mod state;
 pub mod timer;
 
 use std::borrow::Cow;
 
 use apu::*;
 use cpu::*;
+use frame::*;
 use memory::*;
 use ppu::*;
 use rom::*;
+use scheduler::*;
+use serial::*;
+use sgb::*;
 use timer::*;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum JoypadButton {
     Right = 0b0000_0001,
     Left = 0b0000_0010,
@@ -32,7 +42,7 @@ pub struct Joypad {
 
 impl Joypad {
     pub fn new() -> Self {
-        Self { data: 0b1110_0000 }
+        Self { data: 0 }
     }
 
     pub fn set_button(&mut self, button: JoypadButton, value: bool) {
@@ -50,6 +60,169 @@ impl Joypad {
     pub fn directional_buttons(&self) -> u8 {
         self.data & 0x0F
     }
+
+    /// Returns the value JOYP reads as given the select bits in `joyp`: bits 6 and 7 always read 1, bits 4 and 5 are
+    /// the selects and bits 0~3 are low for the held buttons of every selected group.
+    pub fn joyp(&self, joyp: u8) -> u8 {
+        let select = joyp & 0x30;
+        let mut held = 0;
+        if select & 0x10 == 0 {
+            held |= self.directional_buttons();
+        }
+
+        if select & 0x20 == 0 {
+            held |= self.action_buttons();
+        }
+
+        0xC0 | select | (!held & 0x0F)
+    }
+}
+
+/// Frequency of the gameboy clock, in Hz.
+pub const CLOCK_FREQUENCY: u32 = 4_194_304;
+
+/// What the emulator currently supports, so frontends can warn about or hide features that aren't available.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The cartridge MBC types that are emulated.
+    pub mbc_types: &'static [RomMBCType],
+    /// Whether sound is emulated and can be played back.
+    pub audio: bool,
+    /// Whether CGB games are fully supported in CGB mode, objects included.
+    pub cgb: bool,
+    /// Whether CGB games get their background colors and VRAM banking in CGB mode, which needs a CGB boot rom.
+    /// Objects are still drawn in grays unless [Capabilities::cgb] is set too.
+    pub cgb_background: bool,
+    /// Whether super gameboy borders and palettes are supported.
+    pub sgb: bool,
+    /// Whether two emulators can be connected through the link cable. Without it, serial transfers still complete
+    /// but always receive 0xFF.
+    pub link: bool,
+}
+
+/// Returns what this build of the emulator supports.
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        mbc_types: SUPPORTED_MBC_TYPES,
+        audio: cfg!(feature = "audio"),
+        cgb: false,
+        cgb_background: true,
+        sgb: true,
+        link: false,
+    }
+}
+
+/// Details about a single step of the emulation, returned by [Gameboy::step_detailed].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepInfo {
+    /// How many machine cycles have been executed.
+    pub m_cycles: u8,
+    /// Whether the PPU entered VBlank, finishing a frame.
+    pub vblank: bool,
+    /// Whether an interrupt was serviced instead of executing an instruction.
+    pub interrupt_serviced: bool,
+    /// Whether the CPU was halted, executing nothing. This includes STOP mode.
+    pub halted: bool,
+    /// Whether the CPU was in STOP mode. The whole system is paused and only a button press wakes it up.
+    pub stopped: bool,
+    /// The instruction that was executed, if any.
+    pub instruction: Option<ExecutedInstruction>,
+    /// The value of PC before the step.
+    pub pc_before: u16,
+    /// The value of PC after the step.
+    pub pc_after: u16,
+}
+
+/// Formats the CPU state as a line of a gameboy-doctor log, including the 4 bytes at PC. Meant to be used from a
+/// [TraceHook] to log every instruction.
+pub fn doctor_trace_line(registers: &Registers, memory: &Memory) -> String {
+    let pc = registers.get_reg_16(WordRegister::PC);
+    let pc_mem: Vec<_> = (0..4)
+        .map(|offset| format!("{:02X}", memory.read(pc.wrapping_add(offset))))
+        .collect();
+
+    let byte_registers = [
+        ("A", ByteRegister::A),
+        ("F", ByteRegister::F),
+        ("B", ByteRegister::B),
+        ("C", ByteRegister::C),
+        ("D", ByteRegister::D),
+        ("E", ByteRegister::E),
+        ("H", ByteRegister::H),
+        ("L", ByteRegister::L),
+    ];
+
+    let mut line = String::new();
+    for (name, reg) in byte_registers {
+        line += &format!("{}:{:02X} ", name, registers.get_reg_8(reg));
+    }
+
+    line + &format!(
+        "SP:{:04X} PC:{:04X} PCMEM:{}",
+        registers.get_reg_16(WordRegister::SP),
+        pc,
+        pc_mem.join(",")
+    )
+}
+
+/// Updates JOYP with the state of the buttons, requesting the joypad interrupt when any of the input lines goes low.
+/// Returns the new value of JOYP.
+fn update_joypad(
+    memory: &mut Memory,
+    joypad: &Joypad,
+    sgb: &mut Option<Sgb>,
+    screen: &ScreenBuffer,
+) -> u8 {
+    let raw_joyp = memory.read(registers::addresses::JOYP);
+    if let Some(sgb) = sgb {
+        sgb.update_joyp(memory, screen, raw_joyp);
+    }
+
+    let joyp = match sgb.as_ref().and_then(|sgb| sgb.joypad_id(raw_joyp)) {
+        Some(id) => 0xC0 | (raw_joyp & 0x30) | (!id & 0x0F),
+        None => joypad.joyp(raw_joyp),
+    };
+
+    memory.write(registers::addresses::JOYP, joyp);
+    if raw_joyp & !joyp & 0x0F != 0 {
+        memory.request_interrupt(registers::Interrupt::Joypad);
+    }
+
+    joyp
+}
+
+/// Advances the component behind `event` by the given amount of clock cycles, returning how many clock cycles are
+/// left until its next event. Meant to be used as the event handler of the [Scheduler].
+fn advance_component(
+    event: Event,
+    cycles: u32,
+    memory: &mut Memory,
+    ppu: &mut Ppu,
+    timer: &mut Timer,
+    serial: &mut Serial,
+) -> Option<u32> {
+    match event {
+        Event::Ppu => {
+            // events are never more than a scanline apart
+            ppu.advance(memory, cycles as u16);
+            Some(ppu.cycles_to_next_event())
+        }
+        Event::Timer => {
+            timer.advance(memory, cycles);
+            Some(timer.cycles_to_next_event())
+        }
+        Event::Serial => {
+            serial.advance(memory, cycles);
+            serial.cycles_to_next_event()
+        }
+    }
+}
+
+/// An input scheduled through [Gameboy::queue_input].
+struct QueuedInput {
+    frame: u64,
+    button: JoypadButton,
+    pressed: bool,
 }
 
 /// A Gameboy emulator.
@@ -59,82 +232,724 @@ pub struct Gameboy {
     ppu: Ppu,
     apu: Apu,
     timer: Timer,
+    serial: Serial,
+    scheduler: Scheduler,
     joypad: Joypad,
+    sgb: Option<Sgb>,
+    input_queue: Vec<QueuedInput>,
+    post_boot: bool,
+    /// Value the timer's internal counter is set to when the game gains control, if any.
+    initial_div: Option<u16>,
+    cycles_elapsed: u64,
 }
 
 impl Gameboy {
     /// Returns a new gameboy emulator instance with the given rom and bootrom.
+    ///
+    /// The bootrom must either be a DMG one or, for roms that support CGB, a CGB one. Use
+    /// [Gameboy::new_with_boot_fallback] to run without one.
     pub fn new<'a, R, B>(rom: R, boot: B) -> anyhow::Result<Self>
     where
         R: Into<Cow<'a, [u8]>>,
         B: Into<Box<[u8]>>,
     {
         let rom = Rom::try_from_bytes(rom)?;
+        let boot = boot.into();
+        Self::validate_required_boot(rom.header(), &boot)?;
+
+        Ok(Self::from_rom(rom, boot))
+    }
+
+    /// Like [Gameboy::new], but recoverable problems with the rom or bootrom are returned as warnings instead of
+    /// failing. If the bootrom is invalid or empty, the emulator starts in the state the bootrom leaves it in.
+    pub fn new_lenient<'a, R, B>(rom: R, boot: B) -> anyhow::Result<(Self, Vec<String>)>
+    where
+        R: Into<Cow<'a, [u8]>>,
+        B: Into<Box<[u8]>>,
+    {
+        let mut warnings = Vec::new();
+        let rom = Rom::try_from_bytes_lenient(rom, &mut warnings)?;
+        let gameboy = Self::with_boot_fallback(rom, boot.into(), &mut warnings);
+
+        Ok((gameboy, warnings))
+    }
+
+    /// Like [Gameboy::new], but if the bootrom is invalid or empty the emulator starts in the state the bootrom
+    /// leaves it in, with a warning, instead of failing. Unlike [Gameboy::new_lenient], the rom is still strictly
+    /// validated.
+    pub fn new_with_boot_fallback<'a, R, B>(rom: R, boot: B) -> anyhow::Result<(Self, Vec<String>)>
+    where
+        R: Into<Cow<'a, [u8]>>,
+        B: Into<Box<[u8]>>,
+    {
+        let mut warnings = Vec::new();
+        let rom = Rom::try_from_bytes(rom)?;
+        let gameboy = Self::with_boot_fallback(rom, boot.into(), &mut warnings);
+
+        Ok((gameboy, warnings))
+    }
+
+    fn with_boot_fallback(rom: Rom, mut boot: Box<[u8]>, warnings: &mut Vec<String>) -> Self {
+        if let Err(e) = Self::validate_boot(rom.header(), &boot) {
+            warnings.push(format!("{}, running without a boot rom", e));
+            boot = Box::new([]);
+        }
+
+        let mut gameboy = Self::from_rom(rom, boot);
+        if !gameboy.memory.boot_mode() {
+            gameboy.post_boot = true;
+            gameboy.apply_post_boot_state();
+        }
+
+        gameboy
+    }
+
+    /// Like [Gameboy::validate_boot], but an empty boot rom is an error too. Without a boot rom, the game would start
+    /// at 0x0000 with every register cleared instead of in the state the boot rom leaves it in.
+    fn validate_required_boot(header: &RomHeader, boot: &[u8]) -> anyhow::Result<()> {
+        if boot.is_empty() {
+            anyhow::bail!("Missing boot rom");
+        }
+
+        Self::validate_boot(header, boot)
+    }
 
-        let mut memory = Memory::new(rom, boot.into());
-        let cpu = Cpu::new();
+    fn validate_boot(header: &RomHeader, boot: &[u8]) -> anyhow::Result<()> {
+        match (boot.len(), header.cgb) {
+            (0 | DMG_BOOT_SIZE, _) => Ok(()),
+            (CGB_BOOT_SIZE, RomCgbStatus::NoCGB) => {
+                anyhow::bail!("CGB boot rom given for a DMG only rom")
+            }
+            (CGB_BOOT_SIZE, _) => Ok(()),
+            (size, _) => anyhow::bail!("Invalid boot rom size ({} bytes)", size),
+        }
+    }
+
+    fn from_rom(rom: Rom, boot: Box<[u8]>) -> Self {
+        Self::from_parts(Memory::new(rom, boot), Cpu::new())
+    }
+
+    /// Returns a new gameboy emulator instance from an existing [Memory] and [Cpu], which can be in any state. No
+    /// validation is done and the rest of the components start from their initial state.
+    ///
+    /// This is meant for tests and tools that need arbitrary starting conditions, [Gameboy::new] should be
+    /// preferred otherwise.
+    pub fn from_parts(mut memory: Memory, cpu: Cpu) -> Self {
         let ppu = Ppu::new(&mut memory);
         let apu = Apu::new();
         let timer = Timer::new();
+        let serial = Serial::new();
         let joypad = Joypad::new();
 
-        Ok(Self {
+        Self {
             memory,
             cpu,
             ppu,
             apu,
             timer,
+            serial,
+            scheduler: Scheduler::new(),
             joypad,
-        })
+            sgb: None,
+            input_queue: Vec::new(),
+            post_boot: false,
+            initial_div: None,
+            cycles_elapsed: 0,
+        }
+    }
+
+    /// Sets the registers to the values the DMG bootrom leaves them with, starting execution at 0x0100.
+    fn apply_post_boot_state(&mut self) {
+        let registers = self.cpu.registers_mut();
+        registers.set_reg_16(WordRegister::AF, 0x01B0);
+        registers.set_reg_16(WordRegister::BC, 0x0013);
+        registers.set_reg_16(WordRegister::DE, 0x00D8);
+        registers.set_reg_16(WordRegister::HL, 0x014D);
+        registers.set_reg_16(WordRegister::SP, 0xFFFE);
+        registers.set_reg_16(WordRegister::PC, 0x0100);
+
+        self.memory.write(registers::addresses::LCDC, 0x91);
+        self.memory.write(registers::addresses::BGP, 0xFC);
+        self.memory
+            .write(registers::addresses::INTERRUPT_REQUEST, 0xE1);
+        self.memory
+            .write(registers::addresses::INTERRUPT_ENABLE, 0x00);
+    }
+
+    /// Restarts the loaded rom as if the gameboy was power cycled. Boot mode is re-enabled (or the post-boot state
+    /// re-applied, if the emulator started without a boot rom) and volatile ram is cleared, while battery-backed
+    /// external ram is kept. Settings that aren't part of the emulated state, like the debugger's masked interrupts
+    /// and the audio output, survive the reset.
+    pub fn reset(&mut self) {
+        self.memory.reset();
+        self.cpu.reset();
+        self.ppu = Ppu::new(&mut self.memory);
+        // the output settings of the apu aren't part of the emulated state
+        let sample_rate = self.apu.sample_rate();
+        let audible = SoundChannel::ALL.map(|channel| self.apu.channel_enabled(channel));
+        #[cfg(feature = "audio")]
+        let sample_output = self.apu.take_sample_output();
+        self.apu = Apu::new();
+        self.apu.set_sample_rate(sample_rate);
+        #[cfg(feature = "audio")]
+        self.apu.set_sample_output(sample_output);
+        for (channel, on) in SoundChannel::ALL.into_iter().zip(audible) {
+            self.apu.set_channel_enabled(channel, on);
+        }
+        self.timer = Timer::new();
+        self.serial = Serial::new();
+        self.scheduler = Scheduler::new();
+        self.joypad = Joypad::new();
+        if self.sgb.is_some() {
+            self.sgb = Some(Sgb::new());
+        }
+        self.input_queue.clear();
+        self.cycles_elapsed = 0;
+
+        if self.post_boot {
+            self.apply_post_boot_state();
+            self.apply_initial_div();
+        }
+    }
+
+    /// Sets the value of the timer's internal counter (whose upper byte is DIV) for the moment the game gains
+    /// control: right after the boot rom finishes, when starting from the post-boot state and after a reset. Many
+    /// games seed their RNG from DIV, so this makes it possible to reproduce a specific seed. If the game already
+    /// has control, the counter is set right away.
+    pub fn set_initial_div(&mut self, counter: Option<u16>) {
+        self.initial_div = counter;
+        if !self.memory.boot_mode() {
+            self.apply_initial_div();
+        }
+    }
+
+    fn apply_initial_div(&mut self) {
+        if let Some(counter) = self.initial_div {
+            // bring the timer up to date first, then schedule its next event from the new counter
+            self.sync(Event::Timer);
+            self.timer.set_counter(&mut self.memory, counter);
+            self.sync(Event::Timer);
+        }
+    }
+
+    /// Advances a component to the current point in time and schedules its next event again.
+    fn sync(&mut self, event: Event) {
+        let (memory, ppu, timer, serial) = (
+            &mut self.memory,
+            &mut self.ppu,
+            &mut self.timer,
+            &mut self.serial,
+        );
+        self.scheduler.sync(event, &mut |event, cycles| {
+            advance_component(event, cycles, memory, ppu, timer, serial)
+        });
+    }
+
+    /// Turns boot mode on or off. Turning it on restarts the CPU at 0x0000, so the boot rom runs again.
+    pub fn set_boot_mode(&mut self, enabled: bool) {
+        self.memory.set_boot_mode(enabled);
+        if self.memory.boot_mode() {
+            self.cpu.reset();
+        }
+    }
+
+    /// Replaces the boot rom and restarts the CPU at 0x0000 in boot mode. The same validation as in [Gameboy::new]
+    /// is done on the new boot rom.
+    pub fn set_boot<B>(&mut self, boot: B) -> anyhow::Result<()>
+    where
+        B: Into<Box<[u8]>>,
+    {
+        let boot = boot.into();
+        Self::validate_required_boot(self.memory.rom_header(), &boot)?;
+
+        self.memory.set_boot(boot);
+        self.post_boot &= !self.memory.boot_mode();
+        self.cpu.reset();
+        Ok(())
+    }
+
+    /// Returns a new gameboy emulator instance running the given program without a boot rom, skipping any
+    /// validation of the rom. Execution starts at 0x0000 and no MBC is used.
+    ///
+    /// This is meant for small test programs such as [SMOKE_TEST_PROGRAM].
+    pub fn new_from_bytes_no_validation<'a, R>(rom: R) -> anyhow::Result<Self>
+    where
+        R: Into<Cow<'a, [u8]>>,
+    {
+        let rom = Rom::from_bytes_unchecked(rom)?;
+        Ok(Self::from_rom(rom, Box::new([])))
     }
 
     /// Steps the emulation forward by 1 cpu step. Returns how many machine cycles have been executed.
     pub fn step(&mut self) -> u8 {
+        self.step_detailed().m_cycles
+    }
+
+    /// Like [Gameboy::step], but returns details about what happened during the step.
+    pub fn step_detailed(&mut self) -> StepInfo {
+        let pc_before = self.cpu.registers().get_reg_16(WordRegister::PC);
+        let frame_before = self.ppu.frame_count();
+        let boot_before = self.memory.boot_mode();
+
+        if self.cpu.stopped() {
+            return self.step_stopped(pc_before);
+        }
+
         let mut m_cycles: u8 = 0;
-        self.cpu.step(&mut self.memory, &mut |memory: &mut Memory| {
-            // one machine cycle is 4 clock cycles
-            for _ in 0..4 {
-                self.ppu.cycle(memory);
-                self.apu.cycle(memory);
-                self.timer.cycle(memory);
-            }
+        let cpu_step = self.cpu.step(&mut self.memory, &mut |memory: &mut Memory| {
+            memory.advance_dma();
+            self.apu.advance(memory, 4);
 
-            // update joypad register
-            let joyp = !memory.read(registers::addresses::JOYP);
-            let updated = if joyp & (1 << 4) == 0 {
-                self.joypad.action_buttons()
-            } else {
-                self.joypad.directional_buttons()
+            // one machine cycle is 4 clock cycles. the other components are only advanced when one of their events
+            // is due in it, or right away if the last machine cycle wrote to one of their registers
+            let requests = memory.take_sync_requests();
+            let mut handle = |event, cycles| {
+                advance_component(
+                    event,
+                    cycles,
+                    memory,
+                    &mut self.ppu,
+                    &mut self.timer,
+                    &mut self.serial,
+                )
             };
+            self.scheduler.sync_requested(requests, &mut handle);
+            self.scheduler.advance(4, &mut handle);
 
-            memory.write(registers::addresses::JOYP, !((joyp & 0xF0) | updated));
-            if joyp & 0x0F != updated {
-                memory.request_interrupt(registers::Interrupt::Joypad);
-            }
-
+            update_joypad(memory, &self.joypad, &mut self.sgb, self.ppu.screen());
             m_cycles += 1;
         });
 
+        self.apply_queued_inputs();
+        self.cycles_elapsed += m_cycles as u64;
+
+        // the boot rom just handed control over to the game
+        if boot_before && !self.memory.boot_mode() {
+            self.apply_initial_div();
+        }
+
+        StepInfo {
+            m_cycles,
+            vblank: self.ppu.frame_count() != frame_before,
+            interrupt_serviced: cpu_step == CpuStep::Interrupt,
+            halted: cpu_step == CpuStep::Halted,
+            stopped: false,
+            instruction: match cpu_step {
+                CpuStep::Instruction(instruction) => Some(instruction),
+                _ => None,
+            },
+            pc_before,
+            pc_after: self.cpu.registers().get_reg_16(WordRegister::PC),
+        }
+    }
+
+    /// Steps for one machine cycle in STOP mode: the clock is stopped, so only the joypad is alive. Pressing a button
+    /// in a selected group wakes the CPU up.
+    fn step_stopped(&mut self, pc_before: u16) -> StepInfo {
+        let joyp = update_joypad(
+            &mut self.memory,
+            &self.joypad,
+            &mut self.sgb,
+            self.ppu.screen(),
+        );
+        if joyp & 0x0F != 0x0F {
+            self.cpu.leave_stop();
+        }
+
+        self.apply_queued_inputs();
+        self.cycles_elapsed += 1;
+
+        StepInfo {
+            m_cycles: 1,
+            vblank: false,
+            interrupt_serviced: false,
+            halted: true,
+            stopped: true,
+            instruction: None,
+            pc_before,
+            pc_after: pc_before,
+        }
+    }
+
+    /// Steps the emulation until the next frame is finished. While the LCD is off, no frames are drawn, so at most a
+    /// frame's worth of cycles is executed. Returns how many machine cycles have been executed.
+    pub fn run_frame(&mut self) -> u32 {
+        let frame = self.ppu.frame_count();
+        let mut m_cycles = 0;
+        while self.ppu.frame_count() == frame && m_cycles * 4 < FRAME_CYCLES {
+            m_cycles += self.step() as u32;
+        }
+
         m_cycles
     }
 
+    /// Formats the CPU state as a line of a gameboy-doctor log, including the 4 bytes at PC.
+    pub fn doctor_trace_line(&self) -> String {
+        doctor_trace_line(self.cpu.registers(), &self.memory)
+    }
+
+    /// Sets a hook that's called with the CPU state before every instruction. See [Cpu::set_trace_hook].
+    pub fn set_trace_hook(&mut self, hook: TraceHook) {
+        self.cpu.set_trace_hook(hook);
+    }
+
+    /// Removes the trace hook, if any, returning it.
+    pub fn take_trace_hook(&mut self) -> Option<TraceHook> {
+        self.cpu.take_trace_hook()
+    }
+
+    /// Every byte sent through the serial port since the emulator was created or reset.
+    pub fn serial_output(&self) -> &[u8] {
+        self.serial.output()
+    }
+
+    /// Steps the emulation until the serial output, read as text, satisfies `matches`, or until `max_m_cycles`
+    /// machine cycles have been executed. Test roms commonly report through the serial port, so this makes checking
+    /// them a one-liner, e.g. `gameboy.run_until_serial_matches(|log| log.contains("Passed"), 100_000_000)`.
+    ///
+    /// Returns the output either way: `Ok` if it matched and `Err` if the cycle budget ran out first.
+    pub fn run_until_serial_matches<F>(
+        &mut self,
+        mut matches: F,
+        max_m_cycles: u64,
+    ) -> Result<String, String>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        let mut m_cycles = 0;
+        let mut checked_len = None;
+        loop {
+            // only check again when there's new output
+            let output = self.serial.output();
+            if checked_len != Some(output.len()) {
+                checked_len = Some(output.len());
+
+                let text = String::from_utf8_lossy(output);
+                if matches(&text) {
+                    return Ok(text.into_owned());
+                }
+            }
+
+            if m_cycles >= max_m_cycles {
+                return Err(String::from_utf8_lossy(output).into_owned());
+            }
+
+            m_cycles += self.step() as u64;
+        }
+    }
+
+    /// How many machine cycles have been executed since the emulator was created or reset.
+    pub fn cycles_elapsed(&self) -> u64 {
+        self.cycles_elapsed
+    }
+
+    /// How much time has passed for the emulated gameboy, based on [Gameboy::cycles_elapsed].
+    pub fn emulated_time(&self) -> std::time::Duration {
+        // one machine cycle is 4 ticks of the 4.194304MHz clock
+        let nanos = self.cycles_elapsed as u128 * 4 * 1_000_000_000 / CLOCK_FREQUENCY as u128;
+        std::time::Duration::from_nanos(nanos as u64)
+    }
+
+    /// Schedules a button to be pressed or released `frame_offset` frames from now. Queued inputs are applied at
+    /// frame boundaries, so an offset of 0 applies it on the next step and 1 at the start of the next frame.
+    pub fn queue_input(&mut self, frame_offset: u64, button: JoypadButton, pressed: bool) {
+        let frame = self.ppu.frame_count() + frame_offset;
+
+        // keep the queue sorted by frame, preserving the order of inputs in the same frame
+        let index = self
+            .input_queue
+            .partition_point(|input| input.frame <= frame);
+        self.input_queue.insert(
+            index,
+            QueuedInput {
+                frame,
+                button,
+                pressed,
+            },
+        );
+    }
+
+    fn apply_queued_inputs(&mut self) {
+        let frame = self.ppu.frame_count();
+        let due = self
+            .input_queue
+            .partition_point(|input| input.frame <= frame);
+        for input in self.input_queue.drain(..due) {
+            self.joypad.set_button(input.button, input.pressed);
+        }
+    }
+
+    /// Saves the external ram, and the real-time clock if there's one, to the given path if the cartridge is
+    /// battery-backed. Does nothing otherwise, or if the cartridge has neither.
+    pub fn save_ram<P>(&self, path: P) -> anyhow::Result<()>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        if !self.memory.rom_header().rom_type.has_battery() {
+            return Ok(());
+        }
+
+        self.memory.rom().save_external_ram(path)
+    }
+
+    /// Loads the external ram, and the real-time clock if there's one, from the given path if the cartridge is
+    /// battery-backed. Does nothing otherwise.
+    pub fn load_ram<P>(&mut self, path: P) -> anyhow::Result<()>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        if !self.memory.rom_header().rom_type.has_battery() {
+            return Ok(());
+        }
+
+        self.memory.rom_mut().load_external_ram(path)
+    }
+
+    /// Whether the cartridge's rumble motor is currently on.
+    pub fn rumble_state(&self) -> bool {
+        self.memory.rumble()
+    }
+
+    /// Enables super gameboy support. Fails if the rom doesn't support SGB functions.
+    pub fn enable_sgb(&mut self) -> anyhow::Result<()> {
+        if self.memory.rom_header().sgb != RomSgbStatus::SGBSupport {
+            anyhow::bail!("Rom does not support SGB functions");
+        }
+
+        self.sgb = Some(Sgb::new());
+        Ok(())
+    }
+
+    /// Returns an reference to the [Sgb] instance of this emulator, if super gameboy support is enabled.
+    pub fn sgb(&self) -> Option<&Sgb> {
+        self.sgb.as_ref()
+    }
+
+    /// Returns the current frame with the given colors (indexed by shade) applied.
+    pub fn frame(&self, colors: &[[u8; 4]; 4]) -> FrameBuffer {
+        let mut frame = FrameBuffer::new();
+        self.update_frame(&mut frame, colors);
+        frame
+    }
+
+    /// Updates the given [FrameBuffer] with the current frame and the given colors (indexed by shade) applied.
+    pub fn update_frame(&self, frame: &mut FrameBuffer, colors: &[[u8; 4]; 4]) {
+        frame.update(self.ppu.screen(), self.ppu.frame_count(), colors);
+    }
+
     /// Returns an reference to the [Cpu] instance of this emulator.
     pub fn cpu(&self) -> &Cpu {
         &self.cpu
     }
 
+    /// Returns a mutable reference to the [Cpu] instance of this emulator.
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
     /// Returns an reference to the [Ppu] instance of this emulator.
     pub fn ppu(&self) -> &Ppu {
         &self.ppu
     }
 
+    /// Returns a mutable reference to the [Ppu] instance of this emulator.
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+
+    /// Returns an reference to the [Apu] instance of this emulator.
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    /// Returns a mutable reference to the [Apu] instance of this emulator.
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
     /// Returns an reference to the [Memory] instance of this emulator.
     pub fn memory(&self) -> &Memory {
         &self.memory
     }
 
+    /// Returns a mutable reference to the [Memory] instance of this emulator, for tools that need to poke memory.
+    ///
+    /// Writes go through [Memory::write]: writes to the rom area reach the MBC, but I/O registers are written
+    /// directly, without the side effects a CPU write has (e.g. writing DIV doesn't reset the timer).
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
     /// Returns an reference to the [Joypad] instance of this emulator.
     pub fn joypad_mut(&mut self) -> &mut Joypad {
         &mut self.joypad
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smoke_test_program_fills_the_screen() {
+        let mut gameboy = Gameboy::new_from_bytes_no_validation(&SMOKE_TEST_PROGRAM[..]).unwrap();
+        for _ in 0..3 {
+            gameboy.run_frame();
+        }
+
+        assert_eq!(
+            gameboy.memory().read(memory::registers::addresses::LCDC),
+            0x91
+        );
+        let screen = gameboy.ppu().screen();
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                assert_eq!(screen.get_pixel(x, y).unwrap(), 3, "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    /// Runs `program` from address 0 with every interrupt flag cleared, until `steps` steps are done.
+    fn run_program(program: &[u8], steps: usize) -> Gameboy {
+        let memory = Memory::new(Rom::from_bytes_unchecked(program).unwrap(), Box::new([]));
+        let mut gameboy = Gameboy::from_parts(memory, Cpu::new());
+        gameboy
+            .cpu_mut()
+            .registers_mut()
+            .set_reg_16(WordRegister::PC, 0x0000);
+        gameboy
+            .memory_mut()
+            .write(registers::addresses::INTERRUPT_REQUEST, 0x00);
+
+        for _ in 0..steps {
+            gameboy.step();
+        }
+
+        gameboy
+    }
+
+    #[test]
+    fn lyc_write_requests_stat_interrupt_right_away() {
+        // LD A, 0x40; LDH (STAT), A; XOR A; LDH (LYC), A; NOP
+        let program = [0x3E, 0x40, 0xE0, 0x41, 0xAF, 0xE0, 0x45, 0x00];
+        let mut gameboy = run_program(&program, 4);
+        let requested = gameboy
+            .memory()
+            .read(registers::addresses::INTERRUPT_REQUEST);
+        assert_eq!(requested & 0b10, 0);
+
+        // the PPU's next event is still far away, but the write is picked up in the next machine cycle
+        gameboy.step();
+        let requested = gameboy
+            .memory()
+            .read(registers::addresses::INTERRUPT_REQUEST);
+        assert_eq!(requested & 0b10, 0b10);
+    }
+
+    #[test]
+    fn tac_write_applies_from_the_next_machine_cycle() {
+        // XOR A; LDH (TIMA), A; LD A, 0x05; LDH (TAC), A; NOP x4
+        let program = [0xAF, 0xE0, 0x05, 0x3E, 0x05, 0xE0, 0x07, 0, 0, 0, 0];
+        let mut gameboy = run_program(&program, 4);
+        let tima = gameboy.memory().read(registers::addresses::TIMA);
+
+        // TIMA is now incremented every 16 clock cycles, 4 machine cycles
+        for _ in 0..4 {
+            gameboy.step();
+        }
+        assert_eq!(gameboy.memory().read(registers::addresses::TIMA), tima + 1);
+    }
+
+    #[test]
+    fn stop_skips_its_operand_and_resets_div() {
+        // NOP x70; STOP 0x00; LD A, 0x42
+        let mut program = vec![0x00; 70];
+        program.extend([0x10, 0x00, 0x3E, 0x42]);
+        let mut gameboy = run_program(&program, 70);
+        assert_ne!(gameboy.memory().read(registers::addresses::DIV), 0);
+
+        gameboy.step();
+        assert!(gameboy.cpu().stopped());
+        assert_eq!(gameboy.cpu().registers().get_reg_16(WordRegister::PC), 72);
+        assert_eq!(gameboy.memory().read(registers::addresses::DIV), 0);
+
+        // the clock is stopped until a button in a selected group is pressed
+        for _ in 0..1000 {
+            assert!(gameboy.step_detailed().stopped);
+        }
+        assert_eq!(gameboy.memory().read(registers::addresses::DIV), 0);
+
+        gameboy.memory_mut().write(registers::addresses::JOYP, 0x10);
+        gameboy.joypad_mut().set_button(JoypadButton::Start, true);
+        gameboy.step();
+        assert!(!gameboy.cpu().stopped());
+
+        gameboy.step();
+        assert_eq!(gameboy.cpu().registers().get_reg_8(ByteRegister::A), 0x42);
+    }
+
+    #[test]
+    fn joyp_reads_for_each_select() {
+        // (written value, value read with Right and B held)
+        for (select, expected) in [(0x30, 0xFF), (0x20, 0xEE), (0x10, 0xDD), (0x00, 0xCC)] {
+            // LD A, select; LDH (JOYP), A; LDH A, (JOYP)
+            let program = [0x3E, select, 0xE0, 0x00, 0xF0, 0x00];
+            let mut gameboy = run_program(&program, 0);
+            gameboy.joypad_mut().set_button(JoypadButton::Right, true);
+            gameboy.joypad_mut().set_button(JoypadButton::B, true);
+            for _ in 0..3 {
+                gameboy.step();
+            }
+
+            let joyp = gameboy.cpu().registers().get_reg_8(ByteRegister::A);
+            assert_eq!(joyp, expected, "select {:02X}", select);
+        }
+    }
+
+    #[test]
+    fn cpu_reads_during_dma_conflict_with_its_bus() {
+        // the transfer reads from vram, so the program keeps running from the rom on the other bus
+        // LD A, 0x80; LDH (DMA), A; LD A, (0x8050); LD B, A; LDH A, (0x80); LD C, A; LD A, (0xFE00); LD D, A;
+        // LD A, (0xC000); LD E, A
+        let program = [
+            0x3E, 0x80, 0xE0, 0x46, 0xFA, 0x50, 0x80, 0x47, 0xF0, 0x80, 0x4F, 0xFA, 0x00, 0xFE,
+            0x57, 0xFA, 0x00, 0xC0, 0x5F,
+        ];
+        let mut gameboy = run_program(&program, 0);
+        for offset in 0..0xA0u16 {
+            gameboy
+                .memory_mut()
+                .write(0x8000 + offset, offset as u8 + 1);
+        }
+        gameboy.memory_mut().write(0xFF80, 0x42);
+        gameboy.memory_mut().write(0xC000, 0x24);
+
+        for _ in 0..10 {
+            gameboy.step();
+        }
+
+        // vram reads see the byte in flight, the third one by then, oam reads 0xFF and the rest is unaffected
+        let registers = gameboy.cpu().registers();
+        assert_eq!(registers.get_reg_8(ByteRegister::B), 0x03);
+        assert_eq!(registers.get_reg_8(ByteRegister::C), 0x42);
+        assert_eq!(registers.get_reg_8(ByteRegister::D), 0xFF);
+        assert_eq!(registers.get_reg_8(ByteRegister::E), 0x24);
+        assert!(gameboy.memory().dma_active());
+    }
+
+    #[test]
+    fn strict_constructor_requires_a_boot_rom() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x014D] = crate::gameboy::rom::RomHeader::compute_header_checksum(&rom);
+
+        assert!(Gameboy::new(&rom[..], Vec::new()).is_err());
+        assert!(Gameboy::new(&rom[..], vec![0; DMG_BOOT_SIZE]).is_ok());
+
+        // the fallback starts right where the boot rom hands control over to the game
+        let (gameboy, _) = Gameboy::new_with_boot_fallback(&rom[..], Vec::new()).unwrap();
+        assert_eq!(
+            gameboy.cpu().registers().get_reg_16(WordRegister::PC),
+            0x0100
+        );
+    }
+}
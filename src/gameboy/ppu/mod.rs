@@ -1,5 +1,5 @@
 use super::memory::registers as memreg;
-use super::memory::Memory;
+use super::memory::{Memory, VRAM_BANK_SIZE};
 use flagset::{flags, FlagSet};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -10,6 +10,17 @@ pub enum PPUMode {
     Rendering,
 }
 
+impl std::fmt::Display for PPUMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PPUMode::HBlank => write!(f, "Mode 0 (HBlank)"),
+            PPUMode::VBlank => write!(f, "Mode 1 (VBlank)"),
+            PPUMode::OAMSearch => write!(f, "Mode 2 (OAM)"),
+            PPUMode::Rendering => write!(f, "Mode 3 (Rendering)"),
+        }
+    }
+}
+
 impl PPUMode {
     #[inline]
     pub fn into_stat_flag(self) -> memreg::StatFlag {
@@ -88,15 +99,18 @@ struct ObjectAttributes {
     pub y: u8,
     pub tile_index: u8,
     pub flags: FlagSet<ObjectAttributesFlags>,
+    /// Position of the object in OAM, used to break ties between objects.
+    pub oam_index: u8,
 }
 
 impl ObjectAttributes {
-    pub fn new(bytes: [u8; 4]) -> anyhow::Result<Self> {
+    pub fn new(bytes: [u8; 4], oam_index: u8) -> anyhow::Result<Self> {
         Ok(Self {
             x: bytes[1],
             y: bytes[0],
             tile_index: bytes[2],
             flags: FlagSet::new(bytes[3]).map_err(|_| anyhow::anyhow!("Invalid bits"))?,
+            oam_index,
         })
     }
 
@@ -132,6 +146,7 @@ impl ObjectAttributes {
         }
     }
 
+    #[allow(dead_code)] // the object color ram isn't emulated yet, see CGB_OBJECT_GRAYS
     pub fn cgb_palette(&self) -> u8 {
         (self.flags & ObjectAttributesFlags::CgbPaletteBits).bits()
     }
@@ -151,20 +166,26 @@ impl Palette {
         Self::from(data)
     }
 
+    /// Returns the shade (0 to 3) the given color index (0 to 3) is mapped to. Only the lower 2 bits of the
+    /// index are considered.
+    pub fn shade(&self, color_index: u8) -> u8 {
+        (self.data >> ((color_index & 0b11) * 2)) & 0b11
+    }
+
     pub fn color_0(&self) -> u8 {
-        self.data & 0b0000_0011
+        self.shade(0)
     }
 
     pub fn color_1(&self) -> u8 {
-        (self.data & 0b0000_1100) >> 2
+        self.shade(1)
     }
 
     pub fn color_2(&self) -> u8 {
-        (self.data & 0b0011_0000) >> 4
+        self.shade(2)
     }
 
     pub fn color_3(&self) -> u8 {
-        (self.data & 0b1100_0000) >> 6
+        self.shade(3)
     }
 }
 
@@ -174,8 +195,13 @@ impl From<u8> for Palette {
     }
 }
 
+#[derive(Default)]
 struct BackgroundPixel {
     pub color_index: u8,
+    /// The CGB palette of the tile, always 0 outside of CGB mode.
+    pub palette: u8,
+    /// Whether the tile has priority over objects, as set by its CGB attributes.
+    pub priority: bool,
 }
 
 struct ObjectPixel {
@@ -184,42 +210,77 @@ struct ObjectPixel {
     pub under_bg_window: bool,
 }
 
+/// Width of the gameboy screen, in pixels.
+pub const SCREEN_WIDTH: usize = 160;
+/// Height of the gameboy screen, in pixels.
+pub const SCREEN_HEIGHT: usize = 144;
+/// How many clock cycles the PPU takes to draw a whole frame.
+pub const FRAME_CYCLES: u32 = 456 * 154;
+
+/// BGR555 grays used for objects in CGB mode, indexed by their DMG shade.
+// TODO: emulate the object color ram, so objects get their actual CGB colors
+const CGB_OBJECT_GRAYS: [u16; 4] = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+
 #[derive(Clone, PartialEq, Eq)]
 pub struct ScreenBuffer {
-    pixels: [u8; 160 * 144],
+    pixels: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    /// The BGR555 color of each pixel, only present when rendering in CGB mode.
+    colors: Option<Box<[u16; SCREEN_WIDTH * SCREEN_HEIGHT]>>,
 }
 
 impl ScreenBuffer {
     pub fn new() -> Self {
         Self {
-            pixels: [0; 160 * 144],
+            pixels: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            colors: None,
         }
     }
 
     pub fn get_pixel(&self, x: usize, y: usize) -> anyhow::Result<u8> {
-        if !((0..160).contains(&x) && (0..144).contains(&y)) {
+        if !((0..SCREEN_WIDTH).contains(&x) && (0..SCREEN_HEIGHT).contains(&y)) {
             anyhow::bail!("Pixel position ({}, {}) out of range", x, y);
         }
 
-        let index = y * 160 + x;
+        let index = y * SCREEN_WIDTH + x;
         Ok(self.pixels[index])
     }
 
     pub fn set_pixel(&mut self, x: usize, y: usize, value: u8) -> anyhow::Result<()> {
-        if !((0..160).contains(&x) && (0..144).contains(&y)) {
+        if !((0..SCREEN_WIDTH).contains(&x) && (0..SCREEN_HEIGHT).contains(&y)) {
             anyhow::bail!("Pixel position ({}, {}) out of range", x, y);
         }
 
-        let index = y * 160 + x;
+        let index = y * SCREEN_WIDTH + x;
         self.pixels[index] = value;
 
         Ok(())
     }
 
+    /// Sets the BGR555 color of a pixel. Once a color is set, the buffer holds colors instead of shades until it's
+    /// cleared.
+    pub fn set_color(&mut self, x: usize, y: usize, color: u16) -> anyhow::Result<()> {
+        if !((0..SCREEN_WIDTH).contains(&x) && (0..SCREEN_HEIGHT).contains(&y)) {
+            anyhow::bail!("Pixel position ({}, {}) out of range", x, y);
+        }
+
+        let colors = self
+            .colors
+            .get_or_insert_with(|| crate::util::boxed_array_copy(0x7FFF));
+        colors[y * SCREEN_WIDTH + x] = color;
+
+        Ok(())
+    }
+
+    /// The BGR555 colors of the pixels, row by row, if the buffer was rendered in CGB mode.
+    pub fn colors(&self) -> Option<&[u16]> {
+        self.colors.as_deref().map(|colors| &colors[..])
+    }
+
     pub fn clear(&mut self) {
         for pixel in self.pixels.iter_mut() {
             *pixel = 0;
         }
+        self.colors = None;
     }
 }
 
@@ -253,16 +314,35 @@ impl ScreenDoubleBuffer {
     }
 }
 
+/// For how many dots LY reads as 153 before reading as 0 during the last vblank line.
+const LY_153_DOTS: u16 = 4;
+
+/// Amount of tiles in the master tileset: the 3 blocks of 128 tiles at 0x8000..=0x97FF. Block 0 (0x8000) and
+/// block 1 (0x8800) are used by objects and by the background with unsigned addressing, while blocks 2 (0x9000)
+/// and 1 are used by the background with signed addressing.
+const MASTER_TILESET_LEN: usize = 384;
+
 pub struct Ppu {
     cycles: u16,
     mode: PPUMode,
     interrupt_ongoing: bool,
     buffers: ScreenDoubleBuffer,
-    master_tileset: Box<[Tile; 384]>,
+    master_tileset: Box<[Tile; MASTER_TILESET_LEN]>,
+    /// The master tileset of VRAM bank 1, only used in CGB mode.
+    master_tileset_bank1: Box<[Tile; MASTER_TILESET_LEN]>,
     tilemap0: Box<[u8; 1024]>,
     tilemap1: Box<[u8; 1024]>,
+    /// The CGB attributes of the tiles in each tilemap, stored in VRAM bank 1. All zero outside of CGB mode.
+    attributes0: Box<[u8; 1024]>,
+    attributes1: Box<[u8; 1024]>,
     scanline_objects: Vec<ObjectAttributes>,
     window_line_counter: u8,
+    dot: u16,
+    frame_count: u64,
+    overflow_lines: u8,
+    sprite_overflow_lines: u8,
+    #[cfg(feature = "tdebugger")]
+    mode_trace: ModeTrace,
 }
 
 impl Ppu {
@@ -279,11 +359,20 @@ impl Ppu {
             mode: PPUMode::OAMSearch,
             interrupt_ongoing: false,
             buffers: ScreenDoubleBuffer::new(),
-            master_tileset: crate::util::boxed_array(Tile::default()),
-            tilemap0: crate::util::boxed_array(0u8),
-            tilemap1: crate::util::boxed_array(0u8),
+            master_tileset: crate::util::boxed_default_array(),
+            master_tileset_bank1: crate::util::boxed_default_array(),
+            tilemap0: crate::util::boxed_default_array(),
+            tilemap1: crate::util::boxed_default_array(),
+            attributes0: crate::util::boxed_default_array(),
+            attributes1: crate::util::boxed_default_array(),
             scanline_objects: Vec::with_capacity(10),
             window_line_counter: 0,
+            dot: 0,
+            frame_count: 0,
+            overflow_lines: 0,
+            sprite_overflow_lines: 0,
+            #[cfg(feature = "tdebugger")]
+            mode_trace: ModeTrace::Off,
         }
     }
 
@@ -318,6 +407,9 @@ impl Ppu {
 
         Self::set_stat_flag(memory, memreg::StatFlag::ModeBits, false);
         Self::set_stat_flag(memory, flag, true);
+
+        #[cfg(feature = "tdebugger")]
+        self.dbg_trace_mode_transition(memory.read(memreg::addresses::LY), mode);
     }
 
     #[inline]
@@ -361,21 +453,85 @@ impl Ppu {
         self.buffers.front()
     }
 
+    /// Returns a copy of the last complete frame, so it can be processed without keeping the emulator locked.
+    pub fn clone_front_buffer(&self) -> Box<ScreenBuffer> {
+        Box::new(self.buffers.front().clone())
+    }
+
+    /// How many scanlines of the last complete frame had more than 10 objects, which means some were dropped.
+    pub fn sprite_overflow_lines(&self) -> u8 {
+        self.sprite_overflow_lines
+    }
+
+    /// The amount of frames that have been completed so far.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// The amount of dots that have elapsed in the current scanline.
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+
     #[inline]
-    fn increment_ly(memory: &mut Memory) {
+    fn increment_ly(&mut self, memory: &mut Memory) {
         let new_ly = (memory.read(memreg::addresses::LY) + 1) % 154;
         memory.write(memreg::addresses::LY, new_ly);
+        self.dot = 0;
+    }
+
+    /// Returns the tile at the given index of the master tileset. Indices are always in range given how they're
+    /// computed, but out of range ones are clamped in release builds instead of panicking.
+    #[inline]
+    fn master_tile(&self, index: usize) -> &Tile {
+        self.banked_master_tile(0, index)
+    }
+
+    /// Same as [Ppu::master_tile], but for the master tileset of the given VRAM bank.
+    #[inline]
+    fn banked_master_tile(&self, bank: u8, index: usize) -> &Tile {
+        debug_assert!(
+            index < MASTER_TILESET_LEN,
+            "tile index {} out of range",
+            index
+        );
+        let tileset = if bank == 0 {
+            &self.master_tileset
+        } else {
+            &self.master_tileset_bank1
+        };
+        &tileset[index.min(MASTER_TILESET_LEN - 1)]
+    }
+
+    /// Whether the screen is rendered with CGB colors, which requires running a CGB game in CGB mode.
+    #[inline]
+    fn cgb_colors(memory: &Memory) -> bool {
+        memory.cgb_mode() && memory.vram().as_slice().len() > VRAM_BANK_SIZE
     }
 
     #[inline]
     fn update_master_tileset(&mut self, memory: &mut Memory) {
-        let vram_tileset = &memory.vram().as_slice()[..0x1800];
-        for (i, chunk) in vram_tileset.chunks_exact(16).enumerate() {
-            let mut bytes = [0u8; 16];
-            bytes.copy_from_slice(chunk);
+        let cgb_colors = Self::cgb_colors(memory);
+        let vram = memory.vram().as_slice();
+        let tilesets = [
+            (&mut self.master_tileset, Some(&vram[..0x1800])),
+            (
+                &mut self.master_tileset_bank1,
+                cgb_colors.then(|| &vram[VRAM_BANK_SIZE..VRAM_BANK_SIZE + 0x1800]),
+            ),
+        ];
+        for (master_tileset, vram_tileset) in tilesets {
+            let Some(vram_tileset) = vram_tileset else {
+                continue;
+            };
+
+            for (i, chunk) in vram_tileset.chunks_exact(16).enumerate() {
+                let mut bytes = [0u8; 16];
+                bytes.copy_from_slice(chunk);
 
-            let tile = Tile::new(bytes);
-            self.master_tileset[i] = tile;
+                let tile = Tile::new(bytes);
+                master_tileset[i] = tile;
+            }
         }
     }
 
@@ -387,6 +543,59 @@ impl Ppu {
 
         self.tilemap0.copy_from_slice(vram_tilemap0);
         self.tilemap1.copy_from_slice(vram_tilemap1);
+
+        // the attributes sit at the same place as the tilemaps, but in bank 1
+        if Self::cgb_colors(memory) {
+            let bank1 = &vram[VRAM_BANK_SIZE..];
+            self.attributes0
+                .copy_from_slice(&bank1[0x9800 - 0x8000..0x9C00 - 0x8000]);
+            self.attributes1
+                .copy_from_slice(&bank1[0x9C00 - 0x8000..0xA000 - 0x8000]);
+        } else {
+            self.attributes0.fill(0);
+            self.attributes1.fill(0);
+        }
+    }
+
+    /// Returns a pixel of a background or window tile. `attributes` is the CGB attribute byte of the tile, which
+    /// shares its layout with the object attribute flags, except that bit 7 gives the tile priority over objects.
+    #[inline]
+    fn tile_pixel(
+        &self,
+        lcdc: &memreg::LCDC,
+        tile_tileset_index: u8,
+        attributes: u8,
+        mut pixel_position_tile: (u8, u8),
+    ) -> BackgroundPixel {
+        let attributes = FlagSet::<ObjectAttributesFlags>::new(attributes).unwrap();
+        let bank = attributes.contains(ObjectAttributesFlags::VramBank) as u8;
+
+        let tile = if lcdc.alternative_addressing_mode() {
+            match tile_tileset_index {
+                0..=127 => self.banked_master_tile(bank, 0x1000 / 16 + tile_tileset_index as usize),
+                128..=255 => self.banked_master_tile(bank, tile_tileset_index as usize),
+            }
+        } else {
+            self.banked_master_tile(bank, tile_tileset_index as usize)
+        };
+
+        if attributes.contains(ObjectAttributesFlags::FlipX) {
+            pixel_position_tile.0 = 7 - pixel_position_tile.0;
+        }
+        if attributes.contains(ObjectAttributesFlags::FlipY) {
+            pixel_position_tile.1 = 7 - pixel_position_tile.1;
+        }
+
+        // obtendo a cor do pixel
+        let color_index = tile
+            .get_pixel_color_index(pixel_position_tile.0, pixel_position_tile.1)
+            .unwrap();
+
+        BackgroundPixel {
+            color_index,
+            palette: (attributes & ObjectAttributesFlags::CgbPaletteBits).bits(),
+            priority: attributes.contains(ObjectAttributesFlags::UnderBgWindow),
+        }
     }
 
     fn oam_search(&mut self, memory: &mut Memory) {
@@ -395,7 +604,7 @@ impl Ppu {
         let lcdc = Self::get_lcdc(memory);
 
         let oam = &memory.oam()[..];
-        for chunk in oam.chunks_exact(4) {
+        for (oam_index, chunk) in oam.chunks_exact(4).enumerate() {
             let mut bytes = [0; 4];
             bytes.copy_from_slice(chunk);
 
@@ -404,15 +613,17 @@ impl Ppu {
             }
 
             let ly = ly as i16;
-            let obj_attributes = ObjectAttributes::new(bytes).unwrap();
+            let obj_attributes = ObjectAttributes::new(bytes, oam_index as u8).unwrap();
             if obj_attributes.y_top_left() > ly - if lcdc.double_height_objects() { 16 } else { 8 }
                 && obj_attributes.y_top_left() <= ly
             {
-                self.scanline_objects.push(obj_attributes);
-
+                // only 10 objects are drawn per line, any others are dropped
                 if self.scanline_objects.len() >= 10 {
+                    self.overflow_lines += 1;
                     return;
                 }
+
+                self.scanline_objects.push(obj_attributes);
             }
         }
     }
@@ -422,9 +633,9 @@ impl Ppu {
         let lcdc = Self::get_lcdc(memory);
         let scx = memory.read(memreg::addresses::SCX);
         let scy = memory.read(memreg::addresses::SCY);
-        let bg_tilemap = match lcdc.background_tilemap() {
-            Tilemap::Tilemap0 => &self.tilemap0,
-            Tilemap::Tilemap1 => &self.tilemap1,
+        let (bg_tilemap, bg_attributes) = match lcdc.background_tilemap() {
+            Tilemap::Tilemap0 => (&self.tilemap0, &self.attributes0),
+            Tilemap::Tilemap1 => (&self.tilemap1, &self.attributes1),
         };
 
         // convertendo para tilemap space
@@ -448,23 +659,12 @@ impl Ppu {
         // convertendo a posiçao do pixel em tile space para a posiçao no tilemap do tile que o contem e a posiçao relativa do pixel ao tile
         let tile_index_tilemap =
             tile_position_tilemap.1 as usize * 32 + tile_position_tilemap.0 as usize;
-        let tile_tileset_index = bg_tilemap[tile_index_tilemap];
-
-        let tile = if lcdc.alternative_addressing_mode() {
-            match tile_tileset_index {
-                0..=127 => &self.master_tileset[0x1000 / 16 + tile_tileset_index as usize],
-                128..=255 => &self.master_tileset[tile_tileset_index as usize],
-            }
-        } else {
-            &self.master_tileset[tile_tileset_index as usize]
-        };
-
-        // obtendo a cor do pixel
-        let color_index = tile
-            .get_pixel_color_index(pixel_position_tile.0, pixel_position_tile.1)
-            .unwrap();
-
-        BackgroundPixel { color_index }
+        self.tile_pixel(
+            &lcdc,
+            bg_tilemap[tile_index_tilemap],
+            bg_attributes[tile_index_tilemap],
+            pixel_position_tile,
+        )
     }
 
     #[inline]
@@ -480,10 +680,18 @@ impl Ppu {
             })
             .collect();
 
-        objs.sort_by_key(|obj| obj.x_top_left());
+        // objects are in OAM order. the DMG prioritizes by x coordinate, with the lower OAM index winning ties, while
+        // the CGB uses OAM order unless bit 0 of OPRI selects the DMG behavior
+        let opri = memory.read(memreg::addresses::OPRI);
+        if !memory.cgb_mode() || opri & 0x01 != 0 {
+            objs.sort_unstable_by_key(|obj| (obj.x_top_left(), obj.oam_index));
+        }
 
+        let cgb_colors = Self::cgb_colors(memory);
         let mut obj_pixel = None;
         for obj in objs {
+            // only the CGB has a second VRAM bank to take object tiles from
+            let bank = if cgb_colors { obj.vram_bank() } else { 0 };
             let mut pixel_position_tile = (
                 pixel_position.0 - obj.x_top_left(),
                 pixel_position.1 - obj.y_top_left(),
@@ -494,17 +702,18 @@ impl Ppu {
                     pixel_position_tile.1 = 15 - pixel_position_tile.1;
                 }
 
+                // in 8x16 mode, bit 0 of the tile index is ignored
                 if pixel_position_tile.1 > 7 {
                     pixel_position_tile.1 -= 8;
-                    &self.master_tileset[obj.tile_index as usize + 1]
+                    self.banked_master_tile(bank, (obj.tile_index | 0x01) as usize)
                 } else {
-                    &self.master_tileset[obj.tile_index as usize]
+                    self.banked_master_tile(bank, (obj.tile_index & 0xFE) as usize)
                 }
             } else {
                 if obj.flip_y() {
                     pixel_position_tile.1 = 7 - pixel_position_tile.1;
                 }
-                &self.master_tileset[obj.tile_index as usize]
+                self.banked_master_tile(bank, obj.tile_index as usize)
             };
 
             if obj.flip_x() {
@@ -538,14 +747,17 @@ impl Ppu {
         let lcdc = Self::get_lcdc(memory);
         let wx = memory.read(memreg::addresses::WX);
         let wy = memory.read(memreg::addresses::WY);
-        let window_tilemap = match lcdc.window_tilemap() {
-            Tilemap::Tilemap0 => &self.tilemap0,
-            Tilemap::Tilemap1 => &self.tilemap1,
+        let (window_tilemap, window_attributes) = match lcdc.window_tilemap() {
+            Tilemap::Tilemap0 => (&self.tilemap0, &self.attributes0),
+            Tilemap::Tilemap1 => (&self.tilemap1, &self.attributes1),
         };
 
-        // converter a posiçao do pixel pra posiçao relativa à window
-        let pixel_position_window = if pixel_position.0 + 7 >= wx && pixel_position.1 >= wy {
-            (pixel_position.0 + 7 - wx, self.window_line_counter)
+        // converter a posiçao do pixel pra posiçao relativa à window. done in i16 so any WX is fine: the window
+        // starts at WX - 7, so it's never visible for WX > 166, and for WX < 7 (including 0) its first
+        // 7 - WX columns are off screen to the left. window_x is at most 159 + 7 = 166, so it fits in a u8
+        let window_x = pixel_position.0 as i16 + 7 - wx as i16;
+        let pixel_position_window = if window_x >= 0 && pixel_position.1 >= wy {
+            (window_x as u8, self.window_line_counter)
         } else {
             return None;
         };
@@ -565,37 +777,15 @@ impl Ppu {
         // convertendo a posiçao do pixel em tile space para a posiçao no tilemap do tile que o contem e a posiçao relativa do pixel ao tile
         let tile_index_tilemap =
             tile_position_tilemap.1 as usize * 32 + tile_position_tilemap.0 as usize;
-        let tile_tileset_index = window_tilemap[tile_index_tilemap];
-
-        let tile = if lcdc.alternative_addressing_mode() {
-            match tile_tileset_index {
-                0..=127 => &self.master_tileset[0x1000 / 16 + tile_tileset_index as usize],
-                128..=255 => &self.master_tileset[tile_tileset_index as usize],
-            }
-        } else {
-            &self.master_tileset[tile_tileset_index as usize]
-        };
-
-        // obtendo a cor do pixel
-        let color_index = tile
-            .get_pixel_color_index(pixel_position_tile.0, pixel_position_tile.1)
-            .unwrap();
-
-        Some(BackgroundPixel { color_index })
+        Some(self.tile_pixel(
+            &lcdc,
+            window_tilemap[tile_index_tilemap],
+            window_attributes[tile_index_tilemap],
+            pixel_position_tile,
+        ))
     }
 
     fn render_scanline(&mut self, memory: &mut Memory) {
-        #[inline]
-        fn get_color(index: u8, palette: Palette) -> u8 {
-            match index {
-                0 => palette.color_0(),
-                1 => palette.color_1(),
-                2 => palette.color_2(),
-                3 => palette.color_3(),
-                _ => unreachable!(),
-            }
-        }
-
         self.update_master_tileset(memory);
         self.update_tilemaps(memory);
 
@@ -610,17 +800,21 @@ impl Ppu {
         let obj_palette0 = Palette::from(memory.read(memreg::addresses::OBP0));
         let obj_palette1 = Palette::from(memory.read(memreg::addresses::OBP1));
 
+        // in CGB mode, LCDC bit 0 doesn't hide the background and window, it only takes their priority away
+        let cgb_colors = Self::cgb_colors(memory);
+        let bg_window_shown = lcdc.background_window_priority() || cgb_colors;
+
         let mut window_drawn = false;
-        for x in 0..160u8 {
+        for x in 0..SCREEN_WIDTH as u8 {
             let pixel_position = (x, ly);
 
-            let bg_pixel = if lcdc.background_window_priority() {
+            let bg_pixel = if bg_window_shown {
                 self.get_bg_pixel(memory, pixel_position)
             } else {
-                BackgroundPixel { color_index: 0 }
+                BackgroundPixel::default()
             };
 
-            let window_pixel = if lcdc.window_enabled() && lcdc.background_window_priority() {
+            let window_pixel = if lcdc.window_enabled() && bg_window_shown {
                 self.get_window_pixel(memory, pixel_position)
             } else {
                 None
@@ -635,33 +829,46 @@ impl Ppu {
                 None
             };
 
-            let final_color = if let Some(obj_pixel) = obj_pixel {
-                if obj_pixel.color_index != 0
-                    && !(obj_pixel.under_bg_window && bg_pixel.color_index != 0)
-                {
-                    get_color(
-                        obj_pixel.color_index,
-                        if obj_pixel.palette == 0 {
-                            obj_palette0
-                        } else {
-                            obj_palette1
-                        },
-                    )
+            let obj_pixel = obj_pixel.filter(|obj_pixel| {
+                let bg_over_obj = if cgb_colors {
+                    lcdc.background_window_priority()
+                        && (obj_pixel.under_bg_window || bg_pixel.priority)
                 } else {
-                    get_color(bg_pixel.color_index, bg_palette)
-                }
+                    obj_pixel.under_bg_window
+                };
+                obj_pixel.color_index != 0 && !(bg_over_obj && bg_pixel.color_index != 0)
+            });
+
+            let final_color = if let Some(obj_pixel) = &obj_pixel {
+                let obj_palette = if obj_pixel.palette == 0 {
+                    obj_palette0
+                } else {
+                    obj_palette1
+                };
+                obj_palette.shade(obj_pixel.color_index)
             } else {
-                get_color(bg_pixel.color_index, bg_palette)
+                bg_palette.shade(bg_pixel.color_index)
             };
 
-            self.buffers
-                .back_mut()
+            let buffer = self.buffers.back_mut();
+            buffer
                 .set_pixel(
                     pixel_position.0 as usize,
                     pixel_position.1 as usize,
                     final_color,
                 )
                 .unwrap();
+
+            if cgb_colors {
+                let color = if obj_pixel.is_some() {
+                    CGB_OBJECT_GRAYS[final_color as usize]
+                } else {
+                    memory.bg_color(bg_pixel.palette, bg_pixel.color_index)
+                };
+                buffer
+                    .set_color(pixel_position.0 as usize, pixel_position.1 as usize, color)
+                    .unwrap();
+            }
         }
 
         if window_drawn {
@@ -669,34 +876,59 @@ impl Ppu {
         }
     }
 
-    pub fn cycle(&mut self, memory: &mut Memory) {
-        self.update_stat_interrupt(memory);
+    /// Advances the PPU by the given amount of dots. Instead of polling every dot, it jumps straight to the
+    /// next mode change.
+    pub fn advance(&mut self, memory: &mut Memory, dots: u16) {
+        let mut remaining = dots;
+        loop {
+            // nothing that affects the STAT interrupt line changes between events, so checking it once before
+            // the idle dots is the same as checking it on every dot
+            self.update_stat_interrupt(memory);
+
+            let idle = self.cycles.min(remaining);
+            self.cycles -= idle;
+            self.dot += idle;
+            remaining -= idle;
+
+            if self.cycles > 0 {
+                break;
+            }
 
-        if self.cycles > 0 {
-            self.cycles -= 1;
-            return;
+            self.next_event(memory);
         }
+    }
+
+    /// How many dots (clock cycles) until the PPU's next event.
+    pub fn cycles_to_next_event(&self) -> u32 {
+        self.cycles as u32
+    }
 
+    /// Handles the event at the end of the current countdown, usually a mode change.
+    fn next_event(&mut self, memory: &mut Memory) {
         let ly = memory.read(memreg::addresses::LY);
         match ly {
             0..=143 => match self.mode {
                 PPUMode::HBlank => {
                     if ly == 143 {
+                        self.increment_ly(memory);
                         self.set_mode(memory, PPUMode::VBlank);
                         self.cycles = 456;
 
                         self.buffers.switch();
-                        Self::increment_ly(memory);
+                        self.frame_count += 1;
+                        self.sprite_overflow_lines = std::mem::take(&mut self.overflow_lines);
                         memory.request_interrupt(memreg::Interrupt::VBlank);
                     } else {
+                        self.increment_ly(memory);
                         self.set_mode(memory, PPUMode::OAMSearch);
                         self.cycles = 80;
 
-                        Self::increment_ly(memory);
                         self.oam_search(memory);
                     }
                 }
                 PPUMode::VBlank => {
+                    // line 153 is over, LY already reads as 0
+                    self.dot = 0;
                     self.set_mode(memory, PPUMode::OAMSearch);
                     self.cycles = 80;
 
@@ -717,18 +949,20 @@ impl Ppu {
                 PPUMode::HBlank => unreachable!(),
                 PPUMode::VBlank => match ly {
                     152 => {
-                        self.cycles = 4;
-                        Self::increment_ly(memory);
+                        self.cycles = LY_153_DOTS;
+                        self.increment_ly(memory);
                     }
                     153 => {
+                        // on line 153, LY only reads as 153 for a few dots: it reads as 0 for the rest of
+                        // the line, while still in vblank
                         self.window_line_counter = 0;
-                        Self::increment_ly(memory);
+                        memory.write(memreg::addresses::LY, 0);
 
-                        self.cycles = 456 - 4;
+                        self.cycles = 456 - LY_153_DOTS;
                     }
                     _ => {
                         self.cycles = 456;
-                        Self::increment_ly(memory);
+                        self.increment_ly(memory);
                     }
                 },
                 PPUMode::OAMSearch => unreachable!(),
@@ -738,9 +972,66 @@ impl Ppu {
     }
 }
 
+/// A PPU mode transition, recorded for debugging purposes.
+#[cfg(feature = "tdebugger")]
+#[derive(Debug, Clone, Copy)]
+pub struct ModeTransition {
+    pub ly: u8,
+    pub mode: PPUMode,
+    pub dot: u16,
+}
+
+#[cfg(feature = "tdebugger")]
+enum ModeTrace {
+    Off,
+    /// Waiting for the start of the next frame.
+    Armed,
+    Recording(Vec<ModeTransition>),
+    Finished(Vec<ModeTransition>),
+}
+
 // debug
 #[cfg(feature = "tdebugger")]
 impl Ppu {
+    /// Starts recording the mode transitions of the next full frame.
+    pub fn dbg_record_mode_trace(&mut self) {
+        self.mode_trace = ModeTrace::Armed;
+    }
+
+    fn dbg_trace_mode_transition(&mut self, ly: u8, mode: PPUMode) {
+        let transition = ModeTransition {
+            ly,
+            mode,
+            dot: self.dot,
+        };
+        let frame_start = ly == 0 && mode == PPUMode::OAMSearch;
+
+        let trace = std::mem::replace(&mut self.mode_trace, ModeTrace::Off);
+        self.mode_trace = match trace {
+            ModeTrace::Armed if frame_start => ModeTrace::Recording(vec![transition]),
+            ModeTrace::Recording(transitions) if frame_start => ModeTrace::Finished(transitions),
+            ModeTrace::Recording(mut transitions) => {
+                transitions.push(transition);
+                ModeTrace::Recording(transitions)
+            }
+            trace => trace,
+        };
+    }
+
+    /// Returns the recorded mode transitions as CSV, if a full frame has been recorded.
+    pub fn dbg_mode_trace_csv(&self) -> Option<String> {
+        if let ModeTrace::Finished(transitions) = &self.mode_trace {
+            let mut csv = String::from("ly,mode,dot\n");
+            for t in transitions {
+                csv.push_str(&format!("{},{:?},{}\n", t.ly, t.mode, t.dot));
+            }
+
+            Some(csv)
+        } else {
+            None
+        }
+    }
+
     pub fn dbg_save_master_tileset(&self) {
         fn tile_to_img(tile: &Tile) -> image::RgbImage {
             image::RgbImage::from_fn(8, 8, |x, y| {
@@ -756,9 +1047,73 @@ impl Ppu {
         }
     }
 
+    /// Renders the 40 OAM entries into a sprite sheet with 8 entries per row, flips and palettes applied. Each
+    /// entry gets a 10x18 cell, so 8x16 objects fit too, and color 0 is left transparent. Also returns a line per
+    /// entry with its position in the sheet and attributes.
+    pub fn dbg_oam_sprite_sheet(
+        &self,
+        memory: &Memory,
+        colors: &[[u8; 4]; 4],
+    ) -> (image::RgbaImage, String) {
+        const CELL: (u32, u32) = (10, 18);
+
+        let lcdc = Self::get_lcdc(memory);
+        let height = if lcdc.double_height_objects() { 16 } else { 8 };
+        let palettes = [
+            Palette::new(memory.read(memreg::addresses::OBP0)),
+            Palette::new(memory.read(memreg::addresses::OBP1)),
+        ];
+
+        let mut sheet = image::RgbaImage::new(8 * CELL.0, 5 * CELL.1);
+        let mut labels = String::from("index,column,row,x,y,tile,flags\n");
+        for (index, chunk) in memory.oam().chunks_exact(4).enumerate() {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(chunk);
+            let obj = ObjectAttributes::new(bytes, index as u8).unwrap();
+
+            let (row, column) = crate::util::div_rem(index as u32, 8);
+            labels.push_str(&format!(
+                "{},{},{},{},{},{:02X},{:08b}\n",
+                index, column, row, obj.x, obj.y, obj.tile_index, bytes[3]
+            ));
+
+            for y in 0..height {
+                let mut tile_y = if obj.flip_y() { height - 1 - y } else { y };
+                let tile = if height == 16 {
+                    // in 8x16 mode, bit 0 of the tile index is ignored
+                    if tile_y > 7 {
+                        tile_y -= 8;
+                        self.master_tile((obj.tile_index | 0x01) as usize)
+                    } else {
+                        self.master_tile((obj.tile_index & 0xFE) as usize)
+                    }
+                } else {
+                    self.master_tile(obj.tile_index as usize)
+                };
+
+                for x in 0..8 {
+                    let tile_x = if obj.flip_x() { 7 - x } else { x };
+                    let color_index = tile.get_pixel_color_index(tile_x, tile_y).unwrap();
+                    if color_index == 0 {
+                        continue;
+                    }
+
+                    let shade = palettes[obj.dmg_palette() as usize].shade(color_index);
+                    sheet.put_pixel(
+                        column * CELL.0 + 1 + x as u32,
+                        row * CELL.1 + 1 + y as u32,
+                        image::Rgba(colors[shade as usize]),
+                    );
+                }
+            }
+        }
+
+        (sheet, labels)
+    }
+
     pub fn dbg_save_current_buffer(&self) {
         let buffer = self.buffers.back();
-        let img = image::RgbImage::from_fn(160, 144, |x, y| {
+        let img = image::RgbImage::from_fn(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, |x, y| {
             let c = buffer.get_pixel(x as usize, y as usize).unwrap() * 85;
             image::Rgb([c, c, c])
         });
@@ -766,3 +1121,290 @@ impl Ppu {
         img.save("/dump/buffer_dump.png").unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::rom::Rom;
+
+    /// Creates a PPU along with the memory it renders from, holding `program` as an MBC-less rom.
+    fn ppu(program: &[u8]) -> (Ppu, Memory) {
+        let mut memory = Memory::new(Rom::from_bytes_unchecked(program).unwrap(), Box::new([]));
+        let ppu = Ppu::new(&mut memory);
+        (ppu, memory)
+    }
+
+    fn ly(memory: &Memory) -> u8 {
+        memory.read(memreg::addresses::LY)
+    }
+
+    fn mode(memory: &Memory) -> u8 {
+        memory.read(memreg::addresses::STAT) & 0b11
+    }
+
+    #[test]
+    fn ly_reads_zero_for_most_of_line_153() {
+        let (mut ppu, mut memory) = ppu(&[]);
+        while ly(&memory) != 152 {
+            ppu.advance(&mut memory, 1);
+        }
+
+        let mut line_dots = 0;
+        while ly(&memory) == 152 {
+            line_dots += 1;
+            ppu.advance(&mut memory, 1);
+        }
+        assert_eq!(line_dots, 456);
+
+        let mut dots_at_153 = 0;
+        while ly(&memory) == 153 {
+            assert_eq!(mode(&memory), 1);
+            dots_at_153 += 1;
+            ppu.advance(&mut memory, 1);
+        }
+        assert_eq!(dots_at_153, LY_153_DOTS);
+
+        // the rest of the line reads as 0, still in vblank
+        let mut dots_at_0 = 0;
+        while mode(&memory) == 1 {
+            assert_eq!(ly(&memory), 0);
+            dots_at_0 += 1;
+            ppu.advance(&mut memory, 1);
+        }
+        assert_eq!(dots_at_0, line_dots - LY_153_DOTS);
+        assert_eq!(ly(&memory), 0);
+        assert_eq!(mode(&memory), 2);
+    }
+
+    #[test]
+    fn palette_shades() {
+        // (BGP, shades of color indices 0 to 3)
+        for (data, shades) in [
+            (0xE4, [0, 1, 2, 3]),
+            (0x1B, [3, 2, 1, 0]),
+            (0x00, [0, 0, 0, 0]),
+            (0xFF, [3, 3, 3, 3]),
+            (0xD2, [2, 0, 1, 3]),
+        ] {
+            let palette = Palette::new(data);
+            for (color_index, shade) in shades.into_iter().enumerate() {
+                assert_eq!(palette.shade(color_index as u8), shade, "{:02X}", data);
+            }
+            assert_eq!(
+                [
+                    palette.color_0(),
+                    palette.color_1(),
+                    palette.color_2(),
+                    palette.color_3()
+                ],
+                shades
+            );
+        }
+
+        // only the lower 2 bits of the color index matter
+        assert_eq!(Palette::new(0xD2).shade(0b101), 0);
+    }
+
+    #[test]
+    fn scanlines_and_frames_have_exact_lengths() {
+        let (mut ppu, mut memory) = ppu(&[]);
+        // line 1 is the first one to start with a full OAM search
+        while ly(&memory) != 1 {
+            ppu.advance(&mut memory, 1);
+        }
+
+        let mut modes = Vec::new();
+        let mut dots = 0u32;
+        while ly(&memory) == 1 {
+            let mode = mode(&memory);
+            match modes.last_mut() {
+                Some((last, length)) if *last == mode => *length += 1,
+                _ => modes.push((mode, 1)),
+            }
+
+            dots += 1;
+            ppu.advance(&mut memory, 1);
+        }
+        assert_eq!(modes, [(2, 80), (3, 168), (0, 208)]);
+        assert_eq!(dots, 456);
+
+        let frame = ppu.frame_count();
+        while ppu.frame_count() == frame {
+            ppu.advance(&mut memory, 1);
+        }
+        let frame = ppu.frame_count();
+        let mut frame_dots = 0;
+        while ppu.frame_count() == frame {
+            frame_dots += 1;
+            ppu.advance(&mut memory, 1);
+        }
+        assert_eq!(frame_dots, FRAME_CYCLES);
+    }
+
+    /// Renders line 0 with the window at `wx`, returning which columns show the window and the window line counter
+    /// afterwards. The background is color 0 everywhere and the window color 3.
+    fn window_columns(wx: u8) -> (Vec<bool>, u8) {
+        let (mut ppu, mut memory) = ppu(&[]);
+        for address in 0x8000..0xA000 {
+            let data = match address {
+                0x8010..=0x801F => 0xFF,
+                0x9C00.. => 0x01,
+                _ => 0x00,
+            };
+            memory.write(address, data);
+        }
+
+        // lcd on, window tilemap 1, window on, unsigned tile data, background tilemap 0, background on
+        memory.write(memreg::addresses::LCDC, 0b1111_0001);
+        memory.write(memreg::addresses::BGP, 0xE4);
+        memory.write(memreg::addresses::WY, 0);
+        memory.write(memreg::addresses::WX, wx);
+        ppu.render_scanline(&mut memory);
+
+        let screen = ppu.buffers.back();
+        let columns = (0..SCREEN_WIDTH)
+            .map(|x| screen.get_pixel(x, 0).unwrap() == 3)
+            .collect();
+        (columns, ppu.window_line_counter)
+    }
+
+    #[test]
+    fn window_x_positions() {
+        // (WX, first window column)
+        for (wx, first) in [(0, 0), (7, 0), (100, 93), (166, 159)] {
+            let (columns, line_counter) = window_columns(wx);
+            for (x, window) in columns.into_iter().enumerate() {
+                assert_eq!(window, x >= first, "WX {} x {}", wx, x);
+            }
+            assert_eq!(line_counter, 1, "WX {}", wx);
+        }
+
+        // the window starts past the last column, so it isn't drawn at all
+        for wx in [167, 200, 255] {
+            let (columns, line_counter) = window_columns(wx);
+            assert!(columns.into_iter().all(|window| !window), "WX {}", wx);
+            assert_eq!(line_counter, 0, "WX {}", wx);
+        }
+    }
+
+    #[test]
+    fn window_at_screen_edges_over_a_whole_frame() {
+        // the window tile only has its leftmost column set, so the window shows a color 3 pixel every 8 columns,
+        // starting at the screen column where window x is 0
+        // (WX, first screen column with color 3)
+        for (wx, first) in [(0, 1), (166, 159)] {
+            let (mut ppu, mut memory) = ppu(&[]);
+            for address in 0x8000..0xA000 {
+                let data = match address {
+                    0x8010..=0x801F => 0x80,
+                    0x9C00.. => 0x01,
+                    _ => 0x00,
+                };
+                memory.write(address, data);
+            }
+
+            memory.write(memreg::addresses::LCDC, 0b1111_0001);
+            memory.write(memreg::addresses::BGP, 0xE4);
+            memory.write(memreg::addresses::WY, 0);
+            memory.write(memreg::addresses::WX, wx);
+
+            let frame = ppu.frame_count();
+            while ppu.frame_count() == frame {
+                ppu.advance(&mut memory, 4);
+            }
+
+            let screen = ppu.screen();
+            for y in 0..SCREEN_HEIGHT {
+                for x in 0..SCREEN_WIDTH {
+                    let window = screen.get_pixel(x, y).unwrap() == 3;
+                    let expected = x >= first && (x - first) % 8 == 0;
+                    assert_eq!(window, expected, "WX {} at {}, {}", wx, x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lower_oam_index_wins_same_x_ties() {
+        // tile 1 is color 1 and tile 2 color 2 everywhere. for each pair of (OAM index, tile), both objects are at
+        // the top left of the screen, 16 pixels in
+        for objects in [[(2, 1), (5, 2)], [(2, 2), (5, 1)], [(5, 1), (2, 2)]] {
+            let (mut ppu, mut memory) = ppu(&[]);
+            for address in 0x8000..0xA000 {
+                let data = match address {
+                    0x8010..=0x801F => (address % 2 == 0) as u8 * 0xFF,
+                    0x8020..=0x802F => (address % 2 == 1) as u8 * 0xFF,
+                    _ => 0x00,
+                };
+                memory.write(address, data);
+            }
+
+            for address in 0xFE00..0xFEA0 {
+                memory.write(address, 0x00);
+            }
+            for (index, tile) in objects {
+                let base = 0xFE00 + index * 4;
+                for (offset, data) in [16, 24, tile, 0x00].into_iter().enumerate() {
+                    memory.write(base + offset as u16, data);
+                }
+            }
+
+            // lcd on, objects on, background on
+            memory.write(memreg::addresses::LCDC, 0b1000_0011);
+            memory.write(memreg::addresses::BGP, 0xE4);
+            memory.write(memreg::addresses::OBP0, 0xE4);
+            ppu.oam_search(&mut memory);
+            ppu.render_scanline(&mut memory);
+
+            let winner = objects.iter().min_by_key(|(index, _)| index).unwrap().1;
+            let screen = ppu.buffers.back();
+            for x in 16..24 {
+                assert_eq!(
+                    screen.get_pixel(x, 0).unwrap(),
+                    winner,
+                    "{:?} x {}",
+                    objects,
+                    x
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cgb_objects_use_their_vram_bank() {
+        let mut program = vec![0; 0x150];
+        program[0x0143] = 0x80;
+        let mut memory = Memory::new(
+            Rom::from_bytes_unchecked(program).unwrap(),
+            vec![0; crate::gameboy::memory::CGB_BOOT_SIZE].into(),
+        );
+        let mut ppu = Ppu::new(&mut memory);
+
+        // tile 1 is color 1 in bank 0 and color 2 in bank 1
+        for bank in 0..2 {
+            memory.write(memreg::addresses::VBK, bank);
+            for address in 0x8000..0xA000 {
+                let data = match address {
+                    0x8010..=0x801F if address % 2 == bank as u16 => 0xFF,
+                    _ => 0x00,
+                };
+                memory.write(address, data);
+            }
+        }
+        memory.write(memreg::addresses::VBK, 0);
+
+        for address in 0xFE00..0xFEA0 {
+            memory.write(address, 0x00);
+        }
+        // object 0 at the top left, 16 pixels in, using tile 1 from bank 1
+        for (offset, data) in [16, 24, 1, 0b0000_1000].into_iter().enumerate() {
+            memory.write(0xFE00 + offset as u16, data);
+        }
+
+        memory.write(memreg::addresses::LCDC, 0b1000_0011);
+        memory.write(memreg::addresses::OBP0, 0xE4);
+        ppu.oam_search(&mut memory);
+        ppu.render_scanline(&mut memory);
+        assert_eq!(ppu.buffers.back().get_pixel(16, 0).unwrap(), 2);
+    }
+}
@@ -2,10 +2,16 @@ use binread::{io::Cursor, BinReaderExt};
 use std::{
     borrow::Cow,
     ops::{Deref, DerefMut},
+    time::{Duration, SystemTime},
 };
 
 const HEADER_LEN: usize = 0x014F - 0x0133 + 1;
 
+/// Length of the real-time clock state appended to the external ram in save files, in the format most emulators
+/// use: the clock registers and the latched registers as little endian 32-bit words, then a 64-bit UNIX timestamp
+/// of when the save was made.
+pub const RTC_SAVE_LEN: usize = 48;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RomCgbStatus {
     CGBOnly,
@@ -19,13 +25,442 @@ pub enum RomSgbStatus {
     NoSGB,
 }
 
+/// The controller family of a cartridge, regardless of the extra hardware it carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomMBCFamily {
+    NoMBC,
+    MBC1,
+    MBC2,
+    MMM01,
+    MBC3,
+    MBC5,
+    MBC6,
+    MBC7,
+    PocketCamera,
+    BandaiTama5,
+    HuC3,
+    HuC1,
+    Unknown,
+}
+
+/// The cartridge type, as given by byte 0x0147 of the rom.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RomMBCType {
     NoMBC,
+    NoMBCRam,
+    NoMBCRamBattery,
     MBC1,
     MBC1Ram,
     MBC1RamBattery,
-    Unknown,
+    MBC2,
+    MBC2Battery,
+    MMM01,
+    MMM01Ram,
+    MMM01RamBattery,
+    MBC3TimerBattery,
+    MBC3TimerRamBattery,
+    MBC3,
+    MBC3Ram,
+    MBC3RamBattery,
+    MBC5,
+    MBC5Ram,
+    MBC5RamBattery,
+    MBC5Rumble,
+    MBC5RumbleRam,
+    MBC5RumbleRamBattery,
+    MBC6,
+    MBC7SensorRumbleRamBattery,
+    PocketCamera,
+    BandaiTama5,
+    HuC3,
+    HuC1RamBattery,
+    /// A cartridge type byte that doesn't match any known type.
+    Unknown(u8),
+}
+
+impl std::fmt::Display for RomCgbStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RomCgbStatus::CGBOnly => write!(f, "CGB only"),
+            RomCgbStatus::CGBSupport => write!(f, "CGB supported"),
+            RomCgbStatus::NoCGB => write!(f, "DMG only"),
+        }
+    }
+}
+
+impl std::fmt::Display for RomSgbStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RomSgbStatus::SGBSupport => write!(f, "SGB supported"),
+            RomSgbStatus::NoSGB => write!(f, "no SGB"),
+        }
+    }
+}
+
+impl std::fmt::Display for RomMBCType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RomMBCType::NoMBC => write!(f, "ROM only"),
+            RomMBCType::NoMBCRam => write!(f, "ROM+RAM"),
+            RomMBCType::NoMBCRamBattery => write!(f, "ROM+RAM+BATTERY"),
+            RomMBCType::MBC1 => write!(f, "MBC1"),
+            RomMBCType::MBC1Ram => write!(f, "MBC1+RAM"),
+            RomMBCType::MBC1RamBattery => write!(f, "MBC1+RAM+BATTERY"),
+            RomMBCType::MBC2 => write!(f, "MBC2"),
+            RomMBCType::MBC2Battery => write!(f, "MBC2+BATTERY"),
+            RomMBCType::MMM01 => write!(f, "MMM01"),
+            RomMBCType::MMM01Ram => write!(f, "MMM01+RAM"),
+            RomMBCType::MMM01RamBattery => write!(f, "MMM01+RAM+BATTERY"),
+            RomMBCType::MBC3TimerBattery => write!(f, "MBC3+TIMER+BATTERY"),
+            RomMBCType::MBC3TimerRamBattery => write!(f, "MBC3+TIMER+RAM+BATTERY"),
+            RomMBCType::MBC3 => write!(f, "MBC3"),
+            RomMBCType::MBC3Ram => write!(f, "MBC3+RAM"),
+            RomMBCType::MBC3RamBattery => write!(f, "MBC3+RAM+BATTERY"),
+            RomMBCType::MBC5 => write!(f, "MBC5"),
+            RomMBCType::MBC5Ram => write!(f, "MBC5+RAM"),
+            RomMBCType::MBC5RamBattery => write!(f, "MBC5+RAM+BATTERY"),
+            RomMBCType::MBC5Rumble => write!(f, "MBC5+RUMBLE"),
+            RomMBCType::MBC5RumbleRam => write!(f, "MBC5+RUMBLE+RAM"),
+            RomMBCType::MBC5RumbleRamBattery => write!(f, "MBC5+RUMBLE+RAM+BATTERY"),
+            RomMBCType::MBC6 => write!(f, "MBC6"),
+            RomMBCType::MBC7SensorRumbleRamBattery => write!(f, "MBC7+SENSOR+RUMBLE+RAM+BATTERY"),
+            RomMBCType::PocketCamera => write!(f, "POCKET CAMERA"),
+            RomMBCType::BandaiTama5 => write!(f, "BANDAI TAMA5"),
+            RomMBCType::HuC3 => write!(f, "HuC3"),
+            RomMBCType::HuC1RamBattery => write!(f, "HuC1+RAM+BATTERY"),
+            RomMBCType::Unknown(byte) => write!(f, "Unknown ({:#04X})", byte),
+        }
+    }
+}
+
+/// The MBC types that are emulated. Roms using any other type fall back to no MBC in lenient mode.
+pub const SUPPORTED_MBC_TYPES: &[RomMBCType] = &[
+    RomMBCType::NoMBC,
+    RomMBCType::NoMBCRam,
+    RomMBCType::NoMBCRamBattery,
+    RomMBCType::MBC1,
+    RomMBCType::MBC1Ram,
+    RomMBCType::MBC1RamBattery,
+    RomMBCType::MBC2,
+    RomMBCType::MBC2Battery,
+    RomMBCType::MBC3TimerBattery,
+    RomMBCType::MBC3TimerRamBattery,
+    RomMBCType::MBC3,
+    RomMBCType::MBC3Ram,
+    RomMBCType::MBC3RamBattery,
+    RomMBCType::MBC5,
+    RomMBCType::MBC5Ram,
+    RomMBCType::MBC5RamBattery,
+    RomMBCType::MBC5Rumble,
+    RomMBCType::MBC5RumbleRam,
+    RomMBCType::MBC5RumbleRamBattery,
+];
+
+impl RomMBCType {
+    /// Whether this MBC type is emulated.
+    pub fn is_supported(self) -> bool {
+        SUPPORTED_MBC_TYPES.contains(&self)
+    }
+
+    /// The controller family of this cartridge type.
+    pub fn family(self) -> RomMBCFamily {
+        match self {
+            RomMBCType::NoMBC | RomMBCType::NoMBCRam | RomMBCType::NoMBCRamBattery => {
+                RomMBCFamily::NoMBC
+            }
+            RomMBCType::MBC1 | RomMBCType::MBC1Ram | RomMBCType::MBC1RamBattery => {
+                RomMBCFamily::MBC1
+            }
+            RomMBCType::MBC2 | RomMBCType::MBC2Battery => RomMBCFamily::MBC2,
+            RomMBCType::MMM01 | RomMBCType::MMM01Ram | RomMBCType::MMM01RamBattery => {
+                RomMBCFamily::MMM01
+            }
+            RomMBCType::MBC3TimerBattery
+            | RomMBCType::MBC3TimerRamBattery
+            | RomMBCType::MBC3
+            | RomMBCType::MBC3Ram
+            | RomMBCType::MBC3RamBattery => RomMBCFamily::MBC3,
+            RomMBCType::MBC5
+            | RomMBCType::MBC5Ram
+            | RomMBCType::MBC5RamBattery
+            | RomMBCType::MBC5Rumble
+            | RomMBCType::MBC5RumbleRam
+            | RomMBCType::MBC5RumbleRamBattery => RomMBCFamily::MBC5,
+            RomMBCType::MBC6 => RomMBCFamily::MBC6,
+            RomMBCType::MBC7SensorRumbleRamBattery => RomMBCFamily::MBC7,
+            RomMBCType::PocketCamera => RomMBCFamily::PocketCamera,
+            RomMBCType::BandaiTama5 => RomMBCFamily::BandaiTama5,
+            RomMBCType::HuC3 => RomMBCFamily::HuC3,
+            RomMBCType::HuC1RamBattery => RomMBCFamily::HuC1,
+            RomMBCType::Unknown(_) => RomMBCFamily::Unknown,
+        }
+    }
+
+    /// Whether the cartridge has external ram. MBC2's built-in ram counts as such.
+    pub fn has_ram(self) -> bool {
+        matches!(
+            self,
+            RomMBCType::NoMBCRam
+                | RomMBCType::NoMBCRamBattery
+                | RomMBCType::MBC1Ram
+                | RomMBCType::MBC1RamBattery
+                | RomMBCType::MBC2
+                | RomMBCType::MBC2Battery
+                | RomMBCType::MMM01Ram
+                | RomMBCType::MMM01RamBattery
+                | RomMBCType::MBC3TimerRamBattery
+                | RomMBCType::MBC3Ram
+                | RomMBCType::MBC3RamBattery
+                | RomMBCType::MBC5Ram
+                | RomMBCType::MBC5RamBattery
+                | RomMBCType::MBC5RumbleRam
+                | RomMBCType::MBC5RumbleRamBattery
+                | RomMBCType::MBC7SensorRumbleRamBattery
+                | RomMBCType::PocketCamera
+                | RomMBCType::HuC3
+                | RomMBCType::HuC1RamBattery
+        )
+    }
+
+    /// Whether the cartridge has battery-backed external ram, which should be persisted.
+    pub fn has_battery(self) -> bool {
+        matches!(
+            self,
+            RomMBCType::NoMBCRamBattery
+                | RomMBCType::MBC1RamBattery
+                | RomMBCType::MBC2Battery
+                | RomMBCType::MMM01RamBattery
+                | RomMBCType::MBC3TimerBattery
+                | RomMBCType::MBC3TimerRamBattery
+                | RomMBCType::MBC3RamBattery
+                | RomMBCType::MBC5RamBattery
+                | RomMBCType::MBC5RumbleRamBattery
+                | RomMBCType::MBC7SensorRumbleRamBattery
+                | RomMBCType::PocketCamera
+                | RomMBCType::HuC3
+                | RomMBCType::HuC1RamBattery
+        )
+    }
+
+    /// Whether the cartridge has a real-time clock.
+    pub fn has_rtc(self) -> bool {
+        matches!(
+            self,
+            RomMBCType::MBC3TimerBattery
+                | RomMBCType::MBC3TimerRamBattery
+                | RomMBCType::BandaiTama5
+                | RomMBCType::HuC3
+        )
+    }
+
+    /// Whether the cartridge has a rumble motor.
+    pub fn has_rumble(self) -> bool {
+        matches!(
+            self,
+            RomMBCType::MBC5Rumble
+                | RomMBCType::MBC5RumbleRam
+                | RomMBCType::MBC5RumbleRamBattery
+                | RomMBCType::MBC7SensorRumbleRamBattery
+        )
+    }
+}
+
+/// The publisher of a game, decoded from the licensee codes in the header. Only the more common publishers are
+/// known, others keep their raw code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Licensee {
+    None,
+    Nintendo,
+    Capcom,
+    ElectronicArts,
+    HudsonSoft,
+    Kemco,
+    Seta,
+    Bandai,
+    OceanAcclaim,
+    Konami,
+    Taito,
+    Banpresto,
+    UbiSoft,
+    Atlus,
+    Irem,
+    Acclaim,
+    Activision,
+    AmericanSammy,
+    Ljn,
+    Titus,
+    Virgin,
+    Ocean,
+    Infogrames,
+    Interplay,
+    Broderbund,
+    Thq,
+    Chunsoft,
+    Varie,
+    Jaleco,
+    Tecmo,
+    Imagineer,
+    Takara,
+    Namco,
+    SquareEnix,
+    HalLaboratory,
+    Sunsoft,
+    Sammy,
+    Squaresoft,
+    Koei,
+    Tomy,
+    Natsume,
+    /// A new licensee code (two ASCII characters) that isn't known.
+    UnknownNew([u8; 2]),
+    /// An old licensee code that isn't known.
+    UnknownOld(u8),
+}
+
+impl Licensee {
+    /// Old licensee code which means the new licensee code should be used instead.
+    pub const USE_NEW_CODE: u8 = 0x33;
+
+    /// Decodes a new licensee code, given as its two ASCII characters.
+    pub fn from_new_code(code: [u8; 2]) -> Self {
+        match &code {
+            b"00" => Licensee::None,
+            b"01" | b"31" => Licensee::Nintendo,
+            b"08" => Licensee::Capcom,
+            b"13" | b"69" => Licensee::ElectronicArts,
+            b"18" | b"38" => Licensee::HudsonSoft,
+            b"28" => Licensee::Kemco,
+            b"29" => Licensee::Seta,
+            b"32" => Licensee::Bandai,
+            b"33" | b"93" => Licensee::OceanAcclaim,
+            b"34" | b"54" | b"A4" => Licensee::Konami,
+            b"37" => Licensee::Taito,
+            b"39" => Licensee::Banpresto,
+            b"41" => Licensee::UbiSoft,
+            b"42" => Licensee::Atlus,
+            b"49" => Licensee::Irem,
+            b"51" => Licensee::Acclaim,
+            b"52" => Licensee::Activision,
+            b"53" => Licensee::AmericanSammy,
+            b"56" => Licensee::Ljn,
+            b"60" => Licensee::Titus,
+            b"61" => Licensee::Virgin,
+            b"67" => Licensee::Ocean,
+            b"70" => Licensee::Infogrames,
+            b"71" => Licensee::Interplay,
+            b"72" => Licensee::Broderbund,
+            b"78" => Licensee::Thq,
+            b"91" => Licensee::Chunsoft,
+            b"95" => Licensee::Varie,
+            _ => Licensee::UnknownNew(code),
+        }
+    }
+
+    /// Decodes an old licensee code. This shouldn't be [Licensee::USE_NEW_CODE].
+    pub fn from_old_code(code: u8) -> Self {
+        match code {
+            0x00 => Licensee::None,
+            0x01 | 0x31 => Licensee::Nintendo,
+            0x08 | 0x38 => Licensee::Capcom,
+            0x13 | 0x69 => Licensee::ElectronicArts,
+            0x18 => Licensee::HudsonSoft,
+            0x7F | 0xC2 => Licensee::Kemco,
+            0x29 => Licensee::Seta,
+            0x32 | 0xA2 | 0xB2 => Licensee::Bandai,
+            0x34 | 0xA4 => Licensee::Konami,
+            0xC0 | 0xD0 => Licensee::Taito,
+            0x39 | 0x9D | 0xD9 => Licensee::Banpresto,
+            0x41 => Licensee::UbiSoft,
+            0x42 | 0xEB => Licensee::Atlus,
+            0x49 => Licensee::Irem,
+            0x51 | 0xB0 => Licensee::Acclaim,
+            0x52 => Licensee::Activision,
+            0x53 => Licensee::AmericanSammy,
+            0x56 | 0xDB | 0xFF => Licensee::Ljn,
+            0x60 => Licensee::Titus,
+            0x1F | 0x4A | 0x61 => Licensee::Virgin,
+            0x67 => Licensee::Ocean,
+            0x30 | 0x70 => Licensee::Infogrames,
+            0x71 => Licensee::Interplay,
+            0x72 | 0xAA => Licensee::Broderbund,
+            0x78 => Licensee::Thq,
+            0x91 => Licensee::Chunsoft,
+            0x95 | 0xE3 => Licensee::Varie,
+            0x0A | 0xE0 => Licensee::Jaleco,
+            0x9B => Licensee::Tecmo,
+            0x9C => Licensee::Imagineer,
+            0xA7 => Licensee::Takara,
+            0xAF => Licensee::Namco,
+            0xB4 => Licensee::SquareEnix,
+            0xB6 => Licensee::HalLaboratory,
+            0xBB => Licensee::Sunsoft,
+            0xBF => Licensee::Sammy,
+            0xC3 => Licensee::Squaresoft,
+            0xC8 => Licensee::Koei,
+            0xDA => Licensee::Tomy,
+            0xE9 => Licensee::Natsume,
+            _ => Licensee::UnknownOld(code),
+        }
+    }
+
+    /// The name of the publisher, or "Unknown" if it isn't known.
+    pub fn name(self) -> &'static str {
+        match self {
+            Licensee::None => "None",
+            Licensee::Nintendo => "Nintendo",
+            Licensee::Capcom => "Capcom",
+            Licensee::ElectronicArts => "Electronic Arts",
+            Licensee::HudsonSoft => "Hudson Soft",
+            Licensee::Kemco => "Kemco",
+            Licensee::Seta => "Seta",
+            Licensee::Bandai => "Bandai",
+            Licensee::OceanAcclaim => "Ocean/Acclaim",
+            Licensee::Konami => "Konami",
+            Licensee::Taito => "Taito",
+            Licensee::Banpresto => "Banpresto",
+            Licensee::UbiSoft => "Ubi Soft",
+            Licensee::Atlus => "Atlus",
+            Licensee::Irem => "Irem",
+            Licensee::Acclaim => "Acclaim",
+            Licensee::Activision => "Activision",
+            Licensee::AmericanSammy => "American Sammy",
+            Licensee::Ljn => "LJN",
+            Licensee::Titus => "Titus",
+            Licensee::Virgin => "Virgin",
+            Licensee::Ocean => "Ocean",
+            Licensee::Infogrames => "Infogrames",
+            Licensee::Interplay => "Interplay",
+            Licensee::Broderbund => "Broderbund",
+            Licensee::Thq => "THQ",
+            Licensee::Chunsoft => "Chunsoft",
+            Licensee::Varie => "Varie",
+            Licensee::Jaleco => "Jaleco",
+            Licensee::Tecmo => "Tecmo",
+            Licensee::Imagineer => "Imagineer",
+            Licensee::Takara => "Takara",
+            Licensee::Namco => "Namco",
+            Licensee::SquareEnix => "Square Enix",
+            Licensee::HalLaboratory => "HAL Laboratory",
+            Licensee::Sunsoft => "Sunsoft",
+            Licensee::Sammy => "Sammy",
+            Licensee::Squaresoft => "Squaresoft",
+            Licensee::Koei => "Koei",
+            Licensee::Tomy => "Tomy",
+            Licensee::Natsume => "Natsume",
+            Licensee::UnknownNew(_) | Licensee::UnknownOld(_) => "Unknown",
+        }
+    }
+}
+
+impl std::fmt::Display for Licensee {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Licensee::UnknownNew([a, b]) => {
+                write!(f, "Unknown ({}{})", char::from(*a), char::from(*b))
+            }
+            Licensee::UnknownOld(code) => write!(f, "Unknown ({:#04X})", code),
+            _ => write!(f, "{}", self.name()),
+        }
+    }
 }
 
 /// Represents information regarding a [Rom].
@@ -37,8 +472,8 @@ pub struct RomHeader {
     pub manufacturer: u32, // todo: turn into an enum
     /// Whether this rom supports CGB, and if it does, whether it is CGB only or not.
     pub cgb: RomCgbStatus,
-    /// The license code.
-    pub license: u16, // todo: turn into an enum
+    /// The new license code, as two ASCII characters. Only used if `old_license` is [Licensee::USE_NEW_CODE].
+    pub license: u16,
     /// Wether the game supports SGB functions.
     pub sgb: RomSgbStatus,
     /// Specifies which MBC is used in this rom, if any.
@@ -50,16 +485,50 @@ pub struct RomHeader {
     /// Wether this version of the game was sold in Japan or not.
     pub japanese: bool,
     /// The old license code.
-    pub old_license: u8, // todo: turn into an enum
+    pub old_license: u8,
+    /// The publisher, decoded from the license codes.
+    pub licensee: Licensee,
     /// The version number of the rom.
     pub rom_version: u8,
     /// The header checksum.
     pub checksum: u8,
-    /// The rom checksum.
+    /// The global rom checksum. Not checked by the hardware.
     pub rom_checksum: u16,
 }
 
 impl RomHeader {
+    /// The name of the game's publisher, or "Unknown" if it isn't known.
+    pub fn publisher(&self) -> &str {
+        self.licensee.name()
+    }
+
+    /// Computes the header checksum of a rom over 0x0134..=0x014C, the same way the boot rom does. The rom must be
+    /// at least 0x014D bytes long.
+    pub fn compute_header_checksum(rom: &[u8]) -> u8 {
+        rom[0x0134..=0x014C]
+            .iter()
+            .fold(0u8, |x, byte| x.wrapping_sub(*byte).wrapping_sub(1))
+    }
+
+    /// Whether the header checksum matches the one computed from `full_rom`. The boot rom locks up if it doesn't.
+    pub fn verify_header_checksum(&self, full_rom: &[u8]) -> bool {
+        full_rom.len() > 0x014C && Self::compute_header_checksum(full_rom) == self.checksum
+    }
+
+    /// Computes the global checksum of a rom: the sum of every byte, except the two bytes of the checksum itself.
+    pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+        rom.iter()
+            .enumerate()
+            .filter(|(address, _)| !matches!(address, 0x014E | 0x014F))
+            .fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16))
+    }
+
+    /// Whether the global checksum matches the one computed from `full_rom`. A mismatch usually means a truncated
+    /// or corrupted dump.
+    pub fn verify_global_checksum(&self, full_rom: &[u8]) -> bool {
+        Self::compute_global_checksum(full_rom) == self.rom_checksum
+    }
+
     /// Tries to decode a [RomHeader] instance from bytes.
     ///
     /// Exactly [HEADER_LEN] bytes are expected and an error is returned if the input length is wrong.
@@ -76,7 +545,9 @@ impl RomHeader {
             );
         }
 
-        let title: String = bytes[0..16]
+        // the header starts at the last byte of the logo. the title is at 0x0134..=0x0143, where newer roms have the
+        // cgb flag instead, which is never ascii
+        let title: String = bytes[1..=16]
             .iter()
             .map_while(|x| {
                 if x.is_ascii() && !x.is_ascii_control() {
@@ -105,11 +576,39 @@ impl RomHeader {
             0x01 => RomMBCType::MBC1,
             0x02 => RomMBCType::MBC1Ram,
             0x03 => RomMBCType::MBC1RamBattery,
-            _ => RomMBCType::Unknown,
+            0x05 => RomMBCType::MBC2,
+            0x06 => RomMBCType::MBC2Battery,
+            0x08 => RomMBCType::NoMBCRam,
+            0x09 => RomMBCType::NoMBCRamBattery,
+            0x0B => RomMBCType::MMM01,
+            0x0C => RomMBCType::MMM01Ram,
+            0x0D => RomMBCType::MMM01RamBattery,
+            0x0F => RomMBCType::MBC3TimerBattery,
+            0x10 => RomMBCType::MBC3TimerRamBattery,
+            0x11 => RomMBCType::MBC3,
+            0x12 => RomMBCType::MBC3Ram,
+            0x13 => RomMBCType::MBC3RamBattery,
+            0x19 => RomMBCType::MBC5,
+            0x1A => RomMBCType::MBC5Ram,
+            0x1B => RomMBCType::MBC5RamBattery,
+            0x1C => RomMBCType::MBC5Rumble,
+            0x1D => RomMBCType::MBC5RumbleRam,
+            0x1E => RomMBCType::MBC5RumbleRamBattery,
+            0x20 => RomMBCType::MBC6,
+            0x22 => RomMBCType::MBC7SensorRumbleRamBattery,
+            0xFC => RomMBCType::PocketCamera,
+            0xFD => RomMBCType::BandaiTama5,
+            0xFE => RomMBCType::HuC3,
+            0xFF => RomMBCType::HuC1RamBattery,
+            byte => RomMBCType::Unknown(byte),
+        };
+        let rom_size = match reader.read_le::<u8>()? {
+            size @ 0x00..=0x08 => 32 * 2usize.pow(size as u32) * bytesize::KIB as usize,
+            size => anyhow::bail!("Invalid rom size in header ({:#04X})", size),
         };
-        let rom_size = 32 * 2usize.pow(reader.read_le::<u8>()? as u32) * bytesize::KIB as usize;
         let ram_size = match reader.read_le::<u8>()? {
             0x00 => 0,
+            0x01 => 2,   // unofficial, smaller than a bank
             0x02 => 8,   // 1 bank
             0x03 => 32,  // 4 banks of 8kb
             0x04 => 128, // 16 banks of 8kb
@@ -120,7 +619,13 @@ impl RomHeader {
         let old_license = reader.read_le::<u8>()?;
         let rom_version = reader.read_le::<u8>()?;
         let checksum = reader.read_le::<u8>()?;
-        let rom_checksum: u16 = reader.read_le()?;
+        // unlike everything else, the global checksum is big endian
+        let rom_checksum: u16 = reader.read_be()?;
+        let licensee = if old_license == Licensee::USE_NEW_CODE {
+            Licensee::from_new_code(license.to_le_bytes())
+        } else {
+            Licensee::from_old_code(old_license)
+        };
 
         Ok(Self {
             title,
@@ -133,6 +638,7 @@ impl RomHeader {
             ram_size,
             japanese,
             old_license,
+            licensee,
             rom_version,
             checksum,
             rom_checksum,
@@ -140,11 +646,75 @@ impl RomHeader {
     }
 }
 
+/// Formats a rom or ram size in KiB, or MiB if it's at least that big. Sizes are always powers of two.
+fn format_size(size: usize) -> String {
+    let (kib, mib) = (bytesize::KIB as usize, bytesize::MIB as usize);
+    if size >= mib {
+        format!("{} MiB", size / mib)
+    } else {
+        format!("{} KiB", size / kib)
+    }
+}
+
+impl std::fmt::Display for RomHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hardware: Vec<_> = [
+            (self.rom_type.has_ram(), "ram"),
+            (self.rom_type.has_battery(), "battery"),
+            (self.rom_type.has_rtc(), "rtc"),
+            (self.rom_type.has_rumble(), "rumble"),
+        ]
+        .into_iter()
+        .filter_map(|(present, name)| present.then_some(name))
+        .collect();
+
+        writeln!(f, "title:     {}", self.title)?;
+        writeln!(f, "publisher: {}", self.licensee)?;
+        if hardware.is_empty() {
+            writeln!(f, "cartridge: {}", self.rom_type)?;
+        } else {
+            writeln!(f, "cartridge: {} ({})", self.rom_type, hardware.join(", "))?;
+        }
+        writeln!(f, "rom size:  {}", format_size(self.rom_size))?;
+        if self.ram_size == 0 {
+            writeln!(f, "ram size:  none")?;
+        } else {
+            writeln!(f, "ram size:  {}", format_size(self.ram_size))?;
+        }
+        writeln!(f, "cgb:       {}", self.cgb)?;
+        writeln!(f, "sgb:       {}", self.sgb)?;
+        writeln!(
+            f,
+            "region:    {}",
+            if self.japanese { "Japan" } else { "overseas" }
+        )?;
+        write!(f, "version:   {}", self.rom_version)
+    }
+}
+
 pub trait MemoryBankController {
     fn read(&self, address: u16) -> u8;
     fn write(&mut self, address: u16, data: u8);
     fn external_read(&self, address: u16) -> u8;
     fn external_write(&mut self, address: u16, data: u8);
+    fn external_ram(&self) -> &[u8];
+    fn external_ram_mut(&mut self) -> &mut [u8];
+    /// Puts the controller back in its power-on state. External ram is only cleared if `clear_external` is set.
+    fn reset(&mut self, clear_external: bool);
+
+    /// Whether the rumble motor is on. Only rumble cartridges ever turn it on.
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    /// The state of the cartridge's real-time clock as stored in save files, if it has one.
+    fn rtc_save(&self) -> Option<[u8; RTC_SAVE_LEN]> {
+        None
+    }
+
+    /// Restores the real-time clock from the state returned by [MemoryBankController::rtc_save]. Does nothing for
+    /// cartridges without one.
+    fn load_rtc_save(&mut self, _save: &[u8; RTC_SAVE_LEN]) {}
 }
 
 struct NoMBC {
@@ -168,11 +738,28 @@ impl MemoryBankController for NoMBC {
     }
 
     fn external_read(&self, address: u16) -> u8 {
-        self.external[address as usize]
+        // most carts without a MBC don't have any external ram
+        self.external.get(address as usize).copied().unwrap_or(0xFF)
     }
 
     fn external_write(&mut self, address: u16, data: u8) {
-        self.external[address as usize] = data;
+        if let Some(byte) = self.external.get_mut(address as usize) {
+            *byte = data;
+        }
+    }
+
+    fn external_ram(&self) -> &[u8] {
+        &self.external
+    }
+
+    fn external_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.external
+    }
+
+    fn reset(&mut self, clear_external: bool) {
+        if clear_external {
+            self.external.fill(0xFF);
+        }
     }
 }
 
@@ -204,6 +791,31 @@ impl MBC1 {
     pub fn external_bank_count(&self) -> usize {
         self.external.len() / 0x2000
     }
+
+    /// Index into the external ram for the given address, if ram is enabled and the cartridge has any. Carts can
+    /// declare a ram-type MBC with no ram at all.
+    fn external_index(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.external.is_empty() {
+            return None;
+        }
+
+        let ram_bank = if self.alt_mode {
+            self.bank2 as usize
+        } else {
+            0
+        };
+
+        let shift_amount = (self.external_bank_count().max(1) - 1).leading_zeros();
+        let mask = if shift_amount == usize::BITS {
+            0
+        } else {
+            usize::MAX >> shift_amount
+        };
+        let ram_bank = ram_bank & mask;
+
+        // 2KiB rams are mirrored through the bank
+        Some((ram_bank * 0x2000 + address as usize) % self.external.len())
+    }
 }
 
 impl MemoryBankController for MBC1 {
@@ -271,53 +883,505 @@ impl MemoryBankController for MBC1 {
         }
     }
 
+    fn external_read(&self, address: u16) -> u8 {
+        match self.external_index(address) {
+            Some(index) => self.external[index],
+            None => 0xFF,
+        }
+    }
+
+    fn external_write(&mut self, address: u16, data: u8) {
+        if let Some(index) = self.external_index(address) {
+            self.external[index] = data;
+        }
+    }
+
+    fn external_ram(&self) -> &[u8] {
+        &self.external
+    }
+
+    fn external_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.external
+    }
+
+    fn reset(&mut self, clear_external: bool) {
+        self.bank1 = 1;
+        self.bank2 = 0;
+        self.ram_enabled = false;
+        self.alt_mode = false;
+        if clear_external {
+            self.external.fill(0xFF);
+        }
+    }
+}
+
+/// Size of the ram built into MBC2, in 4-bit cells.
+const MBC2_RAM_SIZE: usize = 512;
+
+struct MBC2 {
+    rom: Box<[u8]>,      // Maximum 256KiB
+    external: Box<[u8]>, // 512 4-bit cells, one per byte
+    rom_bank: u8,
+    ram_enabled: bool,
+}
+
+impl MBC2 {
+    pub fn new(rom: Box<[u8]>) -> Self {
+        Self {
+            rom,
+            external: vec![0xFF; MBC2_RAM_SIZE].into(),
+            rom_bank: 1,
+            ram_enabled: false,
+        }
+    }
+
+    pub fn rom_bank_count(&self) -> usize {
+        self.rom.len() / 0x4000
+    }
+}
+
+impl MemoryBankController for MBC2 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                let rom_bank = self.rom_bank as usize % self.rom_bank_count();
+                self.rom[rom_bank * 0x4000 + address as usize - 0x4000]
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        // bit 8 of the address selects between the ram enable and the rom bank registers
+        match address {
+            0x0000..=0x3FFF if address & 0x0100 == 0 => {
+                self.ram_enabled = (data & 0x0F) == 0x0A;
+            }
+            0x0000..=0x3FFF => {
+                let data = data & 0b0000_1111;
+                self.rom_bank = if data == 0 { 1 } else { data };
+            }
+            _ => (),
+        }
+    }
+
     fn external_read(&self, address: u16) -> u8 {
         if !self.ram_enabled {
             return 0xFF;
         }
 
-        let ram_bank = if self.alt_mode {
-            self.bank2 as usize
-        } else {
-            0
+        // only the lower nibble exists, the upper one reads as 1s. the ram is mirrored through the whole area
+        0xF0 | self.external[address as usize % MBC2_RAM_SIZE]
+    }
+
+    fn external_write(&mut self, address: u16, data: u8) {
+        if self.ram_enabled {
+            self.external[address as usize % MBC2_RAM_SIZE] = data & 0x0F;
+        }
+    }
+
+    fn external_ram(&self) -> &[u8] {
+        &self.external
+    }
+
+    fn external_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.external
+    }
+
+    fn reset(&mut self, clear_external: bool) {
+        self.rom_bank = 1;
+        self.ram_enabled = false;
+        if clear_external {
+            self.external.fill(0xFF);
+        }
+    }
+}
+
+/// Real-time clock of MBC3 cartridges, backed by the system clock so it keeps running while the game does.
+struct Rtc {
+    /// When the clock would have read zero, while it's running.
+    base: SystemTime,
+    /// The seconds the clock stopped at, while it's halted.
+    halted: Option<u64>,
+    /// Whether the day counter overflowed, which stays set until cleared by the game.
+    carry: bool,
+    /// The registers as of the last latch: seconds, minutes, hours, lower 8 bits of the day counter and the day
+    /// high register.
+    latched: [u8; 5],
+    /// Whether 0x00 was written to the latch register, so writing 0x01 latches the clock.
+    latch_armed: bool,
+}
+
+impl Rtc {
+    fn new() -> Self {
+        let mut rtc = Self {
+            base: SystemTime::now(),
+            halted: None,
+            carry: false,
+            latched: [0; 5],
+            latch_armed: false,
         };
+        rtc.latch();
+        rtc
+    }
 
-        let shift_amount = (self.external_bank_count() - 1).leading_zeros();
-        let mask = if shift_amount == usize::BITS {
-            0
+    /// The seconds the clock is at.
+    fn seconds(&self) -> u64 {
+        self.halted.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(self.base)
+                .unwrap_or_default()
+                .as_secs()
+        })
+    }
+
+    /// The current value of the clock registers.
+    fn registers(&self) -> [u8; 5] {
+        let seconds = self.seconds();
+        let days = seconds / 86400;
+        let carry = self.carry || days > 0x1FF;
+        [
+            (seconds % 60) as u8,
+            (seconds / 60 % 60) as u8,
+            (seconds / 3600 % 24) as u8,
+            days as u8,
+            ((days >> 8) & 1) as u8 | (self.halted.is_some() as u8) << 6 | (carry as u8) << 7,
+        ]
+    }
+
+    fn latch(&mut self) {
+        self.latched = self.registers();
+    }
+
+    /// Handles a write to the latch register (0x6000..=0x7FFF): writing 0x00 and then 0x01 latches the clock.
+    fn write_latch(&mut self, data: u8) {
+        if self.latch_armed && data == 0x01 {
+            self.latch();
+        }
+
+        self.latch_armed = data == 0x00;
+    }
+
+    /// Writes to one of the clock registers, indexed like [Rtc::latched]. The clock is set to the new value.
+    fn write(&mut self, register: usize, data: u8) {
+        const MASKS: [u8; 5] = [0x3F, 0x3F, 0x1F, 0xFF, 0xC1];
+        let mut registers = self.registers();
+        registers[register] = data & MASKS[register];
+        self.latched[register] = registers[register];
+        self.set(registers, 0);
+    }
+
+    /// Sets the clock to the given registers, plus `elapsed` seconds if it isn't halted.
+    fn set(&mut self, registers: [u8; 5], elapsed: u64) {
+        let [seconds, minutes, hours, days_low, day_high] = registers.map(u64::from);
+        let days = days_low | (day_high & 1) << 8;
+        let seconds = seconds + minutes * 60 + hours * 3600 + days * 86400;
+
+        self.carry = day_high & 0x80 != 0;
+        if day_high & 0x40 != 0 {
+            self.halted = Some(seconds);
         } else {
-            usize::MAX >> shift_amount
-        };
-        let ram_bank = ram_bank & mask;
+            self.halted = None;
+            self.base = SystemTime::now() - Duration::from_secs(seconds + elapsed);
+        }
+    }
+
+    /// The clock state as stored in save files, see [RTC_SAVE_LEN].
+    fn to_save(&self) -> [u8; RTC_SAVE_LEN] {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
 
-        let ram_bank_start = ram_bank * 0x2000;
-        self.external[ram_bank_start + address as usize]
+        let mut save = [0; RTC_SAVE_LEN];
+        let registers = self.registers().into_iter().chain(self.latched);
+        for (word, register) in save.chunks_exact_mut(4).zip(registers) {
+            word.copy_from_slice(&(register as u32).to_le_bytes());
+        }
+        save[40..].copy_from_slice(&timestamp.to_le_bytes());
+        save
+    }
+
+    /// Restores the clock from a save made by [Rtc::to_save]. A running clock also catches up with the time that
+    /// passed since the save was made.
+    fn load_save(&mut self, save: &[u8; RTC_SAVE_LEN]) {
+        let word = |index: usize| save[index * 4];
+        let registers = [0, 1, 2, 3, 4].map(word);
+        self.latched = [5, 6, 7, 8, 9].map(word);
+
+        let timestamp = u64::from_le_bytes(save[40..].try_into().unwrap());
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.set(registers, now.saturating_sub(timestamp));
+    }
+}
+
+struct MBC3 {
+    rom: Box<[u8]>,      // Maximum 2MiB
+    external: Box<[u8]>, // Maximum 32KiB
+    rtc: Option<Rtc>,
+    rom_bank: u8,
+    /// Ram bank (0x00..=0x03) or clock register (0x08..=0x0C) mapped at 0xA000..=0xBFFF.
+    ram_bank: u8,
+    ram_enabled: bool,
+}
+
+impl MBC3 {
+    pub fn new(rom: Box<[u8]>, external: Box<[u8]>, has_rtc: bool) -> Self {
+        Self {
+            rom,
+            external,
+            rtc: has_rtc.then(Rtc::new),
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+        }
+    }
+
+    pub fn rom_bank_count(&self) -> usize {
+        self.rom.len() / 0x4000
+    }
+
+    /// Index into the external ram for the given address, if ram is enabled and a ram bank is mapped.
+    fn external_index(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.external.is_empty() || self.ram_bank > 0x03 {
+            return None;
+        }
+
+        Some((self.ram_bank as usize * 0x2000 + address as usize) % self.external.len())
+    }
+}
+
+impl MemoryBankController for MBC3 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                let rom_bank = self.rom_bank as usize % self.rom_bank_count();
+                self.rom[rom_bank * 0x4000 + address as usize - 0x4000]
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0x0000..=0x1FFF => {
+                // enables both the ram and the clock
+                self.ram_enabled = (data & 0x0F) == 0x0A;
+            }
+            0x2000..=0x3FFF => {
+                let data = data & 0b0111_1111;
+                self.rom_bank = if data == 0 { 1 } else { data };
+            }
+            0x4000..=0x5FFF => {
+                self.ram_bank = data & 0x0F;
+            }
+            0x6000..=0x7FFF => {
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write_latch(data);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn external_read(&self, address: u16) -> u8 {
+        match (&self.rtc, self.ram_bank) {
+            (Some(rtc), register @ 0x08..=0x0C) if self.ram_enabled => {
+                rtc.latched[register as usize - 0x08]
+            }
+            _ => match self.external_index(address) {
+                Some(index) => self.external[index],
+                None => 0xFF,
+            },
+        }
     }
 
     fn external_write(&mut self, address: u16, data: u8) {
-        if !self.ram_enabled {
-            return;
+        match (&mut self.rtc, self.ram_bank) {
+            (Some(rtc), register @ 0x08..=0x0C) if self.ram_enabled => {
+                rtc.write(register as usize - 0x08, data)
+            }
+            _ => {
+                if let Some(index) = self.external_index(address) {
+                    self.external[index] = data;
+                }
+            }
         }
+    }
 
-        let ram_bank = if self.alt_mode {
-            self.bank2 as usize
-        } else {
-            0
-        };
+    fn external_ram(&self) -> &[u8] {
+        &self.external
+    }
 
-        let shift_amount = (self.external_bank_count() - 1).leading_zeros();
-        let mask = if shift_amount == usize::BITS {
-            0
-        } else {
-            usize::MAX >> shift_amount
-        };
-        let ram_bank = ram_bank & mask;
+    fn external_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.external
+    }
+
+    fn reset(&mut self, clear_external: bool) {
+        // the clock has its own battery, so it keeps running through resets
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+        self.ram_enabled = false;
+        if clear_external {
+            self.external.fill(0xFF);
+        }
+    }
+
+    fn rtc_save(&self) -> Option<[u8; RTC_SAVE_LEN]> {
+        self.rtc.as_ref().map(Rtc::to_save)
+    }
+
+    fn load_rtc_save(&mut self, save: &[u8; RTC_SAVE_LEN]) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load_save(save);
+        }
+    }
+}
+
+struct MBC5 {
+    rom: Box<[u8]>,      // Maximum 8MiB
+    external: Box<[u8]>, // Maximum 128KiB
+    /// 9-bit rom bank. Unlike MBC1, bank 0 can be mapped at 0x4000..=0x7FFF too.
+    rom_bank: u16,
+    ram_bank: u8,
+    ram_enabled: bool,
+    /// Whether bit 3 of the ram bank register drives a rumble motor instead of selecting banks.
+    has_rumble: bool,
+    rumble: bool,
+}
+
+impl MBC5 {
+    pub fn new(rom: Box<[u8]>, external: Box<[u8]>, has_rumble: bool) -> Self {
+        Self {
+            rom,
+            external,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            has_rumble,
+            rumble: false,
+        }
+    }
+
+    pub fn rom_bank_count(&self) -> usize {
+        self.rom.len() / 0x4000
+    }
+
+    /// Index into the external ram for the given address, if ram is enabled and the cartridge has any.
+    fn external_index(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.external.is_empty() {
+            return None;
+        }
 
-        let ram_bank_start = ram_bank * 0x2000;
-        self.external[ram_bank_start + address as usize] = data;
+        Some((self.ram_bank as usize * 0x2000 + address as usize) % self.external.len())
     }
 }
 
+impl MemoryBankController for MBC5 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                let rom_bank = self.rom_bank as usize % self.rom_bank_count();
+                self.rom[rom_bank * 0x4000 + address as usize - 0x4000]
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0x0000..=0x1FFF => {
+                self.ram_enabled = (data & 0x0F) == 0x0A;
+            }
+            0x2000..=0x2FFF => {
+                // lower 8 bits of the rom bank
+                self.rom_bank = (self.rom_bank & 0x100) | data as u16;
+            }
+            0x3000..=0x3FFF => {
+                // bit 8 of the rom bank
+                self.rom_bank = (self.rom_bank & 0xFF) | ((data as u16 & 1) << 8);
+            }
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.rumble = data & 0b0000_1000 != 0;
+                    self.ram_bank = data & 0b0000_0111;
+                } else {
+                    self.ram_bank = data & 0b0000_1111;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn external_read(&self, address: u16) -> u8 {
+        match self.external_index(address) {
+            Some(index) => self.external[index],
+            None => 0xFF,
+        }
+    }
+
+    fn external_write(&mut self, address: u16, data: u8) {
+        if let Some(index) = self.external_index(address) {
+            self.external[index] = data;
+        }
+    }
+
+    fn external_ram(&self) -> &[u8] {
+        &self.external
+    }
+
+    fn external_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.external
+    }
+
+    fn reset(&mut self, clear_external: bool) {
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+        self.ram_enabled = false;
+        self.rumble = false;
+        if clear_external {
+            self.external.fill(0xFF);
+        }
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
+}
+
+/// A tiny hand-assembled program, meant to be run without a boot rom through
+/// [Gameboy::new_from_bytes_no_validation](crate::gameboy::Gameboy::new_from_bytes_no_validation) as a smoke test.
+///
+/// It fills tile 0 with color 3, points the whole background tilemap at it, sets BGP to 0xE4 and turns the LCD
+/// on. Once a frame is rendered, every pixel of the screen should have shade 3.
+pub const SMOKE_TEST_PROGRAM: [u8; 34] = [
+    0x21, 0x00, 0x80, // LD HL, 0x8000
+    0x3E, 0xFF, // LD A, 0xFF
+    0x06, 0x10, // LD B, 0x10
+    0x22, // tile_loop: LD (HL+), A
+    0x05, // DEC B
+    0x20, 0xFC, // JR NZ, tile_loop
+    0x21, 0x00, 0x98, // LD HL, 0x9800
+    0x01, 0x00, 0x04, // LD BC, 0x0400
+    0xAF, // map_loop: XOR A
+    0x22, // LD (HL+), A
+    0x0B, // DEC BC
+    0x78, // LD A, B
+    0xB1, // OR C
+    0x20, 0xF9, // JR NZ, map_loop
+    0x3E, 0xE4, // LD A, 0xE4
+    0xE0, 0x47, // LDH (BGP), A
+    0x3E, 0x91, // LD A, 0x91
+    0xE0, 0x40, // LDH (LCDC), A
+    0x18, 0xFE, // end: JR end
+];
+
 /// Represents a gameboy game rom.
 pub struct Rom {
     header: RomHeader,
@@ -326,37 +1390,163 @@ pub struct Rom {
 
 impl Rom {
     pub fn try_from_bytes<'a, B>(bytes: B) -> anyhow::Result<Self>
+    where
+        B: Into<Cow<'a, [u8]>>,
+    {
+        Self::from_bytes(bytes, None)
+    }
+
+    /// Like [Rom::try_from_bytes], but recoverable problems are pushed into `warnings` instead of failing: a size
+    /// mismatch pads or truncates the rom to the size in the header and an unsupported MBC falls back to no MBC.
+    pub fn try_from_bytes_lenient<'a, B>(
+        bytes: B,
+        warnings: &mut Vec<String>,
+    ) -> anyhow::Result<Self>
+    where
+        B: Into<Cow<'a, [u8]>>,
+    {
+        Self::from_bytes(bytes, Some(warnings))
+    }
+
+    fn from_bytes<'a, B>(bytes: B, mut warnings: Option<&mut Vec<String>>) -> anyhow::Result<Self>
     where
         B: Into<Cow<'a, [u8]>>,
     {
         let bytes: Cow<'a, [u8]> = bytes.into();
-        if bytes.len() < 0x014F {
+        if bytes.len() <= 0x014F {
             anyhow::bail!("Rom too small to even contain a rom header");
         }
 
-        let bytes: Box<[u8]> = bytes.into_owned().into();
+        let mut bytes = bytes.into_owned();
         let header = RomHeader::try_from_bytes(&bytes[0x0133..=0x014F])?;
 
+        // plenty of homebrew and patched roms get this wrong and run fine without a boot rom, so it's never an
+        // error here, only a warning in lenient mode
+        if !header.verify_header_checksum(&bytes) {
+            if let Some(warnings) = warnings.as_mut() {
+                warnings.push(format!(
+                    "Rom header checksum {:#04X} doesn't match the computed {:#04X}",
+                    header.checksum,
+                    RomHeader::compute_header_checksum(&bytes)
+                ));
+            }
+        }
+
+        // fails in strict mode, only warns in lenient mode
+        let mut problem = |message: &str| -> anyhow::Result<()> {
+            match warnings.as_mut() {
+                Some(warnings) => {
+                    warnings.push(message.to_string());
+                    Ok(())
+                }
+                None => anyhow::bail!("{}", message),
+            }
+        };
+
         if bytes.len() != header.rom_size {
-            anyhow::bail!("Rom size doesn't match with size specified in it's header");
+            problem("Rom size doesn't match with size specified in it's header")?;
+            bytes.resize(header.rom_size, 0xFF);
         }
 
+        let bytes: Box<[u8]> = bytes.into();
         let external = vec![0xFFu8; header.ram_size].into();
 
         let mbc: Box<dyn MemoryBankController + Sync + Send> = match header.rom_type {
-            RomMBCType::NoMBC => Box::new(NoMBC::new(bytes, external)),
-            RomMBCType::MBC1 | RomMBCType::MBC1RamBattery => Box::new(MBC1::new(bytes, external)),
+            RomMBCType::NoMBC | RomMBCType::NoMBCRam | RomMBCType::NoMBCRamBattery => {
+                Box::new(NoMBC::new(bytes, external))
+            }
+            RomMBCType::MBC1 | RomMBCType::MBC1Ram | RomMBCType::MBC1RamBattery => {
+                Box::new(MBC1::new(bytes, external))
+            }
+            RomMBCType::MBC2 | RomMBCType::MBC2Battery => Box::new(MBC2::new(bytes)),
+            RomMBCType::MBC3TimerBattery
+            | RomMBCType::MBC3TimerRamBattery
+            | RomMBCType::MBC3
+            | RomMBCType::MBC3Ram
+            | RomMBCType::MBC3RamBattery => {
+                Box::new(MBC3::new(bytes, external, header.rom_type.has_rtc()))
+            }
+            RomMBCType::MBC5
+            | RomMBCType::MBC5Ram
+            | RomMBCType::MBC5RamBattery
+            | RomMBCType::MBC5Rumble
+            | RomMBCType::MBC5RumbleRam
+            | RomMBCType::MBC5RumbleRamBattery => {
+                Box::new(MBC5::new(bytes, external, header.rom_type.has_rumble()))
+            }
             _ => {
-                anyhow::bail!("MBC not supported");
+                problem("MBC not supported, falling back to no MBC")?;
+                Box::new(NoMBC::new(bytes, external))
             }
         };
 
         Ok(Self { header, mbc })
     }
 
+    /// Creates a [Rom] without validating its size or header, always without a MBC. The bytes are padded with
+    /// zeros up to 32KiB, so small hand-assembled programs can be used.
+    pub fn from_bytes_unchecked<'a, B>(bytes: B) -> anyhow::Result<Self>
+    where
+        B: Into<Cow<'a, [u8]>>,
+    {
+        let mut bytes = bytes.into().into_owned();
+        if bytes.len() < 0x8000 {
+            bytes.resize(0x8000, 0x00);
+        }
+
+        let header = RomHeader::try_from_bytes(&bytes[0x0133..=0x014F])?;
+        let mbc = Box::new(NoMBC::new(bytes.into(), Box::new([])));
+
+        Ok(Self { header, mbc })
+    }
+
     pub fn header(&self) -> &RomHeader {
         &self.header
     }
+
+    /// Writes the external ram to the given path, followed by the state of the real-time clock if the cartridge
+    /// has one. Cartridges with neither have nothing to save, so no file is written.
+    pub fn save_external_ram<P>(&self, path: P) -> anyhow::Result<()>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let mut save = self.mbc.external_ram().to_vec();
+        if let Some(rtc) = self.mbc.rtc_save() {
+            save.extend(rtc);
+        }
+
+        if !save.is_empty() {
+            std::fs::write(path, save)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the external ram from the given path. A missing file clears the ram, a short one only fills the start
+    /// of it and the rest is cleared. If the file also holds the state of the real-time clock, the clock is
+    /// restored from it.
+    pub fn load_external_ram<P>(&mut self, path: P) -> anyhow::Result<()>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let save = match std::fs::read(path) {
+            Ok(save) => save,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let external = self.mbc.external_ram_mut();
+        let len = save.len().min(external.len());
+        external[..len].copy_from_slice(&save[..len]);
+        external[len..].fill(0);
+
+        let ram_len = external.len();
+        if let Some(rtc) = save.get(ram_len..ram_len + RTC_SAVE_LEN) {
+            self.mbc.load_rtc_save(rtc.try_into().unwrap());
+        }
+
+        Ok(())
+    }
 }
 
 impl Deref for Rom {
@@ -372,3 +1562,156 @@ impl DerefMut for Rom {
         &mut self.mbc
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a rom with the given cartridge type, rom size code and ram size code, and a valid header checksum.
+    /// Every rom bank holds its own number, as a little endian word, at offset 0x1000.
+    fn cartridge(rom_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+        let len = 0x8000 << rom_size;
+        let mut bytes = vec![0; len];
+        for bank in 0..len / 0x4000 {
+            bytes[bank * 0x4000 + 0x1000] = bank as u8;
+            bytes[bank * 0x4000 + 0x1001] = (bank >> 8) as u8;
+        }
+
+        bytes[0x147] = rom_type;
+        bytes[0x148] = rom_size;
+        bytes[0x149] = ram_size;
+        bytes[0x14D] = RomHeader::compute_header_checksum(&bytes);
+        bytes
+    }
+
+    #[test]
+    fn mbc1_ram_cartridge_loads() {
+        let mut rom = Rom::try_from_bytes(cartridge(0x02, 0x02, 0x02)).unwrap();
+        assert_eq!(rom.header().rom_type, RomMBCType::MBC1Ram);
+        assert_eq!(rom.external_ram().len(), 8 * 1024);
+
+        rom.write(0x2000, 3);
+        assert_eq!(rom.read(0x5000), 3);
+
+        rom.write(0x0000, 0x0A);
+        rom.external_write(0x0010, 0x42);
+        assert_eq!(rom.external_read(0x0010), 0x42);
+    }
+
+    #[test]
+    fn mbc1_ram_cartridge_without_ram() {
+        let mut rom = Rom::try_from_bytes(cartridge(0x02, 0x00, 0x00)).unwrap();
+        assert!(rom.external_ram().is_empty());
+
+        rom.write(0x0000, 0x0A);
+        rom.write(0x6000, 0x01);
+        for address in [0x0000, 0x1FFF] {
+            rom.external_write(address, 0x42);
+            assert_eq!(rom.external_read(address), 0xFF);
+        }
+    }
+
+    #[test]
+    fn mbc1_small_ram_is_mirrored() {
+        let mut rom = Rom::try_from_bytes(cartridge(0x02, 0x00, 0x01)).unwrap();
+        assert_eq!(rom.external_ram().len(), 2 * 1024);
+
+        rom.write(0x0000, 0x0A);
+        rom.external_write(0x0000, 0x42);
+        assert_eq!(rom.external_read(0x0800), 0x42);
+    }
+
+    #[test]
+    fn rtc_only_cartridge_saves_just_the_clock() {
+        let dir = std::env::temp_dir().join(format!("abduction-rtc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("clock.sav");
+
+        let mut rom = Rom::try_from_bytes(cartridge(0x0F, 0x00, 0x00)).unwrap();
+        assert!(rom.external_ram().is_empty());
+
+        // halt the clock at 1 day, 2:03:04, then latch it
+        rom.write(0x0000, 0x0A);
+        for (register, value) in [(0x0C, 0x40), (0x08, 4), (0x09, 3), (0x0A, 2), (0x0B, 1)] {
+            rom.write(0x4000, register);
+            rom.external_write(0x0000, value);
+        }
+        rom.write(0x6000, 0x00);
+        rom.write(0x6000, 0x01);
+        rom.save_external_ram(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), RTC_SAVE_LEN as u64);
+
+        let mut loaded = Rom::try_from_bytes(cartridge(0x0F, 0x00, 0x00)).unwrap();
+        loaded.load_external_ram(&path).unwrap();
+        loaded.write(0x0000, 0x0A);
+        for (register, value) in [(0x08, 4), (0x09, 3), (0x0A, 2), (0x0B, 1), (0x0C, 0x40)] {
+            loaded.write(0x4000, register);
+            assert_eq!(loaded.external_read(0x0000), value);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cartridge_without_ram_or_clock_writes_no_save() {
+        let dir = std::env::temp_dir().join(format!("abduction-noram-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("none.sav");
+
+        let rom = Rom::try_from_bytes(cartridge(0x03, 0x00, 0x00)).unwrap();
+        rom.save_external_ram(&path).unwrap();
+        assert!(!path.exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mbc5_reaches_rom_banks_past_0xff() {
+        let mut rom = Rom::try_from_bytes(cartridge(0x19, 0x08, 0x00)).unwrap();
+        let bank = |rom: &Rom| rom.read(0x5000) as u16 | (rom.read(0x5001) as u16) << 8;
+
+        rom.write(0x2000, 0x00);
+        rom.write(0x3000, 0x01);
+        assert_eq!(bank(&rom), 0x100);
+
+        rom.write(0x2000, 0xFF);
+        assert_eq!(bank(&rom), 0x1FF);
+
+        // unlike the other controllers, bank 0 can be mapped to the switchable area
+        rom.write(0x2000, 0x00);
+        rom.write(0x3000, 0x00);
+        assert_eq!(bank(&rom), 0x000);
+    }
+
+    #[test]
+    fn commercial_header_checksums() {
+        // header fields of Pokemon Red and Tetris (Rev A), along with their checksums
+        let mut red = vec![0; 0x8000];
+        red[0x0134..0x013F].copy_from_slice(b"POKEMON RED");
+        red[0x0144..=0x014C]
+            .copy_from_slice(&[b'0', b'1', 0x03, 0x13, 0x05, 0x03, 0x01, 0x33, 0x00]);
+        assert_eq!(RomHeader::compute_header_checksum(&red), 0x20);
+
+        let mut tetris = vec![0; 0x8000];
+        tetris[0x0134..0x013A].copy_from_slice(b"TETRIS");
+        tetris[0x014B..=0x014C].copy_from_slice(&[0x01, 0x01]);
+        tetris[0x014D] = 0x0A;
+        let header = RomHeader::try_from_bytes(&tetris[0x0133..=0x014F]).unwrap();
+        assert!(header.verify_header_checksum(&tetris));
+
+        tetris[0x0134] = b'Z';
+        assert!(!header.verify_header_checksum(&tetris));
+    }
+
+    #[test]
+    fn wrong_header_checksum_still_loads() {
+        let mut bytes = cartridge(0x00, 0x00, 0x00);
+        bytes[0x014D] ^= 0xFF;
+        assert!(Rom::try_from_bytes(&bytes[..]).is_ok());
+
+        let mut warnings = Vec::new();
+        assert!(Rom::try_from_bytes_lenient(&bytes[..], &mut warnings).is_ok());
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("checksum"));
+    }
+}
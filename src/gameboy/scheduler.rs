@@ -0,0 +1,152 @@
+/// A component whose state changes at known points in time, like the PPU switching modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The PPU reaches the end of its current mode, or of a vblank line.
+    Ppu,
+    /// The timer increments DIV or TIMA.
+    Timer,
+    /// A serial transfer finishes.
+    Serial,
+}
+
+const EVENTS: [Event; 3] = [Event::Ppu, Event::Timer, Event::Serial];
+
+/// Keeps track of when the next event of each component happens, in clock cycles, so components are only advanced
+/// when something actually happens to them instead of on every machine cycle. Components are also advanced when the
+/// CPU writes to a register that changes when their next event happens, as requested through
+/// [super::memory::Memory::request_sync].
+pub struct Scheduler {
+    /// Clock cycles elapsed since the scheduler was created.
+    now: u64,
+    /// The point in time each component has been advanced to.
+    synced: [u64; EVENTS.len()],
+    /// When the next event of each component happens, if it has one.
+    pending: [Option<u64>; EVENTS.len()],
+}
+
+impl Scheduler {
+    /// Returns a new scheduler, with the event of every component due right away so they can schedule their first
+    /// real one.
+    pub fn new() -> Self {
+        Self {
+            now: 0,
+            synced: [0; EVENTS.len()],
+            pending: [Some(0); EVENTS.len()],
+        }
+    }
+
+    /// Clock cycles elapsed since the scheduler was created.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// When the next event of the given component happens, if it has one.
+    pub fn pending(&self, event: Event) -> Option<u64> {
+        self.pending[event as usize]
+    }
+
+    /// Advances time by the given amount of clock cycles, handling every event due until then in order.
+    ///
+    /// `handle` is given a component along with how many clock cycles it has to be advanced by, and returns how many
+    /// clock cycles are left until its next event, if any.
+    pub fn advance<F>(&mut self, cycles: u32, handle: &mut F)
+    where
+        F: FnMut(Event, u32) -> Option<u32>,
+    {
+        let target = self.now + cycles as u64;
+        while let Some((time, event)) = self.next_due(target) {
+            self.now = time;
+            self.sync(event, handle);
+        }
+
+        self.now = target;
+    }
+
+    /// Advances a component to the current point in time and schedules its next event again. Used when its next
+    /// event might have moved, e.g. because one of its registers was written.
+    pub fn sync<F>(&mut self, event: Event, handle: &mut F)
+    where
+        F: FnMut(Event, u32) -> Option<u32>,
+    {
+        let index = event as usize;
+        let elapsed = (self.now - self.synced[index]) as u32;
+        self.synced[index] = self.now;
+        self.pending[index] = handle(event, elapsed).map(|cycles| self.now + cycles as u64);
+    }
+
+    /// Syncs every component in `requests`, a bitmask indexed by [Event] as returned by
+    /// [super::memory::Memory::take_sync_requests].
+    pub fn sync_requested<F>(&mut self, requests: u8, handle: &mut F)
+    where
+        F: FnMut(Event, u32) -> Option<u32>,
+    {
+        for event in EVENTS {
+            if requests & (1 << event as u8) != 0 {
+                self.sync(event, handle);
+            }
+        }
+    }
+
+    /// The earliest event due at or before `until`, if any.
+    fn next_due(&self, until: u64) -> Option<(u64, Event)> {
+        EVENTS
+            .into_iter()
+            .filter_map(|event| Some((self.pending[event as usize]?, event)))
+            .filter(|(time, _)| *time <= until)
+            .min_by_key(|(time, _)| *time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_handled_in_order() {
+        let mut scheduler = Scheduler::new();
+        let mut handled = Vec::new();
+        let mut handle = |event, cycles| {
+            handled.push((event, cycles));
+            match event {
+                Event::Ppu => Some(3),
+                Event::Timer => Some(5),
+                Event::Serial => None,
+            }
+        };
+
+        scheduler.advance(10, &mut handle);
+        assert_eq!(scheduler.now(), 10);
+        assert_eq!(
+            handled,
+            [
+                (Event::Ppu, 0),
+                (Event::Timer, 0),
+                (Event::Serial, 0),
+                (Event::Ppu, 3),
+                (Event::Timer, 5),
+                (Event::Ppu, 3),
+                (Event::Ppu, 3),
+                (Event::Timer, 5),
+            ]
+        );
+        assert_eq!(scheduler.pending(Event::Ppu), Some(12));
+        assert_eq!(scheduler.pending(Event::Timer), Some(15));
+        assert_eq!(scheduler.pending(Event::Serial), None);
+    }
+
+    #[test]
+    fn sync_reschedules_from_now() {
+        let mut scheduler = Scheduler::new();
+        let mut handle = |event, _| (event == Event::Serial).then_some(100);
+        scheduler.advance(4, &mut handle);
+        assert_eq!(scheduler.pending(Event::Serial), Some(100));
+
+        let mut elapsed = None;
+        scheduler.sync_requested(1 << Event::Serial as u8, &mut |_, cycles| {
+            elapsed = Some(cycles);
+            Some(100)
+        });
+        assert_eq!(elapsed, Some(4));
+        assert_eq!(scheduler.pending(Event::Serial), Some(104));
+    }
+}
@@ -0,0 +1,57 @@
+use super::memory::registers as memreg;
+use super::memory::Memory;
+
+/// How many clock cycles a transfer using the internal clock takes: 8 bits at 8192 Hz.
+const TRANSFER_CYCLES: u32 = 8 * 512;
+
+/// Serial port of the gameboy. Nothing is ever connected to it, so transfers receive 0xFF, and the bytes sent are
+/// kept as the output of the port (which test roms use to report their results).
+pub struct Serial {
+    output: Vec<u8>,
+    /// Clock cycles left in the current transfer, if one using the internal clock is in progress. Transfers using
+    /// an external clock never finish, since there's nothing to provide the clock.
+    remaining_cycles: Option<u32>,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Self {
+            output: Vec::new(),
+            remaining_cycles: None,
+        }
+    }
+
+    /// Advances the serial port by the given amount of clock cycles. Transfers started since the last call start at
+    /// the end.
+    pub fn advance(&mut self, memory: &mut Memory, cycles: u32) {
+        match self.remaining_cycles {
+            Some(remaining) if remaining <= cycles => {
+                self.remaining_cycles = None;
+
+                memory.write(memreg::addresses::SB, 0xFF);
+                let sc = memory.read(memreg::addresses::SC);
+                memory.write(memreg::addresses::SC, sc & !0b1000_0000);
+                memory.request_interrupt(memreg::Interrupt::Serial);
+            }
+            Some(remaining) => self.remaining_cycles = Some(remaining - cycles),
+            None => (),
+        }
+
+        if memory.take_serial_transfer() {
+            self.output.push(memory.read(memreg::addresses::SB));
+
+            let internal_clock = memory.read(memreg::addresses::SC) & 0b0000_0001 != 0;
+            self.remaining_cycles = internal_clock.then_some(TRANSFER_CYCLES);
+        }
+    }
+
+    /// How many clock cycles until the current transfer finishes, if one is going to.
+    pub fn cycles_to_next_event(&self) -> Option<u32> {
+        self.remaining_cycles
+    }
+
+    /// Every byte sent through the port so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+}
@@ -0,0 +1,311 @@
+use super::memory::registers as memreg;
+use super::memory::Memory;
+use super::ppu::{ScreenBuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::util::bgr555_to_rgb;
+
+/// Width of the super gameboy output, border included, in pixels.
+pub const SGB_SCREEN_WIDTH: usize = 256;
+/// Height of the super gameboy output, border included, in pixels.
+pub const SGB_SCREEN_HEIGHT: usize = 224;
+
+/// Position of the gameboy screen inside the border.
+const SCREEN_OFFSET: (usize, usize) = (48, 40);
+
+/// Palette used before the game sets any colors.
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+/// How the gameboy screen is masked, set by the MASK_EN command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SgbMask {
+    None,
+    Freeze,
+    Black,
+    Color0,
+}
+
+/// A Super Gameboy component. Decodes the command packets games send by pulsing the JOYP register and keeps
+/// the palettes and border they upload.
+///
+/// Only the palette, border, multiplayer and mask commands are supported. Attribute commands are ignored, so the
+/// whole screen always uses palette 0.
+pub struct Sgb {
+    last_joyp: u8,
+    receiving: bool,
+    bit_index: usize,
+    packet: [u8; 16],
+    command: Vec<u8>,
+    remaining_packets: u8,
+
+    palettes: [[u16; 4]; 4],
+    system_palettes: Box<[[u16; 4]; 512]>,
+    border_tiles: Box<[u8; 256 * 32]>,
+    border_map: Box<[u16; 32 * 32]>,
+    border_palettes: [[u16; 16]; 4],
+
+    players: u8,
+    current_player: u8,
+    mask: SgbMask,
+    frozen: Option<ScreenBuffer>,
+}
+
+impl Sgb {
+    pub fn new() -> Self {
+        Self {
+            last_joyp: 0x30,
+            receiving: false,
+            bit_index: 0,
+            packet: [0; 16],
+            command: Vec::new(),
+            remaining_packets: 0,
+
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: crate::util::boxed_array_copy([0; 4]),
+            border_tiles: crate::util::boxed_array_copy(0),
+            border_map: crate::util::boxed_array_copy(0),
+            border_palettes: [[0; 16]; 4],
+
+            players: 1,
+            current_player: 0,
+            mask: SgbMask::None,
+            frozen: None,
+        }
+    }
+
+    /// Observes the current value of JOYP, decoding packet bits from the P14/P15 pulses.
+    pub fn update_joyp(&mut self, memory: &Memory, screen: &ScreenBuffer, joyp: u8) {
+        let lines = joyp & 0x30;
+        if lines == self.last_joyp {
+            return;
+        }
+
+        let previous = std::mem::replace(&mut self.last_joyp, lines);
+        match lines {
+            // reset pulse: a new packet begins
+            0x00 => {
+                self.receiving = true;
+                self.bit_index = 0;
+                self.packet = [0; 16];
+            }
+            // P14 low is a 0 bit, P15 low is a 1 bit
+            0x10 | 0x20 if self.receiving && previous == 0x30 => {
+                let bit = (lines == 0x10) as u8;
+                if self.bit_index < 128 {
+                    self.packet[self.bit_index / 8] |= bit << (self.bit_index % 8);
+                    self.bit_index += 1;
+                } else {
+                    // stop bit
+                    self.receiving = false;
+                    self.receive_packet(memory, screen);
+                }
+            }
+            0x30 if !self.receiving && previous & 0x20 == 0 && self.players > 1 => {
+                self.current_player = (self.current_player + 1) % self.players;
+            }
+            _ => (),
+        }
+    }
+
+    /// The joypad id to be returned in the lower bits of JOYP, if multiplayer is enabled and no buttons are selected.
+    pub fn joypad_id(&self, joyp: u8) -> Option<u8> {
+        (self.players > 1 && joyp & 0x30 == 0x30).then_some(self.current_player)
+    }
+
+    pub fn mask(&self) -> SgbMask {
+        self.mask
+    }
+
+    fn receive_packet(&mut self, memory: &Memory, screen: &ScreenBuffer) {
+        if self.remaining_packets == 0 {
+            self.command.clear();
+            self.remaining_packets = (self.packet[0] & 0x07).max(1);
+        }
+
+        self.command.extend_from_slice(&self.packet);
+        self.remaining_packets -= 1;
+
+        if self.remaining_packets == 0 {
+            let command = std::mem::take(&mut self.command);
+            self.execute(&command, memory, screen);
+        }
+    }
+
+    fn execute(&mut self, data: &[u8], memory: &Memory, screen: &ScreenBuffer) {
+        let word = |index: usize| u16::from_le_bytes([data[index], data[index + 1]]);
+        match data[0] >> 3 {
+            // PAL01, PAL23, PAL03, PAL12
+            command @ 0x00..=0x03 => {
+                let (a, b) = [(0, 1), (2, 3), (0, 3), (1, 2)][command as usize];
+                for palette in self.palettes.iter_mut() {
+                    palette[0] = word(1);
+                }
+
+                for i in 0..3 {
+                    self.palettes[a][i + 1] = word(3 + i * 2);
+                    self.palettes[b][i + 1] = word(9 + i * 2);
+                }
+            }
+            // PAL_SET
+            0x0A => {
+                for i in 0..4 {
+                    self.palettes[i] = self.system_palettes[word(1 + i * 2) as usize & 0x1FF];
+                }
+
+                if data[9] & 0x40 != 0 {
+                    self.set_mask(SgbMask::None, screen);
+                }
+            }
+            // PAL_TRN
+            0x0B => {
+                let transfer = Self::vram_transfer(memory);
+                for (palette, bytes) in self
+                    .system_palettes
+                    .iter_mut()
+                    .zip(transfer.chunks_exact(8))
+                {
+                    for (color, bytes) in palette.iter_mut().zip(bytes.chunks_exact(2)) {
+                        *color = u16::from_le_bytes([bytes[0], bytes[1]]);
+                    }
+                }
+            }
+            // MLT_REQ
+            0x11 => {
+                self.players = match data[1] & 0x03 {
+                    0x01 => 2,
+                    0x03 => 4,
+                    _ => 1,
+                };
+                self.current_player = 0;
+            }
+            // CHR_TRN
+            0x13 => {
+                let transfer = Self::vram_transfer(memory);
+                let offset = (data[1] & 0x01) as usize * 0x1000;
+                self.border_tiles[offset..offset + 0x1000].copy_from_slice(&transfer[..]);
+            }
+            // PCT_TRN
+            0x14 => {
+                let transfer = Self::vram_transfer(memory);
+                for (entry, bytes) in self
+                    .border_map
+                    .iter_mut()
+                    .zip(transfer[..0x800].chunks_exact(2))
+                {
+                    *entry = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+
+                for (palette, bytes) in self
+                    .border_palettes
+                    .iter_mut()
+                    .zip(transfer[0x800..0x880].chunks_exact(32))
+                {
+                    for (color, bytes) in palette.iter_mut().zip(bytes.chunks_exact(2)) {
+                        *color = u16::from_le_bytes([bytes[0], bytes[1]]);
+                    }
+                }
+            }
+            // MASK_EN
+            0x17 => {
+                let mask = match data[1] & 0x03 {
+                    0x01 => SgbMask::Freeze,
+                    0x02 => SgbMask::Black,
+                    0x03 => SgbMask::Color0,
+                    _ => SgbMask::None,
+                };
+                self.set_mask(mask, screen);
+            }
+            _ => (),
+        }
+    }
+
+    fn set_mask(&mut self, mask: SgbMask, screen: &ScreenBuffer) {
+        self.frozen = (mask == SgbMask::Freeze).then(|| screen.clone());
+        self.mask = mask;
+    }
+
+    /// Reads the 4KiB the gameboy is displaying for a VRAM transfer: the first 256 tiles of the background
+    /// tilemap, 20 per row.
+    fn vram_transfer(memory: &Memory) -> Box<[u8; 0x1000]> {
+        let lcdc = memreg::LCDC::from(memory.read(memreg::addresses::LCDC));
+        let vram = memory.vram().as_slice();
+        let tilemap = match lcdc.background_tilemap() {
+            super::ppu::Tilemap::Tilemap0 => 0x1800,
+            super::ppu::Tilemap::Tilemap1 => 0x1C00,
+        };
+
+        let mut transfer = crate::util::boxed_array_copy(0u8);
+        for (tile, bytes) in transfer.chunks_exact_mut(16).enumerate() {
+            let (row, column) = crate::util::div_rem(tile, 20);
+            let index = vram[tilemap + row * 32 + column] as usize;
+            let address = if lcdc.alternative_addressing_mode() {
+                (0x1000 + (index as i8 as isize) * 16) as usize
+            } else {
+                index * 16
+            };
+
+            bytes.copy_from_slice(&vram[address..address + 16]);
+        }
+
+        transfer
+    }
+
+    /// Draws the gameboy screen inside the border into a RGBA frame of [SGB_SCREEN_WIDTH]x[SGB_SCREEN_HEIGHT].
+    pub fn draw(&self, screen: &ScreenBuffer, frame: &mut [u8]) {
+        let screen = self.frozen.as_ref().unwrap_or(screen);
+        let backdrop = bgr555_to_rgb(self.palettes[0][0]);
+        for pixel in frame.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[backdrop[0], backdrop[1], backdrop[2], 0xFF]);
+        }
+
+        // gameboy screen
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let color = match self.mask {
+                    SgbMask::Black => [0, 0, 0],
+                    SgbMask::Color0 => backdrop,
+                    _ => bgr555_to_rgb(self.palettes[0][screen.get_pixel(x, y).unwrap() as usize]),
+                };
+
+                let index = ((y + SCREEN_OFFSET.1) * SGB_SCREEN_WIDTH + x + SCREEN_OFFSET.0) * 4;
+                frame[index..index + 3].copy_from_slice(&color);
+            }
+        }
+
+        // border, color 0 is transparent
+        for (map_index, entry) in self.border_map.iter().enumerate() {
+            let (tile_y, tile_x) = crate::util::div_rem(map_index, 32);
+            if tile_y * 8 >= SGB_SCREEN_HEIGHT {
+                break;
+            }
+
+            let tile = &self.border_tiles[(*entry & 0xFF) as usize * 32..][..32];
+            let palette = &self.border_palettes[((*entry >> 10) & 0x03) as usize];
+            let flip_x = *entry & (1 << 14) != 0;
+            let flip_y = *entry & (1 << 15) != 0;
+
+            for y in 0..8 {
+                let row = if flip_y { 7 - y } else { y };
+                let planes = [
+                    tile[row * 2],
+                    tile[row * 2 + 1],
+                    tile[16 + row * 2],
+                    tile[17 + row * 2],
+                ];
+                for x in 0..8 {
+                    let bit = if flip_x { x } else { 7 - x };
+                    let color_index = planes
+                        .iter()
+                        .enumerate()
+                        .fold(0, |acc, (i, plane)| acc | (((plane >> bit) & 1) << i));
+
+                    if color_index == 0 {
+                        continue;
+                    }
+
+                    let color = bgr555_to_rgb(palette[color_index as usize]);
+                    let index = ((tile_y * 8 + y) * SGB_SCREEN_WIDTH + tile_x * 8 + x) * 4;
+                    frame[index..index + 3].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use super::cpu::{MasterInterrupt, WordRegister};
+use super::Gameboy;
+use std::ops::RangeInclusive;
+
+/// Magic bytes at the start of every state snapshot.
+const STATE_MAGIC: &[u8; 4] = b"ABST";
+/// Version of the snapshot layout, bumped whenever it changes.
+const STATE_VERSION: u8 = 1;
+
+/// Registers in a snapshot, in order, stored as little endian words.
+const STATE_WORD_REGISTERS: [WordRegister; 6] = [
+    WordRegister::AF,
+    WordRegister::BC,
+    WordRegister::DE,
+    WordRegister::HL,
+    WordRegister::SP,
+    WordRegister::PC,
+];
+
+/// Offset of the memory dump in a snapshot: magic, version, word registers, IME and halt.
+const STATE_MEMORY_OFFSET: usize = 4 + 1 + 6 * 2 + 1 + 1;
+/// Total length of a snapshot.
+const STATE_LEN: usize = STATE_MEMORY_OFFSET + 0x10000;
+
+/// A register field that differs between two states.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterDiff {
+    pub name: String,
+    pub this: u16,
+    pub other: u16,
+}
+
+/// The differences between two states, as returned by [Gameboy::diff_state].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub registers: Vec<RegisterDiff>,
+    /// Ranges of addresses whose values differ, as seen by the CPU.
+    pub memory: Vec<RangeInclusive<u16>>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.memory.is_empty()
+    }
+}
+
+impl Gameboy {
+    /// Takes a snapshot of the state that's visible to the CPU: its registers and the whole address space, as
+    /// read through the memory bus. This can't be loaded back, but can be compared through [Gameboy::diff_state].
+    pub fn state_snapshot(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(STATE_LEN);
+        state.extend_from_slice(STATE_MAGIC);
+        state.push(STATE_VERSION);
+
+        let registers = self.cpu().registers();
+        for register in STATE_WORD_REGISTERS {
+            state.extend_from_slice(&registers.get_reg_16(register).to_le_bytes());
+        }
+
+        state.push(match self.cpu().master_interrupt_flag() {
+            MasterInterrupt::Off => 0,
+            MasterInterrupt::TurningOn => 1,
+            MasterInterrupt::On => 2,
+        });
+        state.push(self.cpu().halted() as u8);
+
+        state.extend((0..=0xFFFF).map(|address| self.memory().read(address)));
+        state
+    }
+
+    /// Compares the current state with a snapshot taken by [Gameboy::state_snapshot], listing the registers and
+    /// memory ranges that differ.
+    pub fn diff_state(&self, other: &[u8]) -> anyhow::Result<StateDiff> {
+        if other.len() != STATE_LEN || &other[..4] != STATE_MAGIC {
+            anyhow::bail!("Not a state snapshot");
+        }
+
+        if other[4] != STATE_VERSION {
+            anyhow::bail!("Unsupported state snapshot version ({})", other[4]);
+        }
+
+        let this = self.state_snapshot();
+        let mut diff = StateDiff::default();
+
+        for (i, register) in STATE_WORD_REGISTERS.into_iter().enumerate() {
+            let offset = 5 + i * 2;
+            let this = u16::from_le_bytes([this[offset], this[offset + 1]]);
+            let other = u16::from_le_bytes([other[offset], other[offset + 1]]);
+            if this != other {
+                diff.registers.push(RegisterDiff {
+                    name: register.to_string(),
+                    this,
+                    other,
+                });
+            }
+        }
+
+        for (offset, name) in [(17, "IME"), (18, "HALT")] {
+            if this[offset] != other[offset] {
+                diff.registers.push(RegisterDiff {
+                    name: name.to_string(),
+                    this: this[offset] as u16,
+                    other: other[offset] as u16,
+                });
+            }
+        }
+
+        // coalesce differing addresses into ranges
+        let mut start = None;
+        for address in 0..=0x10000usize {
+            let differs = address <= 0xFFFF
+                && this[STATE_MEMORY_OFFSET + address] != other[STATE_MEMORY_OFFSET + address];
+
+            match (start, differs) {
+                (None, true) => start = Some(address as u16),
+                (Some(first), false) => {
+                    diff.memory.push(first..=(address - 1) as u16);
+                    start = None;
+                }
+                _ => (),
+            }
+        }
+
+        Ok(diff)
+    }
+}
@@ -18,72 +18,140 @@ impl Tac {
         self.data & 0b0000_0100 == 0b0000_0100
     }
 
-    pub fn tima_divider(&self) -> u16 {
+    /// The bit of the internal counter whose falling edge increments TIMA.
+    pub fn counter_bit(&self) -> u32 {
         match self.data & 0b0000_0011 {
-            0b0000_0000 => 1024,
-            0b0000_0001 => 16,
-            0b0000_0010 => 64,
-            0b0000_0011 => 256,
+            0b0000_0000 => 9, // every 1024 cycles
+            0b0000_0001 => 3, // every 16 cycles
+            0b0000_0010 => 5, // every 64 cycles
+            0b0000_0011 => 7, // every 256 cycles
             _ => unreachable!(),
         }
     }
 }
 
+/// Timer component of the gameboy. DIV is the upper byte of a 16-bit counter incremented every clock cycle, and
+/// TIMA is incremented on every falling edge of the counter bit selected by TAC.
 pub struct Timer {
-    div_cycle_count: u32,
-    tima_cycle_count: u16,
+    counter: u16,
+    /// TAC as of the last time the timer was advanced. Writes to TAC only apply from then on.
+    tac: u8,
 }
 
 impl Timer {
     pub fn new() -> Self {
-        Self {
-            div_cycle_count: 0,
-            tima_cycle_count: 0,
-        }
+        Self { counter: 0, tac: 0 }
     }
 
-    fn update_div(&mut self, memory: &mut Memory) {
-        if self.div_cycle_count < 256 {
-            self.div_cycle_count += 1;
-            return;
-        }
+    fn increment_tima(memory: &mut Memory) {
+        let tima = memory.read(memreg::addresses::TIMA);
+        let (new_tima, overflow) = tima.overflowing_add(1);
 
-        self.div_cycle_count = 0;
+        let new_tima = if overflow {
+            memory.request_interrupt(memreg::Interrupt::Timer);
+            memory.read(memreg::addresses::TMA)
+        } else {
+            new_tima
+        };
 
-        let div = memory.read(memreg::addresses::DIV);
-        let new_div = div.wrapping_add(1);
+        memory.write(memreg::addresses::TIMA, new_tima);
+    }
 
-        memory.write(memreg::addresses::DIV, new_div);
+    /// Sets the internal counter, updating DIV to match.
+    pub fn set_counter(&mut self, memory: &mut Memory, counter: u16) {
+        self.counter = counter;
+        memory.write(memreg::addresses::DIV, (counter >> 8) as u8);
     }
 
-    fn update_tima(&mut self, memory: &mut Memory) {
-        let tac = Tac::new(memory.read(memreg::addresses::TAC) & 0b0000_0111).unwrap();
-        if !tac.timer_enabled() {
-            return;
+    /// Advances the timer by the given amount of clock cycles, jumping straight to the TIMA increments instead of
+    /// polling every cycle. Writes to DIV and TAC made since the last call take effect at the end.
+    pub fn advance(&mut self, memory: &mut Memory, cycles: u32) {
+        let tac = Tac::new(self.tac).unwrap();
+        let old = self.counter as u32;
+        let new = old + cycles;
+        if tac.timer_enabled() {
+            // a falling edge happens whenever the counter crosses a multiple of twice the selected bit
+            let shift = tac.counter_bit() + 1;
+            for _ in 0..(new >> shift) - (old >> shift) {
+                Self::increment_tima(memory);
+            }
         }
+        self.counter = new as u16;
 
-        if self.tima_cycle_count <= tac.tima_divider() {
-            self.tima_cycle_count += 1;
-            return;
+        self.tac = memory.read(memreg::addresses::TAC) & 0b0000_0111;
+        if memory.take_div_reset() {
+            // resetting the counter while the selected bit is set is a falling edge too
+            let tac = Tac::new(self.tac).unwrap();
+            if tac.timer_enabled() && self.counter & (1 << tac.counter_bit()) != 0 {
+                Self::increment_tima(memory);
+            }
+
+            self.counter = 0;
         }
 
-        self.tima_cycle_count = 0;
+        memory.write(memreg::addresses::DIV, (self.counter >> 8) as u8);
+    }
 
-        let tima = memory.read(memreg::addresses::TIMA);
-        let (new_tima, overflow) = tima.overflowing_add(1);
+    /// How many clock cycles until the timer's next event: DIV or TIMA being incremented.
+    pub fn cycles_to_next_event(&self) -> u32 {
+        let until_div = 0x100 - (self.counter as u32 & 0xFF);
+        let tac = Tac::new(self.tac).unwrap();
+        if !tac.timer_enabled() {
+            return until_div;
+        }
 
-        let new_tima = if overflow {
-            memory.request_interrupt(memreg::Interrupt::Timer);
-            memory.read(memreg::addresses::TMA)
-        } else {
-            new_tima
-        };
+        let period = 1 << (tac.counter_bit() + 1);
+        until_div.min(period - (self.counter as u32 & (period - 1)))
+    }
+}
 
-        memory.write(memreg::addresses::TIMA, new_tima);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::rom::Rom;
+
+    /// Runs the timer with TIMA incremented every 16 clock cycles for `cycles` cycles, then resets DIV. Returns
+    /// TIMA right after the reset.
+    fn tima_after_div_reset(cycles: u32) -> u8 {
+        let mut memory = Memory::new(Rom::from_bytes_unchecked(&[]).unwrap(), Box::new([]));
+        memory.write(memreg::addresses::TIMA, 0);
+        memory.write(memreg::addresses::TAC, 0b101);
+
+        let mut timer = Timer::new();
+        timer.advance(&mut memory, 0);
+        timer.advance(&mut memory, cycles);
+        memory.reset_div();
+        timer.advance(&mut memory, 0);
+
+        assert_eq!(memory.read(memreg::addresses::DIV), 0);
+        memory.read(memreg::addresses::TIMA)
+    }
+
+    #[test]
+    fn div_reset_with_selected_bit_set_increments_tima() {
+        // bit 3 of the counter is set for cycles 8..=15 of every 16
+        assert_eq!(tima_after_div_reset(4), 0);
+        assert_eq!(tima_after_div_reset(8), 1);
+        assert_eq!(tima_after_div_reset(15), 1);
+        assert_eq!(tima_after_div_reset(16), 1);
+        assert_eq!(tima_after_div_reset(24), 2);
     }
 
-    pub fn cycle(&mut self, memory: &mut Memory) {
-        self.update_div(memory);
-        self.update_tima(memory);
+    #[test]
+    fn next_event_is_the_closest_increment() {
+        let mut memory = Memory::new(Rom::from_bytes_unchecked(&[]).unwrap(), Box::new([]));
+        memory.write(memreg::addresses::TAC, 0b100);
+
+        let mut timer = Timer::new();
+        timer.advance(&mut memory, 0);
+        assert_eq!(timer.cycles_to_next_event(), 256);
+
+        // TIMA every 1024 cycles, DIV every 256
+        timer.advance(&mut memory, 1000);
+        assert_eq!(timer.cycles_to_next_event(), 24);
+
+        memory.write(memreg::addresses::TAC, 0b101);
+        timer.advance(&mut memory, 0);
+        assert_eq!(timer.cycles_to_next_event(), 8);
     }
 }
@@ -1,13 +1,25 @@
+#[cfg(feature = "audio")]
+pub mod audio;
 #[allow(clippy::new_without_default)]
 #[deny(clippy::perf)]
 pub mod gameboy;
+pub mod patch;
+pub mod prelude;
+mod toast;
 pub mod util;
 
 #[cfg(feature = "tdebugger")]
 pub mod tdebugger;
 
 use clap::{ArgEnum, Parser};
-use gameboy::Gameboy;
+use gameboy::{
+    apu::SoundChannel,
+    frame::{FrameBuffer, FRAME_STRIDE},
+    memory::registers,
+    ppu::{ScreenBuffer, FRAME_CYCLES, SCREEN_HEIGHT, SCREEN_WIDTH},
+    sgb::{SGB_SCREEN_HEIGHT, SGB_SCREEN_WIDTH},
+    Gameboy,
+};
 use parking_lot::Mutex;
 use std::sync::{atomic::AtomicBool, Arc};
 
@@ -26,6 +38,15 @@ pub enum ScreenPalette {
 }
 
 impl ScreenPalette {
+    /// Returns the RGBA colors of this palette, indexed by screen shade (0 is the lightest, 3 the darkest).
+    pub fn to_rgba_array(self) -> [[u8; 4]; 4] {
+        // color arrays go from darkest to lightest
+        let [c0, c1, c2, c3] = self
+            .to_color_array()
+            .map(|color| [color.r, color.g, color.b, 0xFF]);
+        [c3, c2, c1, c0]
+    }
+
     pub fn to_color_array(self) -> [hex_color::HexColor; 4] {
         match self {
             ScreenPalette::Classic => [
@@ -92,19 +113,51 @@ impl ScreenPalette {
     }
 }
 
+/// Which emulated frames are presented to the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameSkip {
+    /// Presents the newest frame at the display rate, dropping the ones emulated in between.
+    Auto,
+    /// Presents every Nth emulated frame.
+    Every(u64),
+}
+
+impl std::str::FromStr for FrameSkip {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+
+        match s.parse::<u64>() {
+            Ok(n) if n > 0 => Ok(Self::Every(n)),
+            _ => anyhow::bail!("expected \"auto\" or a number of frames greater than zero"),
+        }
+    }
+}
+
 /// a gameboy emulator, because there sure aren't enough of these out there already!
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 pub struct AbductionArgs {
-    /// Path to the game ROM
-    #[clap(short, long)]
-    pub rom: String,
+    /// Path to the game ROM. If it's a directory, the ROMs inside of it are listed to pick one from
+    #[clap(short, long, required_unless_present = "list-palettes")]
+    pub rom: Option<String>,
 
     /// Path to the boot ROM to utilize
     #[clap(short, long, default_value = "boot.gb")]
     pub boot: String,
 
-    /// When passed, abduction will print the rom header instead of running
+    /// Path to the CGB boot ROM to utilize for games that support CGB. If not given, the DMG one is used
+    #[clap(long)]
+    pub cgb_boot: Option<String>,
+
+    /// Prints the built-in screen palettes and their colors instead of running
+    #[clap(long)]
+    pub list_palettes: bool,
+
+    /// When passed, abduction will print the rom header and verify its global checksum instead of running
     #[clap(short, long)]
     pub header: bool,
 
@@ -119,44 +172,607 @@ pub struct AbductionArgs {
     /// How long a machine cycle should take to execute, in nanoseconds
     #[clap(short, long, default_value = "953")]
     pub cycle_duration_ns: u64,
+
+    /// Enables super gameboy borders and palettes, if the rom supports them
+    #[clap(long)]
+    pub sgb: bool,
+
+    /// IPS or BPS patch to apply to the game ROM before running it
+    #[clap(long)]
+    pub patch: Option<String>,
+
+    /// Starts from the state the boot ROM leaves the gameboy in if the boot ROM is missing or invalid, instead of
+    /// failing
+    #[clap(long)]
+    pub auto_skip_boot: bool,
+
+    /// Value of the 16-bit internal divider counter (whose upper byte is DIV) when the game gains control, for
+    /// reproducing the RNG seeds of games that use DIV. Mostly useful when starting without a boot ROM
+    #[clap(long)]
+    pub initial_div: Option<u16>,
+
+    /// Emulates what the unusable memory region (0xFEA0-0xFEFF) returns on the emulated model, instead of always
+    /// returning 0xFF
+    #[clap(long)]
+    pub accurate_unusable_region: bool,
+
+    /// Treats recoverable problems with the ROM or boot ROM as warnings instead of errors
+    #[clap(long)]
+    pub lenient: bool,
+
+    /// Refuses to run ROMs whose header checksum doesn't match, like the boot ROM does. Otherwise a mismatch is only
+    /// printed as a warning
+    #[clap(long)]
+    pub strict_checksum: bool,
+
+    /// Runs the emulator alongside the terminal debugger
+    #[clap(short, long)]
+    pub debug: bool,
+
+    /// Runs headless up to the given frame, saves it as a PNG and exits
+    #[clap(long)]
+    pub capture_frame: Option<u64>,
+
+    /// Starts with the look of the original DMG LCD instead of the palette. Can be toggled with F2
+    #[clap(long)]
+    pub dmg_look: bool,
+
+    /// Averages each frame with the one before it, smoothing games that only update every other frame
+    #[clap(long)]
+    pub frame_blend: bool,
+
+    /// Paces emulation off display refreshes instead of free-running. On displays within a fraction of a percent
+    /// of the gameboy's refresh rate, exactly one frame is emulated per refresh, slightly adjusting the emulation
+    /// speed. Other displays get frames at the emulated rate, so faster ones show some frames more than once
+    #[clap(long)]
+    pub sync_to_display: bool,
+
+    /// Which frames are presented: "auto" keeps presentation near the display rate, while a number N presents
+    /// only every Nth emulated frame. With --sync-to-display, frames are emulated N at a time
+    #[clap(long, default_value = "auto")]
+    pub frame_skip: FrameSkip,
+
+    /// Runs headless, comparing the CPU state before every instruction against a gameboy-doctor log and stopping
+    /// at the first difference
+    #[clap(long)]
+    pub trace_compare: Option<String>,
+
+    /// Path of the PNG written by --capture-frame
+    #[clap(long, default_value = "frame.png")]
+    pub out: String,
+}
+
+impl AbductionArgs {
+    /// Path to the game ROM. It's only missing when listing palettes.
+    pub fn rom(&self) -> &str {
+        self.rom.as_deref().unwrap_or_default()
+    }
 }
 
-pub fn lib_main(args: AbductionArgs) -> anyhow::Result<()> {
+pub fn lib_main(mut args: AbductionArgs) -> anyhow::Result<()> {
+    if args.list_palettes {
+        list_palettes();
+        return Ok(());
+    }
+
+    if std::path::Path::new(args.rom()).is_dir() {
+        args.rom = Some(pick_rom(args.rom())?);
+    }
+
     if args.header {
-        let rom = crate::util::read_bytes(args.rom)?;
+        let rom = crate::util::read_bytes(args.rom())?;
         let header = gameboy::rom::RomHeader::try_from_bytes(&rom[0x0133..=0x014F])?;
-        println!("{:#?}", header);
+        println!("{}", header);
+
+        let global_checksum = gameboy::rom::RomHeader::compute_global_checksum(&rom);
+        println!(
+            "global checksum: {:#06X} in header, {:#06X} computed ({})",
+            header.rom_checksum,
+            global_checksum,
+            if header.verify_global_checksum(&rom) {
+                "ok"
+            } else {
+                "mismatch"
+            }
+        );
         Ok(())
+    } else if let Some(reference) = &args.trace_compare {
+        trace_compare(&args, reference)
+    } else if let Some(frame) = args.capture_frame {
+        capture_frame(&args, frame)
+    } else if args.debug {
+        run_debugger(args)
     } else {
         run(args)
     }
 }
 
+/// Prints every built-in palette with its colors, from darkest to lightest, along with a preview for terminals
+/// that support true color.
+fn list_palettes() {
+    for palette in ScreenPalette::value_variants() {
+        let name = palette
+            .to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default();
+
+        let colors = palette.to_color_array();
+        let hex: Vec<_> = colors.iter().map(|color| color.to_string()).collect();
+        let preview: String = colors
+            .iter()
+            .map(|color| format!("\x1b[48;2;{};{};{}m   ", color.r, color.g, color.b))
+            .collect();
+
+        println!("{:<14} {} {}\x1b[0m", name, hex.join(" "), preview);
+    }
+}
+
+#[cfg(feature = "tdebugger")]
+fn run_debugger(args: AbductionArgs) -> anyhow::Result<()> {
+    tdebugger::run_with_debugger(args)
+}
+
+#[cfg(not(feature = "tdebugger"))]
+fn run_debugger(_: AbductionArgs) -> anyhow::Result<()> {
+    anyhow::bail!(
+        "abduction was built without the debugger (tdebugger feature), --debug is unavailable"
+    )
+}
+
+/// Runs the emulator without a window until `frame` frames have been rendered, then saves the screen with the
+/// selected palette (or the SGB border, if enabled) to the output path.
+#[cfg(feature = "image")]
+fn capture_frame(args: &AbductionArgs, frame: u64) -> anyhow::Result<()> {
+    let mut gameboy = load_gameboy(args)?;
+    if args.sgb {
+        if let Err(e) = gameboy.enable_sgb() {
+            eprintln!("not enabling sgb support: {}", e);
+        }
+    }
+
+    while gameboy.ppu().frame_count() < frame {
+        gameboy.step();
+    }
+
+    let img = if let Some(sgb) = gameboy.sgb() {
+        let mut rgba = vec![0; SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT * 4];
+        sgb.draw(gameboy.ppu().screen(), &mut rgba);
+        image::RgbaImage::from_raw(SGB_SCREEN_WIDTH as u32, SGB_SCREEN_HEIGHT as u32, rgba)
+    } else {
+        let mut frame = FrameBuffer::new();
+        frame.set_dmg_look(args.dmg_look);
+        gameboy.update_frame(&mut frame, &args.palette.to_rgba_array());
+        image::RgbaImage::from_raw(
+            SCREEN_WIDTH as u32,
+            SCREEN_HEIGHT as u32,
+            frame.rgba().to_vec(),
+        )
+    }
+    .expect("frame has the right size");
+
+    img.save(&args.out)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "image"))]
+fn capture_frame(_: &AbductionArgs, _: u64) -> anyhow::Result<()> {
+    anyhow::bail!("abduction was built without image support, --capture-frame is unavailable")
+}
+
+/// How many matching trace lines are shown before the first difference.
+const TRACE_CONTEXT_LINES: usize = 8;
+
+/// How far the display's refresh interval can be from the emulated one for --sync-to-display to emulate exactly one
+/// frame per refresh, adjusting the emulation speed to match.
+const MAX_SPEED_ADJUSTMENT: f64 = 0.005;
+
+/// How many batches of frames (one frame, or N with --frame-skip N) --sync-to-display emulates at most in a single
+/// refresh to catch up with emulated time. Falling further behind than this restarts the pacing instead.
+const MAX_CATCH_UP_BATCHES: u32 = 4;
+
+/// A complete frame, as handed from the emulation thread to the window.
+#[derive(Clone)]
+struct PresentedFrame {
+    screen: ScreenBuffer,
+    number: u64,
+    /// SCX and SCY when the frame was completed, for the grid overlay.
+    scroll: (u8, u8),
+}
+
+fn trace_compare(args: &AbductionArgs, reference: &str) -> anyhow::Result<()> {
+    let reference = std::fs::read_to_string(reference)?;
+    let mut gameboy = load_gameboy(args)?;
+
+    // logs start where the boot rom hands control over to the game
+    while gameboy.memory().boot_mode() {
+        gameboy.step();
+    }
+
+    let mut context = std::collections::VecDeque::with_capacity(TRACE_CONTEXT_LINES);
+    let mut matched = 0;
+    for expected in reference
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        // only lines for executed instructions count, not the ones for interrupts being serviced or halted cycles
+        let mut idle_cycles = 0u32;
+        let (actual, instruction) = loop {
+            let line = gameboy.doctor_trace_line();
+            let step = gameboy.step_detailed();
+            if let Some(instruction) = step.instruction {
+                break (line, instruction);
+            }
+
+            idle_cycles += step.m_cycles as u32;
+            if idle_cycles > gameboy::CLOCK_FREQUENCY / 4 {
+                anyhow::bail!(
+                    "no instruction was executed for a second after line {}, the cpu seems stuck",
+                    matched
+                );
+            }
+        };
+
+        if actual != expected {
+            println!("trace diverges at line {}:", matched + 1);
+            for line in &context {
+                println!("           {}", line);
+            }
+            println!("expected:  {}", expected);
+            println!("actual:    {}", actual);
+            match instruction.prefixed {
+                Some(prefixed) => println!(
+                    "instruction at {:#06X}: {:?} (prefixed)",
+                    instruction.pc, prefixed
+                ),
+                None => println!(
+                    "instruction at {:#06X}: {:?} (opcode {:#04X})",
+                    instruction.pc, instruction.operation, instruction.opcode
+                ),
+            }
+
+            anyhow::bail!("trace doesn't match the reference log");
+        }
+
+        if context.len() == TRACE_CONTEXT_LINES {
+            context.pop_front();
+        }
+        context.push_back(actual);
+        matched += 1;
+    }
+
+    println!("trace matches all {} lines of the reference log", matched);
+    Ok(())
+}
+
+/// Draws tile grids over a RGBA frame of the gameboy screen: the 20x18 screen tiles in red, the background tiles
+/// (which move with SCX/SCY) in blue and the background origin in yellow.
+fn draw_grid_overlay(frame: &mut [u8], scx: u8, scy: u8) {
+    const SCREEN_GRID: [u8; 3] = [0xFF, 0x00, 0x00];
+    const BACKGROUND_GRID: [u8; 3] = [0x00, 0x60, 0xFF];
+    const ORIGIN: [u8; 3] = [0xFF, 0xE0, 0x00];
+
+    for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+        let (y, x) = crate::util::div_rem(i, SCREEN_WIDTH);
+        let (bg_x, bg_y) = ((x as u8).wrapping_add(scx), (y as u8).wrapping_add(scy));
+
+        let color = if bg_x == 0 || bg_y == 0 {
+            ORIGIN
+        } else if x % 8 == 0 || y % 8 == 0 {
+            SCREEN_GRID
+        } else if bg_x % 8 == 0 || bg_y % 8 == 0 {
+            BACKGROUND_GRID
+        } else {
+            continue;
+        };
+
+        // blend so what's under the grid can still be seen
+        for (channel, color) in pixel.iter_mut().zip(color) {
+            *channel = ((*channel as u16 + color as u16) / 2) as u8;
+        }
+    }
+}
+
+/// Lists the .gb and .gbc files in `dir` along with their titles on the terminal and asks for one to be picked.
+/// Returns the path of the picked rom.
+fn pick_rom(dir: &str) -> anyhow::Result<String> {
+    let mut roms: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path.extension().is_some_and(|extension| {
+                    extension.eq_ignore_ascii_case("gb") || extension.eq_ignore_ascii_case("gbc")
+                })
+        })
+        .collect();
+    roms.sort();
+
+    if roms.is_empty() {
+        anyhow::bail!("No roms found in {}", dir);
+    }
+
+    for (index, path) in roms.iter().enumerate() {
+        let title = crate::util::read_bytes(path)
+            .ok()
+            .and_then(|rom| {
+                let bytes = rom.get(0x0133..=0x014F)?;
+                gameboy::rom::RomHeader::try_from_bytes(bytes).ok()
+            })
+            .map_or_else(|| "(invalid header)".to_string(), |header| header.title);
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        println!("{:>3}: {} - {}", index + 1, title, name);
+    }
+
+    loop {
+        print!("rom to run (1-{}): ", roms.len());
+        std::io::Write::flush(&mut std::io::stdout())?;
+
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            anyhow::bail!("No rom picked");
+        }
+
+        match line.trim().parse::<usize>() {
+            Ok(index @ 1..) if index <= roms.len() => {
+                return Ok(roms[index - 1].to_string_lossy().into_owned())
+            }
+            _ => println!("invalid choice"),
+        }
+    }
+}
+
+/// Reads the boot rom to use for the given rom, picking the CGB one if the rom supports CGB and one was given.
+fn read_boot(args: &AbductionArgs, rom: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let header = match rom.get(0x0133..=0x014F) {
+        Some(bytes) => gameboy::rom::RomHeader::try_from_bytes(bytes)?,
+        None => anyhow::bail!("Rom is too small to contain a header"),
+    };
+
+    crate::util::read_bytes(boot_path(args, &header))
+}
+
+/// The path of the boot rom to use for a rom with the given header.
+fn boot_path<'a>(args: &'a AbductionArgs, header: &gameboy::rom::RomHeader) -> &'a str {
+    match (&args.cgb_boot, header.cgb) {
+        (
+            Some(cgb_boot),
+            gameboy::rom::RomCgbStatus::CGBOnly | gameboy::rom::RomCgbStatus::CGBSupport,
+        ) => cgb_boot,
+        _ => &args.boot,
+    }
+}
+
+/// Creates the emulator from the rom and boot rom paths in the arguments. In lenient mode, recoverable problems
+/// are printed as warnings. The same goes for boot rom problems when automatically skipping the boot rom.
+pub(crate) fn load_gameboy(args: &AbductionArgs) -> anyhow::Result<Gameboy> {
+    let mut rom = crate::util::read_bytes(args.rom())?;
+    if let Some(patch) = &args.patch {
+        rom = crate::patch::apply_patch(&rom, &crate::util::read_bytes(patch)?)?;
+    }
+
+    // in lenient mode, the mismatch is already among the warnings of the rom
+    if let Some(bytes) = rom.get(0x0133..=0x014F) {
+        let header = gameboy::rom::RomHeader::try_from_bytes(bytes)?;
+        if !header.verify_header_checksum(&rom) {
+            if args.strict_checksum {
+                anyhow::bail!("Rom header checksum doesn't match the header");
+            } else if !args.lenient {
+                eprintln!("warning: Rom header checksum doesn't match the header");
+            }
+        }
+    }
+
+    let mut gameboy = if !args.lenient && !args.auto_skip_boot {
+        let boot = read_boot(args, &rom)?;
+        Gameboy::new(rom, boot)?
+    } else {
+        let boot = read_boot(args, &rom).unwrap_or_else(|e| {
+            eprintln!(
+                "warning: failed to read boot rom ({}), running without it",
+                e
+            );
+            Vec::new()
+        });
+
+        let (gameboy, warnings) = if args.lenient {
+            Gameboy::new_lenient(rom, boot)?
+        } else {
+            Gameboy::new_with_boot_fallback(rom, boot)?
+        };
+
+        for warning in warnings {
+            eprintln!("warning: {}", warning);
+        }
+
+        gameboy
+    };
+
+    gameboy.set_initial_div(args.initial_div);
+    if args.accurate_unusable_region {
+        gameboy
+            .memory_mut()
+            .set_unusable_region_mode(gameboy::memory::UnusableRegionMode::Accurate);
+    }
+
+    Ok(gameboy)
+}
+
+/// Starts playing the audio of the gameboy in `shared`, reached through `gameboy`. Failing to open the audio
+/// device isn't fatal, the game just runs without sound.
+#[cfg(feature = "audio")]
+pub(crate) fn start_audio(gameboy: &Mutex<Gameboy>) -> Option<audio::AudioOutput> {
+    let result = audio::AudioOutput::new().and_then(|(output, samples)| {
+        // the apu pushes straight to the device's ring buffer, so the audio thread never takes the gameboy lock
+        let mut lock = gameboy.lock();
+        lock.apu_mut().set_sample_rate(Some(output.sample_rate()));
+        lock.apu_mut().set_sample_output(Some(samples));
+        output.play()?;
+        Ok(output)
+    });
+
+    match result {
+        Ok(output) => Some(output),
+        Err(e) => {
+            eprintln!("running without audio: {}", e);
+            None
+        }
+    }
+}
+
 pub fn run(args: AbductionArgs) -> anyhow::Result<()> {
     // create shared state
-    let rom = crate::util::read_bytes(args.rom)?;
-    let boot = crate::util::read_bytes(args.boot)?;
+    let save_path = std::path::Path::new(args.rom()).with_extension("sav");
+    let mut gameboy = load_gameboy(&args)?;
+    // failing here beats overwriting a save that couldn't be read when exiting
+    gameboy.load_ram(&save_path)?;
+
+    if args.sgb {
+        if let Err(e) = gameboy.enable_sgb() {
+            eprintln!("not enabling sgb support: {}", e);
+        }
+    }
 
-    let gameboy = Mutex::new(Gameboy::new(rom, boot)?);
+    let (width, height) = if gameboy.sgb().is_some() {
+        (SGB_SCREEN_WIDTH, SGB_SCREEN_HEIGHT)
+    } else {
+        (SCREEN_WIDTH, SCREEN_HEIGHT)
+    };
+
+    let sgb_enabled = gameboy.sgb().is_some();
+    let gameboy = Mutex::new(gameboy);
     let shared = Arc::new((gameboy, AtomicBool::new(false)));
 
+    // kept alive for as long as the emulator runs
+    #[cfg(feature = "audio")]
+    let _audio = start_audio(&shared.0);
+
+    // complete frames go through a triple buffer, so the window never has to wait on the gameboy lock for them
+    let (mut frame_writer, mut frame_reader) = crate::util::triple_buffer(PresentedFrame {
+        screen: ScreenBuffer::new(),
+        number: 0,
+        scroll: (0, 0),
+    });
+
+    // when syncing to the display, every presented frame lets the gameboy thread emulate the next ones
+    let (present_tx, present_rx) = std::sync::mpsc::sync_channel::<()>(1);
+    let sync_to_display = args.sync_to_display;
+    let frame_duration =
+        std::time::Duration::from_nanos(FRAME_CYCLES as u64 / 4 * args.cycle_duration_ns);
+
+    // when skipping frames while free-running, only every nth frame is handed over to the window
+    let frame_skip = args.frame_skip;
+
     // spawn thread for gameboy
     let shared_clone = shared.clone();
     let res = std::thread::spawn(move || {
         let shared = shared_clone;
+        let exiting = || shared.1.load(std::sync::atomic::Ordering::Relaxed);
         let mut m_cycles;
+        let mut last_frame = 0;
+
+        // refresh pacing for --sync-to-display: the average time between presents, and when the next batch of
+        // frames is due in emulated time
+        let mut last_present: Option<std::time::Instant> = None;
+        let mut refresh_interval: Option<std::time::Duration> = None;
+        let mut next_batch = std::time::Instant::now();
+
+        let mut publish_frame = |gameboy: &Gameboy| {
+            let frame = frame_writer.back_mut();
+            frame.screen.clone_from(gameboy.ppu().screen());
+            frame.number = gameboy.ppu().frame_count();
+            frame.scroll = (
+                gameboy.memory().read(registers::addresses::SCX),
+                gameboy.memory().read(registers::addresses::SCY),
+            );
+            frame_writer.publish();
+        };
 
         loop {
             m_cycles = 0;
             let before = std::time::Instant::now();
 
-            if shared.1.load(std::sync::atomic::Ordering::Relaxed) {
+            if exiting() {
                 break;
             }
 
-            let mut lock = shared.0.lock();
-            for _ in 0..4 {
-                m_cycles += lock.step();
+            if sync_to_display {
+                match present_rx.recv_timeout(std::time::Duration::from_millis(100)) {
+                    Ok(()) => (),
+                    Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+
+                let frames = match frame_skip {
+                    FrameSkip::Auto => 1,
+                    FrameSkip::Every(n) => n,
+                };
+                let batch_duration = frame_duration * frames as u32;
+
+                let now = std::time::Instant::now();
+                if let Some(last) = last_present.replace(now) {
+                    // averaged, since presents don't happen at perfectly regular intervals
+                    let interval = now - last;
+                    refresh_interval = Some(match refresh_interval {
+                        Some(average) => (average * 15 + interval) / 16,
+                        None => interval,
+                    });
+                }
+
+                let matches_display = refresh_interval.is_none_or(|interval| {
+                    let ratio = interval.as_secs_f64() / batch_duration.as_secs_f64();
+                    (ratio - 1.0).abs() <= MAX_SPEED_ADJUSTMENT
+                });
+
+                let batches = if matches_display {
+                    next_batch = now + batch_duration;
+                    1
+                } else {
+                    // pace by emulated time instead: faster displays get some refreshes without a new frame, while
+                    // slower ones get several frames per refresh
+                    let mut batches = 0;
+                    while next_batch <= now && batches < MAX_CATCH_UP_BATCHES {
+                        next_batch += batch_duration;
+                        batches += 1;
+                    }
+
+                    if next_batch <= now {
+                        next_batch = now + batch_duration;
+                    }
+
+                    batches
+                };
+
+                if batches > 0 {
+                    let mut lock = shared.0.lock();
+                    for _ in 0..frames * batches as u64 {
+                        lock.run_frame();
+                    }
+                    publish_frame(&lock);
+                }
+
+                continue;
+            }
+
+            // the lock must not be held while waiting, or the window would stall on it
+            {
+                let mut lock = shared.0.lock();
+                for _ in 0..4 {
+                    if exiting() {
+                        break;
+                    }
+
+                    m_cycles += lock.step();
+
+                    let frame_number = lock.ppu().frame_count();
+                    if frame_number != last_frame {
+                        last_frame = frame_number;
+                        match frame_skip {
+                            FrameSkip::Every(n) if !frame_number.is_multiple_of(n) => (),
+                            _ => publish_frame(&lock),
+                        }
+                    }
+                }
             }
 
             let frame_time: std::time::Duration =
@@ -174,8 +790,8 @@ pub fn run(args: AbductionArgs) -> anyhow::Result<()> {
     let mut input = winit_input_helper::WinitInputHelper::new();
     let window = {
         let size = winit::dpi::LogicalSize::new(
-            160u16 * args.size_multiplier.max(1) as u16,
-            144u16 * args.size_multiplier.max(1) as u16,
+            width as u16 * args.size_multiplier.max(1) as u16,
+            height as u16 * args.size_multiplier.max(1) as u16,
         );
         winit::window::WindowBuilder::new()
             .with_title(format!(
@@ -193,11 +809,19 @@ pub fn run(args: AbductionArgs) -> anyhow::Result<()> {
         let window_size = window.inner_size();
         let surface_texture =
             pixels::SurfaceTexture::new(window_size.width, window_size.height, &window);
-        pixels::Pixels::new(160, 144, surface_texture).unwrap()
+        pixels::PixelsBuilder::new(width as u32, height as u32, surface_texture)
+            .enable_vsync(true)
+            .build()
+            .unwrap()
     };
 
     // run window
-    let color_array = args.palette.to_color_array();
+    let colors = args.palette.to_rgba_array();
+    let mut frame = FrameBuffer::new();
+    frame.set_dmg_look(args.dmg_look);
+    frame.set_frame_blend(args.frame_blend);
+    let mut grid_overlay = false;
+    let mut toasts = toast::Toasts::new();
     let mut last_redraw = std::time::Instant::now();
     event_loop.run(move |event, _, control_flow| {
         if res.is_finished() {
@@ -206,42 +830,59 @@ pub fn run(args: AbductionArgs) -> anyhow::Result<()> {
 
         match event {
             winit::event::Event::RedrawRequested(_) => {
-                {
-                    let lock = shared.0.lock();
-                    let buffer = lock.ppu().screen();
+                last_redraw = std::time::Instant::now();
+                let present = if frame_reader.update() {
+                    let presented = frame_reader.front();
                     let pixels_frame = pixels.get_frame_mut();
 
-                    for (i, pixel) in pixels_frame.chunks_exact_mut(4).enumerate() {
-                        let (y, x) = crate::util::div_rem(i, 160);
-                        let v = buffer.get_pixel(x, y).unwrap();
-                        // let c = match v {
-                        //     3 => [0x92, 0x5E, 0xC2, 0xFF],
-                        //     2 => [0xCF, 0x5B, 0xA6, 0xFF],
-                        //     1 => [0xFF, 0x71, 0x8F, 0xFF],
-                        //     0 => [0xFF, 0x99, 0x6F, 0xFF],
-                        //     _ => unreachable!(),
-                        // };
-
-                        // let c = match v {
-                        //     3 => [0x1B, 0x03, 0x26, 0xFF],
-                        //     2 => [0x7A, 0x1C, 0x4B, 0xFF],
-                        //     1 => [0xBA, 0x50, 0x44, 0xFF],
-                        //     0 => [0xDC, 0xBC, 0xA1, 0xFF],
-                        //     _ => unreachable!(),
-                        // };
-
-                        let color = color_array[3 - v as usize];
-                        let rgba = [color.r, color.g, color.b, 0xFF];
-
-                        pixel.copy_from_slice(&rgba);
+                    if sgb_enabled {
+                        // the border and palettes live in the gameboy, so only this needs the lock
+                        if let Some(sgb) = shared.0.lock().sgb() {
+                            sgb.draw(&presented.screen, pixels_frame);
+                        }
+                    } else {
+                        frame.update(&presented.screen, presented.number, &colors);
+                        frame
+                            .copy_to(pixels_frame, FRAME_STRIDE)
+                            .expect("pixels frame rows are a screen row long");
+
+                        if grid_overlay {
+                            draw_grid_overlay(pixels_frame, presented.scroll.0, presented.scroll.1);
+                        }
                     }
 
-                    last_redraw = std::time::Instant::now();
-                }
+                    toasts.draw(pixels_frame, width);
+                    true
+                } else {
+                    // nothing new to show. when syncing to the display the old frame is presented again anyway,
+                    // since presenting is what lets the next one be emulated
+                    sync_to_display
+                };
 
-                if pixels.render().is_err() {
+                if present && pixels.render().is_err() {
                     shared.1.store(true, std::sync::atomic::Ordering::SeqCst);
                     *control_flow = winit::event_loop::ControlFlow::Exit;
+                } else if present && sync_to_display {
+                    // the frame was presented, let the next one be emulated
+                    let _ = present_tx.try_send(());
+                }
+            }
+            winit::event::Event::LoopDestroyed => {
+                // every exit path ends up here: make sure nothing is lost. the emulation thread is given some time
+                // to stop so the ram isn't saved mid-step, but a stuck thread can't stall the exit
+                shared.1.store(true, std::sync::atomic::Ordering::SeqCst);
+                let deadline = std::time::Instant::now() + std::time::Duration::from_secs(1);
+                while !res.is_finished() && std::time::Instant::now() < deadline {
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                }
+
+                match shared.0.try_lock_for(std::time::Duration::from_secs(1)) {
+                    Some(lock) => {
+                        if let Err(e) = lock.save_ram(&save_path) {
+                            eprintln!("failed to save external ram: {}", e);
+                        }
+                    }
+                    None => eprintln!("failed to save external ram: emulation thread didn't stop"),
                 }
             }
             _ => {
@@ -256,6 +897,52 @@ pub fn run(args: AbductionArgs) -> anyhow::Result<()> {
                         return;
                     }
 
+                    // Reset
+                    if input.key_pressed(winit::event::VirtualKeyCode::F1) {
+                        shared.0.lock().reset();
+                        toasts.show_toast("Reset");
+                    }
+
+                    // Toggle the DMG look
+                    if input.key_pressed(winit::event::VirtualKeyCode::F2) {
+                        frame.set_dmg_look(!frame.dmg_look());
+                        toasts.show_toast(if frame.dmg_look() {
+                            "DMG look on"
+                        } else {
+                            "DMG look off"
+                        });
+                    }
+
+                    // Toggle the tile grid overlay
+                    if input.key_pressed(winit::event::VirtualKeyCode::F3) {
+                        grid_overlay = !grid_overlay;
+                        toasts.show_toast(if grid_overlay {
+                            "Tile grid on"
+                        } else {
+                            "Tile grid off"
+                        });
+                    }
+
+                    // Mute or unmute each sound channel
+                    const CHANNEL_KEYS: [winit::event::VirtualKeyCode; 4] = [
+                        winit::event::VirtualKeyCode::F5,
+                        winit::event::VirtualKeyCode::F6,
+                        winit::event::VirtualKeyCode::F7,
+                        winit::event::VirtualKeyCode::F8,
+                    ];
+                    for (channel, key) in SoundChannel::ALL.into_iter().zip(CHANNEL_KEYS) {
+                        if input.key_pressed(key) {
+                            let mut lock = shared.0.lock();
+                            let on = !lock.apu().channel_enabled(channel);
+                            lock.apu_mut().set_channel_enabled(channel, on);
+                            toasts.show_toast(format!(
+                                "Channel {} {}",
+                                channel as u8,
+                                if on { "on" } else { "muted" }
+                            ));
+                        }
+                    }
+
                     // Resize the window
                     if let Some(size) = input.window_resized() {
                         pixels
@@ -312,6 +999,9 @@ pub fn run(args: AbductionArgs) -> anyhow::Result<()> {
                             }
                         }
                     }
+                } else if sync_to_display {
+                    // presenting with vsync is what paces the loop
+                    *control_flow = winit::event_loop::ControlFlow::Poll;
                 } else {
                     *control_flow = winit::event_loop::ControlFlow::WaitUntil(
                         std::time::Instant::now()
@@ -322,7 +1012,7 @@ pub fn run(args: AbductionArgs) -> anyhow::Result<()> {
             }
         }
 
-        if last_redraw.elapsed() > std::time::Duration::from_millis(15) {
+        if sync_to_display || last_redraw.elapsed() > std::time::Duration::from_millis(15) {
             window.request_redraw();
         }
     });
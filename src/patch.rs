@@ -0,0 +1,318 @@
+/// Applies an IPS or BPS patch to the given rom, returning the patched rom. The format is detected from the
+/// patch header.
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        anyhow::bail!("Unknown patch format, only IPS and BPS are supported")
+    }
+}
+
+/// A cursor over the bytes of a patch.
+struct PatchReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(data: &'a [u8], position: usize) -> Self {
+        Self { data, position }
+    }
+
+    fn bytes(&mut self, count: usize) -> anyhow::Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + count)
+            .ok_or_else(|| anyhow::anyhow!("Patch ended unexpectedly"))?;
+
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> anyhow::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// Reads a big endian number of `count` bytes.
+    fn number(&mut self, count: usize) -> anyhow::Result<usize> {
+        Ok(self
+            .bytes(count)?
+            .iter()
+            .fold(0, |acc, byte| (acc << 8) | *byte as usize))
+    }
+
+    /// Reads a BPS variable length number. Numbers that don't fit in a usize are an error.
+    fn varint(&mut self) -> anyhow::Result<usize> {
+        let invalid = || anyhow::anyhow!("Invalid number in patch");
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.byte()?;
+            let bits = ((byte & 0x7F) as usize)
+                .checked_mul(shift)
+                .ok_or_else(invalid)?;
+            value = value.checked_add(bits).ok_or_else(invalid)?;
+
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+
+            // a multiplication instead of a shift, so bits shifted out are caught too
+            shift = shift.checked_mul(0x80).ok_or_else(invalid)?;
+            value = value.checked_add(shift).ok_or_else(invalid)?;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut output = rom.to_vec();
+    let mut reader = PatchReader::new(patch, 5);
+
+    loop {
+        if reader.bytes(3)? == b"EOF" {
+            // some patches have the size to truncate the rom to after the EOF marker
+            if let Ok(size) = reader.number(3) {
+                output.truncate(size);
+            }
+
+            return Ok(output);
+        }
+
+        reader.position -= 3;
+        let offset = reader.number(3)?;
+        let size = reader.number(2)?;
+
+        // a size of 0 means the record is RLE encoded
+        let (size, data) = if size == 0 {
+            let size = reader.number(2)?;
+            (size, vec![reader.byte()?; size])
+        } else {
+            (size, reader.bytes(size)?.to_vec())
+        };
+
+        if output.len() < offset + size {
+            output.resize(offset + size, 0);
+        }
+
+        output[offset..offset + size].copy_from_slice(&data);
+    }
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if patch.len() < 16 {
+        anyhow::bail!("Patch too small to be a BPS patch");
+    }
+
+    let footer = &patch[patch.len() - 12..];
+    let checksum = |index: usize| u32::from_le_bytes(footer[index..index + 4].try_into().unwrap());
+    if crc32(&patch[..patch.len() - 4]) != checksum(8) {
+        anyhow::bail!("BPS patch checksum mismatch, the patch is corrupted");
+    }
+
+    if crc32(rom) != checksum(0) {
+        anyhow::bail!("BPS patch was made for a different rom");
+    }
+
+    let mut reader = PatchReader::new(&patch[..patch.len() - 12], 4);
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+
+    if source_size != rom.len() {
+        anyhow::bail!("BPS patch was made for a rom of a different size");
+    }
+
+    let mut output = vec![0u8; target_size];
+    let mut output_offset = 0usize;
+    let mut source_relative = 0usize;
+    let mut target_relative = 0usize;
+
+    // reads a signed relative offset and applies it to `base`
+    let relative = |reader: &mut PatchReader, base: usize| -> anyhow::Result<usize> {
+        let data = reader.varint()?;
+        let offset = data >> 1;
+        let result = if data & 1 != 0 {
+            base.checked_sub(offset)
+        } else {
+            base.checked_add(offset)
+        };
+
+        result.ok_or_else(|| anyhow::anyhow!("Invalid offset in patch"))
+    };
+
+    let out_of_bounds = || anyhow::anyhow!("BPS patch accesses data out of bounds");
+    // the end of a range, which malformed patches can push past usize::MAX
+    let end = |start: usize, length: usize| start.checked_add(length).ok_or_else(out_of_bounds);
+    while reader.position < reader.data.len() {
+        let data = reader.varint()?;
+        let length = (data >> 2) + 1;
+        let target = output
+            .get_mut(output_offset..end(output_offset, length)?)
+            .ok_or_else(out_of_bounds)?;
+
+        match data & 0x03 {
+            // source read
+            0 => {
+                let source = rom
+                    .get(output_offset..end(output_offset, length)?)
+                    .ok_or_else(out_of_bounds)?;
+                target.copy_from_slice(source);
+            }
+            // target read
+            1 => target.copy_from_slice(reader.bytes(length)?),
+            // source copy
+            2 => {
+                source_relative = relative(&mut reader, source_relative)?;
+                let source_end = end(source_relative, length)?;
+                let source = rom
+                    .get(source_relative..source_end)
+                    .ok_or_else(out_of_bounds)?;
+                target.copy_from_slice(source);
+                source_relative = source_end;
+            }
+            // target copy, which can overlap with the bytes being written so it's done byte by byte
+            _ => {
+                target_relative = relative(&mut reader, target_relative)?;
+                let target_end = end(target_relative, length)?;
+                if target_end > output.len() {
+                    return Err(out_of_bounds());
+                }
+
+                for i in 0..length {
+                    output[output_offset + i] = output[target_relative + i];
+                }
+                target_relative = target_end;
+            }
+        }
+
+        output_offset = end(output_offset, length)?;
+    }
+
+    if crc32(&output) != checksum(4) {
+        anyhow::bail!("Patched rom checksum mismatch");
+    }
+
+    Ok(output)
+}
+
+/// CRC-32 (IEEE) of the given bytes, as used by BPS patches.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes a BPS variable length number.
+    fn varint(mut value: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let bits = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(0x80 | bits);
+                return bytes;
+            }
+
+            bytes.push(bits);
+            value -= 1;
+        }
+    }
+
+    /// Builds a BPS patch from its commands, with the checksums of `source` and `target` and of the patch itself.
+    fn bps(source: &[u8], target: &[u8], commands: &[u8]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+        patch.extend(varint(0));
+        patch.extend(commands);
+        patch.extend(crc32(source).to_le_bytes());
+        patch.extend(crc32(target).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn ips_records() {
+        let rom = [0u8; 8];
+        let mut patch = b"PATCH".to_vec();
+        // plain record: 2 bytes at 0x000001
+        patch.extend([0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]);
+        // rle record: 3 times 0xCC at 0x000004
+        patch.extend([0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x03, 0xCC]);
+        patch.extend(b"EOF");
+
+        let output = apply_patch(&rom, &patch).unwrap();
+        assert_eq!(output, [0x00, 0xAA, 0xBB, 0x00, 0xCC, 0xCC, 0xCC, 0x00]);
+
+        // the size to truncate to can follow the EOF marker
+        patch.extend([0x00, 0x00, 0x05]);
+        let output = apply_patch(&rom, &patch).unwrap();
+        assert_eq!(output, [0x00, 0xAA, 0xBB, 0x00, 0xCC]);
+    }
+
+    #[test]
+    fn bps_commands() {
+        let source = b"ABCDEFGH";
+        let target = b"ABxyzFGxyz";
+        let command = |action: usize, length: usize| varint(((length - 1) << 2) | action);
+
+        let mut commands = Vec::new();
+        // source read of "AB"
+        commands.extend(command(0, 2));
+        // target read of "xyz"
+        commands.extend(command(1, 3));
+        commands.extend(b"xyz");
+        // source copy of "FG", 5 bytes forward
+        commands.extend(command(2, 2));
+        commands.extend(varint(5 << 1));
+        // target copy of "xyz", 2 bytes forward
+        commands.extend(command(3, 3));
+        commands.extend(varint(2 << 1));
+
+        let patch = bps(source, target, &commands);
+        assert_eq!(apply_patch(source, &patch).unwrap(), target);
+
+        // a different rom, a corrupted patch and a wrong result are all rejected
+        assert!(apply_patch(b"ABCDEFGX", &patch).is_err());
+
+        let mut corrupted = patch.clone();
+        corrupted[8] ^= 0xFF;
+        assert!(apply_patch(source, &corrupted).is_err());
+
+        let wrong_target = bps(source, b"ABxyzFGxyZ", &commands);
+        let error = apply_patch(source, &wrong_target).unwrap_err();
+        assert!(error.to_string().contains("Patched rom checksum mismatch"));
+    }
+
+    #[test]
+    fn oversized_numbers_are_rejected() {
+        let mut data = vec![0x7F; 16];
+        data.push(0x80);
+        let mut reader = PatchReader::new(&data, 0);
+        assert!(reader.varint().is_err());
+
+        // a source copy way past the end of the rom
+        let source = b"ABCD";
+        let mut commands = varint((1 << 2) | 2);
+        commands.extend(varint((usize::MAX >> 1) << 1));
+        let patch = bps(source, b"AB", &commands);
+        assert!(apply_patch(source, &patch).is_err());
+    }
+}
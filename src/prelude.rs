@@ -0,0 +1,18 @@
+//! Commonly needed types for embedding the emulator: `use abduction::prelude::*;` is enough to load a rom, run
+//! frames, feed input and read the screen back.
+//!
+//! Everything re-exported here is considered stable, and changes to it are treated as breaking. Items only
+//! reachable through the other modules (the CPU internals, memory components, debugging helpers and so on) may
+//! change at any time.
+
+pub use crate::gameboy::{
+    capabilities,
+    frame::{FrameBuffer, DMG_LCD_COLORS, FRAME_STRIDE},
+    memory::registers::Interrupt,
+    ppu::{ScreenBuffer, FRAME_CYCLES, SCREEN_HEIGHT, SCREEN_WIDTH},
+    rom::{Licensee, RomCgbStatus, RomHeader, RomMBCType, RomSgbStatus},
+    sgb::{SGB_SCREEN_HEIGHT, SGB_SCREEN_WIDTH},
+    Capabilities, Gameboy, JoypadButton, StepInfo, CLOCK_FREQUENCY,
+};
+pub use crate::patch::apply_patch;
+pub use crate::ScreenPalette;
@@ -2,6 +2,7 @@ use super::tui_helper::*;
 use crate::gameboy::{
     cpu::{self, MasterInterrupt},
     memory::registers as memreg,
+    ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
     Gameboy,
 };
 use atomic::Atomic;
@@ -15,7 +16,7 @@ use tui::{
     backend::CrosstermBackend,
     layout::{Alignment, Constraint, Direction, Layout},
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, List, ListState, Row, Table},
+    widgets::{Block, Borders, List, ListState, Paragraph, Row, Table, Wrap},
 };
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -30,16 +31,22 @@ pub struct DebuggerShared {
     pub gameboy: Mutex<Gameboy>,
     pub state: Atomic<DebuggerEmulationState>,
     pub exit: AtomicBool,
+    pub palette: crate::ScreenPalette,
+    /// Path of the boot rom in use, to reload it from.
+    pub boot_path: String,
+    /// A message to be shown to the user, such as why emulation was paused.
+    pub notice: Mutex<Option<String>>,
 }
 
 struct SummaryTabInner {
-    address_op_cache: Box<[Option<cpu::operation::Operation>; 0xFFFF]>,
+    /// Disassembly of the instructions PC has been at, by address.
+    address_op_cache: Box<[Option<String>; 0x10000]>,
 }
 
 impl SummaryTabInner {
     pub fn new() -> Self {
         Self {
-            address_op_cache: Box::new([None; 0xFFFF]),
+            address_op_cache: crate::util::boxed_default_array(),
         }
     }
 
@@ -265,26 +272,16 @@ impl SummaryTabInner {
 
             Vec::from_iter(
                 ((pc.wrapping_sub(middle))..(pc.wrapping_add(area.height - middle)))
-                    .into_iter()
                     .map(|i| (i, gameboy_lock.memory().read(i)))
                     .map(|(i, value)| {
-                        let op = match i.cmp(&pc) {
-                            std::cmp::Ordering::Less | std::cmp::Ordering::Greater => {
-                                // let op = crate::gameboy::cpu::operation::Operation::from(value);
-                                // Some(op)
-                                self.address_op_cache[i as usize]
-                            }
-                            std::cmp::Ordering::Equal => {
-                                let op = crate::gameboy::cpu::operation::Operation::from(value);
-                                self.address_op_cache[i as usize] = Some(op);
-
-                                Some(op)
-                            }
-                        };
+                        if i == pc {
+                            let (op, _) = cpu::disassembler::disassemble(gameboy_lock.memory(), pc);
+                            self.address_op_cache[i as usize] = Some(op);
+                        }
 
-                        if let Some(op) = op {
+                        if let Some(op) = &self.address_op_cache[i as usize] {
                             tui::widgets::ListItem::new(format!(
-                                "({:#06X}): {:#04X} | {:?}",
+                                "({:#06X}): {:#04X} | {}",
                                 i, value, op
                             ))
                             .style(Style::default().fg(Color::LightGreen))
@@ -312,9 +309,94 @@ impl SummaryTabInner {
     }
 }
 
+/// Something that can be changed through the state editor.
+enum EditTarget {
+    Byte(cpu::ByteRegister),
+    Word(cpu::WordRegister),
+    Flag(cpu::CpuFlag),
+    Memory(u16),
+}
+
+impl EditTarget {
+    /// Parses a register (e.g. `a`, `hl`), a flag (`fz`, `fn`, `fh`, `fc`) or a memory address (`0xC000` or
+    /// `$C000`).
+    fn parse(target: &str) -> anyhow::Result<Self> {
+        use cpu::{ByteRegister, CpuFlag, WordRegister};
+
+        if let Some(address) = target
+            .strip_prefix("0x")
+            .or_else(|| target.strip_prefix('$'))
+        {
+            return Ok(Self::Memory(u16::from_str_radix(address, 16)?));
+        }
+
+        Ok(match target {
+            "a" => Self::Byte(ByteRegister::A),
+            "f" => Self::Byte(ByteRegister::F),
+            "b" => Self::Byte(ByteRegister::B),
+            "c" => Self::Byte(ByteRegister::C),
+            "d" => Self::Byte(ByteRegister::D),
+            "e" => Self::Byte(ByteRegister::E),
+            "h" => Self::Byte(ByteRegister::H),
+            "l" => Self::Byte(ByteRegister::L),
+            "af" => Self::Word(WordRegister::AF),
+            "bc" => Self::Word(WordRegister::BC),
+            "de" => Self::Word(WordRegister::DE),
+            "hl" => Self::Word(WordRegister::HL),
+            "sp" => Self::Word(WordRegister::SP),
+            "pc" => Self::Word(WordRegister::PC),
+            "fz" => Self::Flag(CpuFlag::Zero),
+            "fn" => Self::Flag(CpuFlag::Negative),
+            "fh" => Self::Flag(CpuFlag::Half),
+            "fc" => Self::Flag(CpuFlag::Carry),
+            _ => anyhow::bail!("Unknown register, flag or address '{}'", target),
+        })
+    }
+
+    /// Writes a hex value to the target, returning a description of the change.
+    fn apply(&self, gameboy: &mut Gameboy, value: &str) -> anyhow::Result<String> {
+        let value = value
+            .strip_prefix("0x")
+            .or_else(|| value.strip_prefix('$'))
+            .unwrap_or(value);
+
+        let registers = gameboy.cpu_mut().registers_mut();
+        Ok(match *self {
+            Self::Byte(register) => {
+                let value = u8::from_str_radix(value, 16)?;
+                registers.set_reg_8(register, value);
+                format!("{} = {:#04X}", register, value)
+            }
+            Self::Word(register) => {
+                let value = u16::from_str_radix(value, 16)?;
+                registers.set_reg_16(register, value);
+                format!("{:?} = {:#06X}", register, value)
+            }
+            Self::Flag(flag) => {
+                let value = match value {
+                    "0" => false,
+                    "1" => true,
+                    _ => anyhow::bail!("Flags can only be set to 0 or 1"),
+                };
+                registers.set_flag(flag, value);
+                format!("{:?} flag = {}", flag, value as u8)
+            }
+            Self::Memory(address) => {
+                let value = u8::from_str_radix(value, 16)?;
+                gameboy.memory_mut().write(address, value);
+                format!("({:#06X}) = {:#04X}", address, value)
+            }
+        })
+    }
+}
+
 pub struct SummaryTab {
     shared: Arc<DebuggerShared>,
     inner: SummaryTabInner,
+    /// Count typed before a step command, to repeat it.
+    count: Option<u32>,
+    /// The command being typed in the state editor, if it's open.
+    edit: Option<String>,
 }
 
 impl SummaryTab {
@@ -322,8 +404,50 @@ impl SummaryTab {
         Self {
             shared,
             inner: SummaryTabInner::new(),
+            count: None,
+            edit: None,
         }
     }
+
+    /// Handles a key while the state editor is open. Commands look like `hl=c000`, `fz=1` or `0xff40=91`, with
+    /// values in hex, and are applied with enter.
+    fn edit_input(&mut self, code: crossterm::event::KeyCode) {
+        let Some(command) = &mut self.edit else {
+            return;
+        };
+
+        match code {
+            crossterm::event::KeyCode::Char(c) => command.push(c.to_ascii_lowercase()),
+            crossterm::event::KeyCode::Backspace => {
+                command.pop();
+            }
+            // esc quits the debugger, so an empty command closes the editor instead
+            crossterm::event::KeyCode::Enter if command.is_empty() => {
+                self.edit = None;
+                *self.shared.notice.lock() = None;
+                return;
+            }
+            crossterm::event::KeyCode::Enter => {
+                let command = self.edit.take().unwrap_or_default();
+                let result = command
+                    .split_once('=')
+                    .ok_or_else(|| anyhow::anyhow!("Expected <target>=<value>"))
+                    .and_then(|(target, value)| {
+                        EditTarget::parse(target.trim())?
+                            .apply(&mut self.shared.gameboy.lock(), value.trim())
+                    });
+
+                *self.shared.notice.lock() = Some(match result {
+                    Ok(change) => format!("Set {}", change),
+                    Err(e) => format!("Edit failed: {}", e),
+                });
+                return;
+            }
+            _ => (),
+        }
+
+        *self.shared.notice.lock() = Some(format!("Edit: {}_", command));
+    }
 }
 
 impl<'a> Tab<'a> for SummaryTab {
@@ -376,25 +500,118 @@ impl<'a> Tab<'a> for SummaryTab {
             .title("Block 3")
             .title_alignment(Alignment::Center)
             .borders(Borders::ALL);
-        f.render_widget(block, chunks[2]);
+        let overflow_lines = self.shared.gameboy.lock().ppu().sprite_overflow_lines();
+        let notice = self.shared.notice.lock().clone().unwrap_or_default();
+        let paragraph = Paragraph::new(format!(
+            "Sprite overflow lines: {}\n{}",
+            overflow_lines, notice
+        ))
+        .style(Style::default().fg(Color::LightRed))
+        .wrap(Wrap { trim: true })
+        .block(block);
+        f.render_widget(paragraph, chunks[2]);
 
         Ok(AppAction::None)
     }
 
     fn input(&mut self, event: crossterm::event::Event) -> anyhow::Result<AppAction> {
         if let crossterm::event::Event::Key(key) = event {
+            if self.edit.is_some() {
+                self.edit_input(key.code);
+                return Ok(AppAction::None);
+            }
+
+            // digits build up a count for the next step command, e.g. "100s" steps 100 instructions
+            let count = match key.code {
+                crossterm::event::KeyCode::Char(c @ '0'..='9') => {
+                    let digit = c.to_digit(10).unwrap();
+                    let count = self
+                        .count
+                        .unwrap_or(0)
+                        .saturating_mul(10)
+                        .saturating_add(digit);
+                    self.count = Some(count);
+                    *self.shared.notice.lock() = Some(format!("Count: {}", count));
+                    return Ok(AppAction::None);
+                }
+                _ => self.count.take().unwrap_or(1),
+            };
+
             match key.code {
                 crossterm::event::KeyCode::Char(c) => match c {
                     'p' => self.shared.state.store(
                         DebuggerEmulationState::Paused,
                         std::sync::atomic::Ordering::SeqCst,
                     ),
-                    'r' => self.shared.state.store(
-                        DebuggerEmulationState::Stepping,
-                        std::sync::atomic::Ordering::SeqCst,
-                    ),
+                    'r' => {
+                        *self.shared.notice.lock() = None;
+                        self.shared.state.store(
+                            DebuggerEmulationState::Stepping,
+                            std::sync::atomic::Ordering::SeqCst,
+                        )
+                    }
+                    'g' => {
+                        let mut lock = self.shared.gameboy.lock();
+                        let enabled = !lock.cpu().dbg_pc_guard();
+                        lock.cpu_mut().dbg_set_pc_guard(enabled);
+                        *self.shared.notice.lock() =
+                            Some(format!("PC guard {}", if enabled { "on" } else { "off" }));
+                    }
                     's' => {
-                        self.shared.gameboy.lock().step();
+                        let mut lock = self.shared.gameboy.lock();
+                        for _ in 0..count {
+                            lock.step();
+                        }
+
+                        self.shared.state.store(
+                            DebuggerEmulationState::Paused,
+                            std::sync::atomic::Ordering::SeqCst,
+                        );
+                    }
+                    'n' => {
+                        let mut lock = self.shared.gameboy.lock();
+                        for _ in 0..count {
+                            lock.run_frame();
+                        }
+
+                        self.shared.state.store(
+                            DebuggerEmulationState::Paused,
+                            std::sync::atomic::Ordering::SeqCst,
+                        );
+                    }
+                    'e' => {
+                        // the state can only be edited while paused, so the emulation thread can't race the edit
+                        let state = self.shared.state.load(std::sync::atomic::Ordering::SeqCst);
+                        if state == DebuggerEmulationState::Paused {
+                            self.edit = Some(String::new());
+                            *self.shared.notice.lock() =
+                                Some("Edit: (e.g. a=3f, hl=c000, fz=1, 0xff40=91, enter to apply or cancel)".to_string());
+                        } else {
+                            *self.shared.notice.lock() =
+                                Some("Pause emulation (p) before editing".to_string());
+                        }
+                    }
+                    'b' => {
+                        let mut lock = self.shared.gameboy.lock();
+                        let enabled = !lock.memory().boot_mode();
+                        lock.set_boot_mode(enabled);
+                        *self.shared.notice.lock() = Some(format!(
+                            "Boot mode {}",
+                            if lock.memory().boot_mode() {
+                                "on, restarted at 0x0000"
+                            } else {
+                                "off"
+                            }
+                        ));
+                    }
+                    'l' => {
+                        // reload the boot rom from disk, e.g. after editing it
+                        let result = crate::util::read_bytes(&self.shared.boot_path)
+                            .and_then(|boot| self.shared.gameboy.lock().set_boot(boot));
+                        *self.shared.notice.lock() = Some(match result {
+                            Ok(()) => format!("Reloaded boot rom from {}", self.shared.boot_path),
+                            Err(e) => format!("Failed to reload boot rom: {}", e),
+                        });
                     }
                     'v' => {
                         let lock = self.shared.gameboy.lock();
@@ -411,8 +628,68 @@ impl<'a> Tab<'a> for SummaryTab {
                         lock.ppu().dbg_save_master_tileset();
                         lock.ppu().dbg_save_current_buffer();
                     }
+                    'f' => {
+                        // dump the frame as it's displayed, palette included
+                        let lock = self.shared.gameboy.lock();
+                        let frame = lock.frame(&self.shared.palette.to_rgba_array());
+                        let img = image::RgbaImage::from_raw(
+                            SCREEN_WIDTH as u32,
+                            SCREEN_HEIGHT as u32,
+                            frame.rgba().to_vec(),
+                        )
+                        .expect("frame has the right size");
+
+                        img.save("frame.png")?;
+                    }
+                    'o' => {
+                        // all 40 objects as a sprite sheet, with their attributes alongside
+                        let lock = self.shared.gameboy.lock();
+                        let (sheet, labels) = lock.ppu().dbg_oam_sprite_sheet(
+                            lock.memory(),
+                            &self.shared.palette.to_rgba_array(),
+                        );
+
+                        sheet.save("oam.png")?;
+                        std::fs::write("oam.csv", labels)?;
+                    }
+                    'm' => {
+                        // first press arms the trace, second press (after a full frame) saves it
+                        let mut lock = self.shared.gameboy.lock();
+                        if let Some(csv) = lock.ppu().dbg_mode_trace_csv() {
+                            std::fs::write("ppu_modes.csv", csv)?;
+                        } else {
+                            lock.ppu_mut().dbg_record_mode_trace();
+                        }
+                    }
                     _ => (),
                 },
+                crossterm::event::KeyCode::F(key @ 1..=5) => {
+                    // F1 to F5 stop or resume servicing each interrupt, in priority order
+                    const INTERRUPTS: [memreg::Interrupt; 5] = [
+                        memreg::Interrupt::VBlank,
+                        memreg::Interrupt::STAT,
+                        memreg::Interrupt::Timer,
+                        memreg::Interrupt::Serial,
+                        memreg::Interrupt::Joypad,
+                    ];
+
+                    let interrupt = INTERRUPTS[key as usize - 1];
+                    let mut lock = self.shared.gameboy.lock();
+                    let masked = !lock.cpu().dbg_masked_interrupts().contains(interrupt);
+                    lock.cpu_mut().dbg_set_interrupt_masked(interrupt, masked);
+
+                    let masked: Vec<_> = lock
+                        .cpu()
+                        .dbg_masked_interrupts()
+                        .into_iter()
+                        .map(|interrupt| format!("{:?}", interrupt))
+                        .collect();
+                    *self.shared.notice.lock() = Some(if masked.is_empty() {
+                        "All interrupts are serviced".to_string()
+                    } else {
+                        format!("Not servicing: {}", masked.join(", "))
+                    });
+                }
                 crossterm::event::KeyCode::Up => return Ok(AppAction::FocusTabs),
                 _ => (),
             }
@@ -12,20 +12,34 @@ use std::{
 use tui::backend::CrosstermBackend;
 use tui_helper::*;
 
-use crate::gameboy::Gameboy;
+use crate::gameboy::{
+    frame::{FrameBuffer, FRAME_STRIDE},
+    ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+};
 
 pub fn run_with_debugger(args: crate::AbductionArgs) -> anyhow::Result<()> {
     // create shared state
-    let boot = crate::util::read_bytes(args.boot)?;
-    let rom = crate::util::read_bytes(args.rom)?;
-    let gameboy = Mutex::new(Gameboy::new(rom, boot)?);
+    let save_path = std::path::Path::new(args.rom()).with_extension("sav");
+    let mut gameboy = crate::load_gameboy(&args)?;
+    // failing here beats overwriting a save that couldn't be read when exiting
+    gameboy.load_ram(&save_path)?;
+
+    let boot_path = crate::boot_path(&args, gameboy.memory().rom_header()).to_string();
+    let gameboy = Mutex::new(gameboy);
 
     let shared = Arc::new(DebuggerShared {
         gameboy,
         state: Atomic::new(DebuggerEmulationState::Stepping),
         exit: AtomicBool::new(false),
+        palette: args.palette,
+        boot_path,
+        notice: Mutex::new(None),
     });
 
+    // kept alive for as long as the emulator runs
+    #[cfg(feature = "audio")]
+    let _audio = crate::start_audio(&shared.gameboy);
+
     // spawn thread for gameboy
     let shared_clone = shared.clone();
     let _ = std::thread::spawn(|| {
@@ -45,6 +59,15 @@ pub fn run_with_debugger(args: crate::AbductionArgs) -> anyhow::Result<()> {
                 let mut lock = shared.gameboy.lock();
                 for _ in 0..4 {
                     m_cycles += lock.step();
+
+                    if let Some(pc) = lock.cpu_mut().dbg_take_pc_guard_hit() {
+                        *shared.notice.lock() = Some(format!("PC in unusual region ({:#06X})", pc));
+                        shared.state.store(
+                            DebuggerEmulationState::Paused,
+                            std::sync::atomic::Ordering::SeqCst,
+                        );
+                        break;
+                    }
                 }
             }
 
@@ -99,8 +122,8 @@ pub fn run_with_debugger(args: crate::AbductionArgs) -> anyhow::Result<()> {
     let mut input = winit_input_helper::WinitInputHelper::new();
     let window = {
         let size = winit::dpi::LogicalSize::new(
-            160u16 * args.size_multiplier as u16,
-            144u16 * args.size_multiplier as u16,
+            SCREEN_WIDTH as u16 * args.size_multiplier as u16,
+            SCREEN_HEIGHT as u16 * args.size_multiplier as u16,
         );
         winit::window::WindowBuilder::new()
             .with_title("abduction")
@@ -115,10 +138,12 @@ pub fn run_with_debugger(args: crate::AbductionArgs) -> anyhow::Result<()> {
         let window_size = window.inner_size();
         let surface_texture =
             pixels::SurfaceTexture::new(window_size.width, window_size.height, &window);
-        pixels::Pixels::new(160, 144, surface_texture).unwrap()
+        pixels::Pixels::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, surface_texture).unwrap()
     };
 
     // run window
+    let colors = args.palette.to_rgba_array();
+    let mut frame = FrameBuffer::new();
     let mut last_redraw = std::time::Instant::now();
     event_loop.run(move |event, _, control_flow| {
         if shared.exit.load(std::sync::atomic::Ordering::SeqCst) {
@@ -129,30 +154,10 @@ pub fn run_with_debugger(args: crate::AbductionArgs) -> anyhow::Result<()> {
             winit::event::Event::RedrawRequested(_) => {
                 {
                     let lock = shared.gameboy.lock();
-                    let buffer = lock.ppu().screen();
-                    let pixels_frame = pixels.get_frame();
-
-                    for (i, pixel) in pixels_frame.chunks_exact_mut(4).enumerate() {
-                        let (y, x) = crate::util::div_rem(i, 160);
-                        let v = buffer.get_pixel(x as usize, y as usize).unwrap();
-                        // let c = match v {
-                        //     3 => [0x92, 0x5E, 0xC2, 0xFF],
-                        //     2 => [0xCF, 0x5B, 0xA6, 0xFF],
-                        //     1 => [0xFF, 0x71, 0x8F, 0xFF],
-                        //     0 => [0xFF, 0x99, 0x6F, 0xFF],
-                        //     _ => unreachable!(),
-                        // };
-
-                        let c = match v {
-                            3 => [0x1B, 0x03, 0x26, 0xFF],
-                            2 => [0x7A, 0x1C, 0x4B, 0xFF],
-                            1 => [0xBA, 0x50, 0x44, 0xFF],
-                            0 => [0xDC, 0xBC, 0xA1, 0xFF],
-                            _ => unreachable!(),
-                        };
-
-                        pixel.copy_from_slice(&c);
-                    }
+                    lock.update_frame(&mut frame, &colors);
+                    frame
+                        .copy_to(pixels.get_frame_mut(), FRAME_STRIDE)
+                        .expect("pixels frame rows are a screen row long");
 
                     last_redraw = std::time::Instant::now();
                 }
@@ -162,10 +167,20 @@ pub fn run_with_debugger(args: crate::AbductionArgs) -> anyhow::Result<()> {
                     *control_flow = winit::event_loop::ControlFlow::Exit;
                 }
             }
+            winit::event::Event::LoopDestroyed => {
+                // every exit path ends up here: make sure nothing is lost
+                shared.exit.store(true, std::sync::atomic::Ordering::SeqCst);
+                if let Err(e) = shared.gameboy.lock().save_ram(&save_path) {
+                    eprintln!("failed to save external ram: {}", e);
+                }
+            }
             _ => {
                 if input.update(&event) {
                     // Close events
-                    if input.key_pressed(winit::event::VirtualKeyCode::Escape) || input.quit() {
+                    if input.key_pressed(winit::event::VirtualKeyCode::Escape)
+                        || input.close_requested()
+                        || input.destroyed()
+                    {
                         shared.exit.store(true, std::sync::atomic::Ordering::SeqCst);
                         *control_flow = winit::event_loop::ControlFlow::Exit;
                         return;
@@ -173,7 +188,9 @@ pub fn run_with_debugger(args: crate::AbductionArgs) -> anyhow::Result<()> {
 
                     // Resize the window
                     if let Some(size) = input.window_resized() {
-                        pixels.resize_surface(size.width, size.height);
+                        pixels
+                            .resize_surface(size.width, size.height)
+                            .expect("resizing successful");
                     }
 
                     // Update input
@@ -0,0 +1,160 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// How long a toast stays on screen, including the fade out.
+const TOAST_DURATION: Duration = Duration::from_millis(2000);
+/// How long a toast takes to fade out at the end of its duration.
+const TOAST_FADE: Duration = Duration::from_millis(500);
+/// How many toasts can be on screen at once. Any others wait for a free spot.
+const MAX_VISIBLE_TOASTS: usize = 3;
+
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+/// Space around the text inside of a toast, in pixels.
+const TOAST_PADDING: usize = 2;
+const TOAST_HEIGHT: usize = GLYPH_HEIGHT + TOAST_PADDING * 2;
+
+const TEXT_COLOR: [u8; 3] = [0xFF, 0xFF, 0xFF];
+const BACKGROUND_COLOR: [u8; 3] = [0x00, 0x00, 0x00];
+const BACKGROUND_ALPHA: f32 = 0.75;
+
+/// Returns the rows of the 3x5 glyph for a character, most significant bit on the left. Letters are always drawn
+/// uppercase and unknown characters are drawn as '?'.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+/// Blends `color` over the RGBA pixel with the given opacity.
+fn blend(pixel: &mut [u8], color: [u8; 3], alpha: f32) {
+    for (channel, color) in pixel.iter_mut().zip(color) {
+        *channel = (*channel as f32 * (1.0 - alpha) + color as f32 * alpha) as u8;
+    }
+}
+
+struct Toast {
+    text: String,
+    /// When the toast got a spot on screen, or `None` if it's still waiting for one.
+    shown_at: Option<Instant>,
+}
+
+/// Short messages drawn over the screen for a couple of seconds, giving feedback on hotkeys and the like. Toasts
+/// are stacked from the bottom of the screen, and the ones that don't fit wait in a queue.
+pub struct Toasts {
+    queue: VecDeque<Toast>,
+}
+
+impl Toasts {
+    pub fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Queues a message to be shown.
+    pub fn show_toast(&mut self, text: impl Into<String>) {
+        self.queue.push_back(Toast {
+            text: text.into(),
+            shown_at: None,
+        });
+    }
+
+    /// Drops expired toasts and draws the visible ones over an RGBA frame `width` pixels wide.
+    pub fn draw(&mut self, frame: &mut [u8], width: usize) {
+        let now = Instant::now();
+        self.queue.retain(|toast| {
+            toast
+                .shown_at
+                .is_none_or(|shown_at| now - shown_at < TOAST_DURATION)
+        });
+
+        let height = frame.len() / 4 / width;
+        for (index, toast) in self.queue.iter_mut().take(MAX_VISIBLE_TOASTS).enumerate() {
+            let elapsed = now - *toast.shown_at.get_or_insert(now);
+            let remaining = TOAST_DURATION.saturating_sub(elapsed);
+            let opacity = (remaining.as_secs_f32() / TOAST_FADE.as_secs_f32()).min(1.0);
+
+            // the oldest toast is at the bottom, newer ones stack on top of it
+            let Some(top) = height.checked_sub((index + 1) * (TOAST_HEIGHT + 1)) else {
+                break;
+            };
+
+            let text_width = (toast.text.chars().count() * (GLYPH_WIDTH + 1)).saturating_sub(1);
+            let toast_width = (text_width + TOAST_PADDING * 2).min(width - 1);
+            for y in top..top + TOAST_HEIGHT {
+                for x in 1..1 + toast_width {
+                    let pixel = &mut frame[(y * width + x) * 4..][..3];
+                    blend(pixel, BACKGROUND_COLOR, BACKGROUND_ALPHA * opacity);
+                }
+            }
+
+            for (char_index, c) in toast.text.chars().enumerate() {
+                let left = 1 + TOAST_PADDING + char_index * (GLYPH_WIDTH + 1);
+                for (row, bits) in glyph(c).into_iter().enumerate() {
+                    for column in 0..GLYPH_WIDTH {
+                        let x = left + column;
+                        if bits & (0b100 >> column) == 0 || x >= width {
+                            continue;
+                        }
+
+                        let y = top + TOAST_PADDING + row;
+                        let pixel = &mut frame[(y * width + x) * 4..][..3];
+                        blend(pixel, TEXT_COLOR, opacity);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -20,6 +20,23 @@ where
     Box::<[T; N]>::try_from(b).ok().unwrap()
 }
 
+/// Creates a boxed array filled with `T::default()`. Elements are written straight to the heap, so even huge
+/// arrays never need a temporary on the stack.
+pub fn boxed_default_array<T, const N: usize>() -> Box<[T; N]>
+where
+    T: Default,
+{
+    let b: Box<[T]> = std::iter::repeat_with(T::default).take(N).collect();
+
+    Box::<[T; N]>::try_from(b).ok().unwrap()
+}
+
+/// Converts a BGR555 color, as used by the SNES (for the SGB) and the CGB, into RGB888.
+pub fn bgr555_to_rgb(color: u16) -> [u8; 3] {
+    let expand = |c: u16| ((c & 0x1F) * 255 / 31) as u8;
+    [expand(color), expand(color >> 5), expand(color >> 10)]
+}
+
 pub fn read_bytes<P>(path: P) -> anyhow::Result<Vec<u8>>
 where
     P: AsRef<std::path::Path>,
@@ -39,3 +56,77 @@ where
 {
     (a / b, a % b)
 }
+
+/// Creates a triple buffer, which hands values from a writer thread to a reader thread without either of them
+/// waiting on the other for longer than a pointer swap. Both sides start with clones of `initial`.
+pub fn triple_buffer<T>(initial: T) -> (TripleBufferWriter<T>, TripleBufferReader<T>)
+where
+    T: Clone,
+{
+    let shared = std::sync::Arc::new(parking_lot::Mutex::new(MiddleBuffer {
+        value: Box::new(initial.clone()),
+        fresh: false,
+    }));
+
+    (
+        TripleBufferWriter {
+            back: Box::new(initial.clone()),
+            shared: shared.clone(),
+        },
+        TripleBufferReader {
+            front: Box::new(initial),
+            shared,
+        },
+    )
+}
+
+/// The buffer in between the writer and the reader, holding the last published value until it's read.
+struct MiddleBuffer<T> {
+    value: Box<T>,
+    fresh: bool,
+}
+
+/// Writing side of a triple buffer: values are written into the back buffer and then published.
+pub struct TripleBufferWriter<T> {
+    back: Box<T>,
+    shared: std::sync::Arc<parking_lot::Mutex<MiddleBuffer<T>>>,
+}
+
+impl<T> TripleBufferWriter<T> {
+    pub fn back_mut(&mut self) -> &mut T {
+        &mut self.back
+    }
+
+    /// Makes the back buffer the newest value available to the reader. A value that was published but never read
+    /// is dropped in favor of this one.
+    pub fn publish(&mut self) {
+        let mut middle = self.shared.lock();
+        std::mem::swap(&mut self.back, &mut middle.value);
+        middle.fresh = true;
+    }
+}
+
+/// Reading side of a triple buffer.
+pub struct TripleBufferReader<T> {
+    front: Box<T>,
+    shared: std::sync::Arc<parking_lot::Mutex<MiddleBuffer<T>>>,
+}
+
+impl<T> TripleBufferReader<T> {
+    /// Takes the newest published value into the front buffer, if there's one that wasn't read yet. Returns
+    /// whether the front buffer changed.
+    pub fn update(&mut self) -> bool {
+        let mut middle = self.shared.lock();
+        if !middle.fresh {
+            return false;
+        }
+
+        std::mem::swap(&mut self.front, &mut middle.value);
+        middle.fresh = false;
+        true
+    }
+
+    pub fn front(&self) -> &T {
+        &self.front
+    }
+}